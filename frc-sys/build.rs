@@ -103,6 +103,18 @@ fn main() {
             .allowlist_var("ctre::.*"),
     );
 
+    // Generate rev bindings
+    generate_bindings(
+        "rev",
+        Builder::default()
+            .allowlist_type("rev::.*")
+            .allowlist_function("rev::.*")
+            .allowlist_var("rev::.*")
+            // SparkMax classes implement wpilib interfaces, which are bound in the wpilib module
+            .opaque_type("frc::.*")
+            .opaque_type("units::.*"),
+    );
+
     // Generate HAL bindings
     generate_bindings(
         "hal",
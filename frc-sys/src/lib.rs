@@ -7,6 +7,7 @@
 
 pub mod ctre;
 pub mod hal;
+pub mod rev;
 pub mod wpilib;
 
 use std::fmt::Display;
@@ -16,3 +17,9 @@ impl Display for ctre::ctre_phoenix_ErrorCode {
         write!(f, "{:?}", self)
     }
 }
+
+impl Display for rev::rev_CANError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
@@ -0,0 +1 @@
+#include <rev/CANSparkMax.h>
\ No newline at end of file
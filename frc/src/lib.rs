@@ -1,8 +1,9 @@
-//! Rust bindings to frc libraries (wpilib, hal, ctre)
+//! Rust bindings to frc libraries (wpilib, hal, ctre, rev)
 
 #![allow(rustdoc::broken_intra_doc_links)]
 
 pub mod ctre;
+pub mod rev;
 pub mod wpilib;
 
 /// Rust bindings to HAL
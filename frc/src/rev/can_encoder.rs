@@ -0,0 +1,59 @@
+use frc_sys::rev::{rev_CANEncoder, rev_CANSparkMax};
+
+use super::EncoderType;
+use crate::rev as frc_rev;
+
+use anyhow::Result;
+
+/// Encoder plugged into a SparkMax, by default the NEO's built-in hall sensor
+///
+/// Positions are in rotations and velocities in RPM, each multiplied by their conversion factor.
+pub struct CANEncoder {
+    handle: rev_CANEncoder,
+}
+
+impl CANEncoder {
+    /// Counts per revolution of the NEO's hall sensor
+    pub const HALL_SENSOR_COUNTS_PER_REVOLUTION: i32 = 42;
+
+    /// # Safety
+    ///
+    /// `device` must outlive the encoder and not move while it exists
+    pub(crate) unsafe fn new(
+        device: *mut rev_CANSparkMax,
+        sensor_type: EncoderType,
+        counts_per_rev: i32,
+    ) -> Self {
+        CANEncoder {
+            handle: rev_CANEncoder::new(device, sensor_type, counts_per_rev),
+        }
+    }
+
+    pub fn get_position(&mut self) -> f64 {
+        unsafe { self.handle.GetPosition() }
+    }
+
+    pub fn get_velocity(&mut self) -> f64 {
+        unsafe { self.handle.GetVelocity() }
+    }
+
+    pub fn set_position(&mut self, position: f64) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetPosition(position) })
+    }
+
+    pub fn set_position_conversion_factor(&mut self, factor: f64) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetPositionConversionFactor(factor) })
+    }
+
+    pub fn set_velocity_conversion_factor(&mut self, factor: f64) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetVelocityConversionFactor(factor) })
+    }
+
+    pub fn get_position_conversion_factor(&mut self) -> f64 {
+        unsafe { self.handle.GetPositionConversionFactor() }
+    }
+
+    pub fn get_velocity_conversion_factor(&mut self) -> f64 {
+        unsafe { self.handle.GetVelocityConversionFactor() }
+    }
+}
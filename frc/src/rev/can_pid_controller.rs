@@ -0,0 +1,82 @@
+use frc_sys::rev::{rev_CANPIDController, rev_CANSparkMax};
+
+use super::{ArbFFUnits, ControlType};
+use crate::rev as frc_rev;
+
+use anyhow::Result;
+
+/// Closed loop running on a SparkMax
+pub struct CANPIDController {
+    handle: rev_CANPIDController,
+}
+
+impl CANPIDController {
+    /// # Safety
+    ///
+    /// `device` must outlive the controller and not move while it exists
+    pub(crate) unsafe fn new(device: *mut rev_CANSparkMax) -> Self {
+        CANPIDController {
+            handle: rev_CANPIDController::new(device),
+        }
+    }
+
+    pub fn set_reference(
+        &mut self,
+        value: f64,
+        ctrl: ControlType,
+        pid_slot: i32,        /* 0 */
+        arb_feedforward: f64, /* 0.0 */
+        arb_ff_units: ArbFFUnits,
+    ) -> Result<()> {
+        frc_rev::to_result(unsafe {
+            self.handle
+                .SetReference(value, ctrl, pid_slot, arb_feedforward, arb_ff_units)
+        })
+    }
+
+    pub fn set_p(&mut self, gain: f64, slot_id: i32 /* 0 */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetP(gain, slot_id) })
+    }
+
+    pub fn set_i(&mut self, gain: f64, slot_id: i32 /* 0 */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetI(gain, slot_id) })
+    }
+
+    pub fn set_d(&mut self, gain: f64, slot_id: i32 /* 0 */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetD(gain, slot_id) })
+    }
+
+    pub fn set_ff(&mut self, gain: f64, slot_id: i32 /* 0 */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetFF(gain, slot_id) })
+    }
+
+    pub fn set_i_zone(&mut self, i_zone: f64, slot_id: i32 /* 0 */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetIZone(i_zone, slot_id) })
+    }
+
+    /// Limits closed-loop output, as a fraction of full output from -1 to 1
+    pub fn set_output_range(
+        &mut self,
+        min: f64,
+        max: f64,
+        slot_id: i32, /* 0 */
+    ) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.SetOutputRange(min, max, slot_id) })
+    }
+
+    pub fn get_p(&mut self, slot_id: i32 /* 0 */) -> f64 {
+        unsafe { self.handle.GetP(slot_id) }
+    }
+
+    pub fn get_i(&mut self, slot_id: i32 /* 0 */) -> f64 {
+        unsafe { self.handle.GetI(slot_id) }
+    }
+
+    pub fn get_d(&mut self, slot_id: i32 /* 0 */) -> f64 {
+        unsafe { self.handle.GetD(slot_id) }
+    }
+
+    pub fn get_ff(&mut self, slot_id: i32 /* 0 */) -> f64 {
+        unsafe { self.handle.GetFF(slot_id) }
+    }
+}
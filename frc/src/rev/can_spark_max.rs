@@ -0,0 +1,187 @@
+use std::ffi::c_void;
+
+use frc_sys::rev::{self, rev_CANSparkMax};
+
+use super::{CANEncoder, CANPIDController, EncoderType, IdleMode, MotorType};
+use crate::rev as frc_rev;
+
+use anyhow::Result;
+use uom::si::electric_current::ampere;
+use uom::si::electric_potential::volt;
+use uom::si::f64::*;
+use uom::si::thermodynamic_temperature::degree_celsius;
+use uom::si::time::second;
+
+/// Boxed so the encoder and PID controller's references to it stay valid when the
+/// [`CANSparkMax`] moves
+struct SparkMaxHandle(Box<rev_CANSparkMax>);
+
+impl Drop for SparkMaxHandle {
+    fn drop(&mut self) {
+        unsafe {
+            rev::rev_CANSparkMax_CANSparkMax_destructor(&mut *self.0);
+        }
+    }
+}
+
+/// REV SparkMax motor controller, along with its built-in encoder and PID controller
+pub struct CANSparkMax {
+    // Declared before the handle so they're dropped before the SparkMax they refer to
+    encoder: CANEncoder,
+    pid_controller: CANPIDController,
+    handle: SparkMaxHandle,
+}
+
+impl CANSparkMax {
+    pub fn new(device_id: i32, motor_type: MotorType) -> Self {
+        let mut handle = Box::new(unsafe { rev_CANSparkMax::new(device_id, motor_type) });
+        let device = &mut *handle as *mut rev_CANSparkMax;
+        // The encoder's counts per revolution only matter for brushed motors; brushless ones
+        // always use the hall sensor
+        let (encoder, pid_controller) = unsafe {
+            (
+                CANEncoder::new(
+                    device,
+                    EncoderType::kHallSensor,
+                    CANEncoder::HALL_SENSOR_COUNTS_PER_REVOLUTION,
+                ),
+                CANPIDController::new(device),
+            )
+        };
+
+        CANSparkMax {
+            encoder,
+            pid_controller,
+            handle: SparkMaxHandle(handle),
+        }
+    }
+
+    fn get_handle_ptr(&mut self) -> *mut c_void {
+        &mut *self.handle.0 as *mut _ as *mut c_void
+    }
+
+    /// Duty cycle output from -1 to 1
+    pub fn set(&mut self, speed: f64) {
+        unsafe {
+            rev::rev_CANSparkMax_Set(self.get_handle_ptr(), speed);
+        }
+    }
+
+    pub fn get(&mut self) -> f64 {
+        unsafe { rev::rev_CANSparkMax_Get(self.get_handle_ptr()) }
+    }
+
+    /// Ignored while following another SparkMax; pass the inversion to [`CANSparkMax::follow`]
+    /// instead
+    pub fn set_inverted(&mut self, is_inverted: bool) {
+        unsafe {
+            rev::rev_CANSparkMax_SetInverted(self.get_handle_ptr(), is_inverted);
+        }
+    }
+
+    pub fn get_inverted(&mut self) -> bool {
+        unsafe { rev::rev_CANSparkMax_GetInverted(self.get_handle_ptr()) }
+    }
+
+    pub fn stop_motor(&mut self) {
+        unsafe {
+            rev::rev_CANSparkMax_StopMotor(self.get_handle_ptr());
+        }
+    }
+
+    pub fn get_encoder(&mut self) -> &mut CANEncoder {
+        &mut self.encoder
+    }
+
+    pub fn get_pid_controller(&mut self) -> &mut CANPIDController {
+        &mut self.pid_controller
+    }
+
+    /// Copies `leader`'s output, reversed if `invert` is set
+    pub fn follow(&mut self, leader: &CANSparkMax, invert: bool /* false */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.Follow(&*leader.handle.0, invert) })
+    }
+
+    pub fn is_follower(&mut self) -> bool {
+        unsafe { self.handle.0.IsFollower() }
+    }
+
+    pub fn set_idle_mode(&mut self, mode: IdleMode) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.SetIdleMode(mode) })
+    }
+
+    /// Current limit the SparkMax holds the motor to, adjusting output smoothly
+    pub fn set_smart_current_limit(&mut self, limit: ElectricCurrent) -> Result<()> {
+        frc_rev::to_result(unsafe {
+            self.handle
+                .0
+                .SetSmartCurrentLimit(limit.get::<ampere>().round() as u32)
+        })
+    }
+
+    /// Hard current limit that cuts output for `limit_cycles` cycles of 50 µs when exceeded
+    pub fn set_secondary_current_limit(
+        &mut self,
+        limit: ElectricCurrent,
+        limit_cycles: i32, /* 0 */
+    ) -> Result<()> {
+        frc_rev::to_result(unsafe {
+            self.handle
+                .0
+                .SetSecondaryCurrentLimit(limit.get::<ampere>(), limit_cycles)
+        })
+    }
+
+    /// Time to ramp from neutral to full output in open loop
+    pub fn set_open_loop_ramp_rate(&mut self, rate: Time) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.SetOpenLoopRampRate(rate.get::<second>()) })
+    }
+
+    /// Time to ramp from neutral to full output in closed loop
+    pub fn set_closed_loop_ramp_rate(&mut self, rate: Time) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.SetClosedLoopRampRate(rate.get::<second>()) })
+    }
+
+    pub fn enable_voltage_compensation(
+        &mut self,
+        nominal_voltage: ElectricPotential,
+    ) -> Result<()> {
+        frc_rev::to_result(unsafe {
+            self.handle
+                .0
+                .EnableVoltageCompensation(nominal_voltage.get::<volt>())
+        })
+    }
+
+    pub fn disable_voltage_compensation(&mut self) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.DisableVoltageCompensation() })
+    }
+
+    pub fn get_bus_voltage(&mut self) -> ElectricPotential {
+        ElectricPotential::new::<volt>(unsafe { self.handle.0.GetBusVoltage() })
+    }
+
+    /// Output as a fraction of the bus voltage, from -1 to 1
+    pub fn get_applied_output(&mut self) -> f64 {
+        unsafe { self.handle.0.GetAppliedOutput() }
+    }
+
+    pub fn get_output_current(&mut self) -> ElectricCurrent {
+        ElectricCurrent::new::<ampere>(unsafe { self.handle.0.GetOutputCurrent() })
+    }
+
+    pub fn get_motor_temperature(&mut self) -> ThermodynamicTemperature {
+        ThermodynamicTemperature::new::<degree_celsius>(unsafe {
+            self.handle.0.GetMotorTemperature()
+        })
+    }
+
+    pub fn restore_factory_defaults(&mut self, persist: bool /* false */) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.RestoreFactoryDefaults(persist) })
+    }
+
+    /// Saves the current settings so they survive the SparkMax losing power
+    pub fn burn_flash(&mut self) -> Result<()> {
+        frc_rev::to_result(unsafe { self.handle.0.BurnFlash() })
+    }
+}
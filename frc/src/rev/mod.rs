@@ -0,0 +1,29 @@
+//! Rust bindings to rev
+
+use anyhow::{anyhow, Result};
+use frc_sys::rev::{
+    rev_CANEncoder_EncoderType, rev_CANError, rev_CANPIDController_ArbFFUnits,
+    rev_CANSparkMaxLowLevel_MotorType, rev_CANSparkMax_IdleMode, rev_ControlType,
+};
+
+mod can_encoder;
+pub use can_encoder::*;
+
+mod can_pid_controller;
+pub use can_pid_controller::*;
+
+mod can_spark_max;
+pub use can_spark_max::*;
+
+pub type ArbFFUnits = rev_CANPIDController_ArbFFUnits;
+pub type ControlType = rev_ControlType;
+pub type EncoderType = rev_CANEncoder_EncoderType;
+pub type IdleMode = rev_CANSparkMax_IdleMode;
+pub type MotorType = rev_CANSparkMaxLowLevel_MotorType;
+
+pub(crate) fn to_result(err: rev_CANError) -> Result<()> {
+    match err {
+        rev_CANError::kOk => Ok(()),
+        e => Err(anyhow!(e)),
+    }
+}
@@ -1,29 +1,69 @@
 use std::sync::{Arc, Mutex};
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use frc::{hal, wpilib::driver_station};
+use tetanus_control::characterization::{SysIdConfig, SysIdRoutine};
+use tetanus_control::geometry::Pose2d;
+use tetanus_core::consumer::Consumer;
 use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+use tetanus_core::resource::{Arbiter, Resource, Resources};
+use tetanus_frc::characterization::DATA_DIRECTORY;
+use tetanus_frc::clock::FpgaClock;
+use tetanus_frc::esc::TalonFxEsc;
+use uom::si::electric_potential::volt;
 use uom::si::f64::*;
-use uom::si::ratio::ratio;
 use uom::si::time::millisecond;
 
 use crate::subsystems::{
-    driver::Driver,
-    drivetrain::{Drivetrain, DrivetrainMsg},
+    driver::{Driver, TankShaping},
+    drivetrain::{Drivetrain, DrivetrainMsg, DrivetrainOdometry, EncoderHeading, DRIVETRAIN},
 };
 
+type DrivetrainRoutine = SysIdRoutine<Drivetrain<TalonFxEsc>, FpgaClock>;
+
 pub fn start_competition() -> Result<()> {
     let mut ds_ticker = BaseNode::new();
 
     let robot = FunkyRobot::new();
+    let resources = Resources::new();
+
+    let characterization = Arc::new(Mutex::new(SysIdRoutine::new(
+        robot.drivetrain.clone(),
+        FpgaClock,
+        SysIdConfig::default(),
+        Drivetrain::<TalonFxEsc>::sysid_data(),
+    )));
+    let mut characterized = false;
 
+    // Test mode characterizes the drivetrain, taking it from the joysticks until it's left
+    let mut arbiter = Arbiter::new(&resources, "drivetrain");
+    arbiter.consume_exclusive(
+        &resources,
+        "drivetrain",
+        Arc::new(Mutex::new(DrivetrainControl {
+            drivetrain: robot.drivetrain.clone(),
+            characterization: characterization.clone(),
+        })),
+    )?;
     ds_ticker
-        .produce(robot.driver)
-        .map(|msg| DrivetrainMsg {
-            left: Ratio::new::<ratio>(msg.left_stick_y),
-            right: Ratio::new::<ratio>(msg.right_stick_y),
+        .map(|()| {
+            Some(DrivetrainDemand::Characterize)
+                .filter(|_| driver_station::is_test() && driver_station::is_enabled())
         })
-        .consume(robot.drivetrain);
+        .chain(arbiter.input("characterization", 1));
+
+    // Tracked every tick so the pose is current whenever something needs it
+    let odometry = robot.odometry.clone();
+    ds_ticker.produce(odometry.clone());
+
+    let precision = robot.driver.lock().unwrap().precision_mode();
+    let shaping = Arc::new(Mutex::new(TankShaping::new(precision, FpgaClock)));
+
+    ds_ticker
+        .produce(robot.driver)
+        .process(shaping)
+        .map(|msg| Some(DrivetrainDemand::Drive(msg)))
+        .chain(arbiter.input("teleop", 0));
 
     unsafe {
         hal::HAL_ObserveUserProgramStarting();
@@ -31,6 +71,28 @@ pub fn start_competition() -> Result<()> {
 
     loop {
         driver_station::wait_for_data_with_timeout(Time::new::<millisecond>(20.0));
+        ds_ticker.send(());
+
+        if driver_station::is_test() && driver_station::is_enabled() {
+            unsafe {
+                hal::HAL_ObserveUserProgramTest();
+            }
+            let routine = characterization.lock().unwrap();
+            if routine.is_finished() && !characterized {
+                characterized = true;
+                if let Err(e) = save_characterization(&routine) {
+                    println!("{:?}", e);
+                }
+            }
+            continue;
+        }
+        if !driver_station::is_test() || !characterized {
+            // Interrupted runs start over the next time test mode is enabled, and saved ones
+            // once test mode is left
+            characterization.lock().unwrap().reset();
+            characterized = false;
+        }
+
         if driver_station::is_operator_control_enabled() {
             unsafe {
                 hal::HAL_ObserveUserProgramTeleop();
@@ -39,25 +101,77 @@ pub fn start_competition() -> Result<()> {
             unsafe {
                 hal::HAL_ObserveUserProgramDisabled();
             }
+            // Poses are measured from wherever the robot is enabled
+            odometry.lock().unwrap().reset(Pose2d::default());
         }
-        ds_ticker.send(());
     }
 }
 
+/// What the drivetrain is asked to do by whoever the arbiter lets drive it
+#[derive(Clone, Copy)]
+enum DrivetrainDemand {
+    Drive(DrivetrainMsg),
+    Characterize,
+}
+
+struct DrivetrainControl {
+    drivetrain: Arc<Mutex<Drivetrain<TalonFxEsc>>>,
+    characterization: Arc<Mutex<DrivetrainRoutine>>,
+}
+
+impl Consumer for DrivetrainControl {
+    type Msg = DrivetrainDemand;
+
+    fn output(&mut self, msg: Self::Msg) {
+        match msg {
+            DrivetrainDemand::Drive(msg) => self.drivetrain.lock().unwrap().output(msg),
+            DrivetrainDemand::Characterize => self.characterization.lock().unwrap().output(()),
+        }
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        vec![DRIVETRAIN]
+    }
+}
+
+fn save_characterization(routine: &DrivetrainRoutine) -> Result<()> {
+    std::fs::create_dir_all(DATA_DIRECTORY)
+        .with_context(|| format!("couldn't create {}", DATA_DIRECTORY))?;
+    routine
+        .data()
+        .save(format!("{}/drivetrain.json", DATA_DIRECTORY))?;
+
+    let fit = routine.data().fit()?;
+    println!(
+        "Drivetrain kS = {:.3} V, kV = {:.3} V·s/m, kA = {:.3} V·s²/m (r² = {:.3})",
+        fit.ks.get::<volt>(),
+        fit.kv,
+        fit.ka,
+        fit.r_squared
+    );
+    Ok(())
+}
+
 pub fn end_competition() {
     println!("End");
 }
 
 pub struct FunkyRobot {
     driver: Arc<Mutex<Driver>>,
-    drivetrain: Arc<Mutex<Drivetrain>>,
+    drivetrain: Arc<Mutex<Drivetrain<TalonFxEsc>>>,
+    odometry: Arc<Mutex<DrivetrainOdometry<TalonFxEsc, EncoderHeading<TalonFxEsc>>>>,
 }
 
 impl FunkyRobot {
     pub fn new() -> Self {
+        let drivetrain = Arc::new(Mutex::new(Drivetrain::new(TalonFxEsc::new)));
+        let heading = Arc::new(Mutex::new(EncoderHeading::new(drivetrain.clone())));
+        let odometry = DrivetrainOdometry::new(drivetrain.clone(), heading, Pose2d::default());
+
         FunkyRobot {
             driver: Arc::new(Mutex::new(Driver::new())),
-            drivetrain: Arc::new(Mutex::new(Drivetrain::new())),
+            drivetrain,
+            odometry: Arc::new(Mutex::new(odometry)),
         }
     }
 }
@@ -1,5 +1,15 @@
 use frc::wpilib::{generic_hid::JoystickHand, GenericHID, XboxController};
+use tetanus_control::shaping::{
+    Deadband, PowerCurve, PrecisionMode, Scale, Shaper, SlewRateLimiter,
+};
+use tetanus_core::clock::Clock;
+use tetanus_core::processor::Processor;
 use tetanus_core::producer::Producer;
+use tetanus_frc::hid::XboxTriggers;
+use uom::si::f64::*;
+use uom::si::ratio::ratio;
+
+use super::drivetrain::DrivetrainMsg;
 
 #[derive(Clone, Copy, Debug)]
 pub struct DriverMsg {
@@ -19,6 +29,12 @@ impl Driver {
             controller: XboxController::new(Self::XBOX_PORT),
         }
     }
+
+    /// Precision mode for driving, active while the right bumper is held
+    pub fn precision_mode(&self) -> PrecisionMode {
+        let bumper = self.controller.right_bumper();
+        PrecisionMode::new(TankShaping::PRECISION_SCALE, move || bumper.get())
+    }
 }
 
 // TODO
@@ -35,3 +51,47 @@ impl Producer for Driver {
         }
     }
 }
+
+/// Turns driver sticks into tank drive outputs
+pub struct TankShaping {
+    left: Box<dyn Shaper>,
+    right: Box<dyn Shaper>,
+}
+
+impl TankShaping {
+    const DEADBAND: f64 = 0.08;
+    const EXPONENT: f64 = 2.0;
+    const SPEED_FACTOR: f64 = 0.2;
+    const PRECISION_SCALE: f64 = 0.5;
+    /// Full output per second
+    const SLEW_RATE: f64 = 1.0;
+
+    pub fn new(precision: PrecisionMode, clock: impl Clock + Clone + 'static) -> Self {
+        let stick = |precision: PrecisionMode| -> Box<dyn Shaper> {
+            Box::new(
+                Deadband::new(Self::DEADBAND)
+                    .then(PowerCurve::new(Self::EXPONENT))
+                    .then(Scale::new(Self::SPEED_FACTOR))
+                    .then(precision)
+                    .then(SlewRateLimiter::new(Self::SLEW_RATE, clock.clone())),
+            )
+        };
+
+        TankShaping {
+            left: stick(precision.clone()),
+            right: stick(precision),
+        }
+    }
+}
+
+impl Processor for TankShaping {
+    type In = DriverMsg;
+    type Out = DrivetrainMsg;
+
+    fn process(&mut self, msg: Self::In) -> Self::Out {
+        DrivetrainMsg {
+            left: Ratio::new::<ratio>(self.left.shape(msg.left_stick_y)),
+            right: Ratio::new::<ratio>(self.right.shape(msg.right_stick_y)),
+        }
+    }
+}
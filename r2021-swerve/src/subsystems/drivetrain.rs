@@ -1,7 +1,18 @@
-use frc::ctre::motorcontrol::{can::BaseMotorController, FollowerType, TalonFXInvertType};
+use std::sync::{Arc, Mutex};
+
+use tetanus_control::characterization::{self, Characterize, DrivetrainSample, SysIdData};
+use tetanus_control::geometry::{Pose2d, Rotation2d};
+use tetanus_control::kinematics::DifferentialDriveKinematics;
+use tetanus_control::odometry::DifferentialDriveOdometry;
 use tetanus_core::consumer::Consumer;
-use tetanus_frc::esc::{self, OffloadedEsc, OffloadedEscConfig};
+use tetanus_core::producer::Producer;
+use tetanus_core::resource::Resource;
+use tetanus_frc::characterization::mechanism_sample;
+use tetanus_frc::esc::{Esc, EscConfig};
+use tetanus_frc::mechanism::Mechanism;
+use uom::si::angle::radian;
 use uom::si::f64::*;
+use uom::si::length::{inch, meter};
 use uom::si::ratio::ratio;
 
 #[derive(Clone, Copy, Debug)]
@@ -10,90 +21,198 @@ pub struct DrivetrainMsg {
     pub right: Ratio,
 }
 
+pub const DRIVETRAIN: Resource = Resource::new("drivetrain");
+
 #[allow(dead_code)]
-pub struct Drivetrain {
-    left_master_esc: OffloadedEsc,
-    left_slave_esc: OffloadedEsc,
-    right_master_esc: OffloadedEsc,
-    right_slave_esc: OffloadedEsc,
+pub struct Drivetrain<E> {
+    left_master: Mechanism<E>,
+    left_slave: E,
+    right_master: Mechanism<E>,
+    right_slave: E,
 }
 
-impl Drivetrain {
+impl<E: Esc> Drivetrain<E> {
     const LEFT_MASTER_ID: i32 = 30;
     const LEFT_SLAVE_ID: i32 = 32;
     const RIGHT_MASTER_ID: i32 = 33;
     const RIGHT_SLAVE_ID: i32 = 31;
 
-    const LEFT_INVERSION: TalonFXInvertType = TalonFXInvertType::CounterClockwise;
+    const LEFT_INVERTED: bool = false;
     const LEFT_SENSOR_INVERSION: bool = true;
 
-    const RIGHT_INVERSION: TalonFXInvertType = TalonFXInvertType::Clockwise;
+    const RIGHT_INVERTED: bool = true;
     const RIGHT_SENSOR_INVERSION: bool = false;
 
-    const SPEED_FACTOR: f64 = 0.2;
-
-    pub fn new() -> Self {
-        let mut left_master_esc = OffloadedEsc::talon_fx(Self::LEFT_MASTER_ID);
-        let mut left_slave_esc = OffloadedEsc::talon_fx(Self::LEFT_SLAVE_ID);
-        let mut right_master_esc = OffloadedEsc::talon_fx(Self::RIGHT_MASTER_ID);
-        let mut right_slave_esc = OffloadedEsc::talon_fx(Self::RIGHT_SLAVE_ID);
-
-        left_master_esc
-            .setup(OffloadedEscConfig::default())
-            .unwrap();
-        left_master_esc.config_talon_fx(|t| {
-            t.set_selected_sensor_position(0.0, 0, esc::CONFIG_TIMEOUT_MS)
-                .unwrap();
-            t.set_inverted(Self::LEFT_INVERSION);
-            t.set_sensor_phase(Self::LEFT_SENSOR_INVERSION);
-        });
-
-        left_slave_esc.setup(OffloadedEscConfig::default()).unwrap();
-        left_slave_esc.config_talon_fx(|t| {
-            t.follow(
-                left_master_esc.as_talon_fx(),
-                FollowerType::FollowerType_PercentOutput,
-            );
-            t.set_inverted(Self::LEFT_INVERSION);
-        });
-
-        right_master_esc
-            .setup(OffloadedEscConfig::default())
-            .unwrap();
-        right_master_esc.config_talon_fx(|t| {
-            t.set_selected_sensor_position(0.0, 0, esc::CONFIG_TIMEOUT_MS)
-                .unwrap();
-            t.set_inverted(Self::RIGHT_INVERSION);
-            t.set_sensor_phase(Self::RIGHT_SENSOR_INVERSION);
-        });
-
-        right_slave_esc
-            .setup(OffloadedEscConfig::default())
-            .unwrap();
-        right_slave_esc.config_talon_fx(|t| {
-            t.follow(
-                right_master_esc.as_talon_fx(),
-                FollowerType::FollowerType_PercentOutput,
-            );
-            t.set_inverted(Self::RIGHT_INVERSION);
-        });
+    /// Falcon revolutions per wheel revolution, from the gearbox's nominal ratio
+    const GEAR_RATIO: f64 = 10.71;
+    /// Nominal size; measure the worn tread before relying on distances
+    const WHEEL_DIAMETER_IN: f64 = 6.0;
+    const TRACK_WIDTH_IN: f64 = 22.0;
+
+    /// `esc` creates the controller with a given CAN ID
+    pub fn new(esc: impl Fn(i32) -> E) -> Self {
+        let mut left_master_esc = esc(Self::LEFT_MASTER_ID);
+        let mut left_slave_esc = esc(Self::LEFT_SLAVE_ID);
+        let mut right_master_esc = esc(Self::RIGHT_MASTER_ID);
+        let mut right_slave_esc = esc(Self::RIGHT_SLAVE_ID);
+
+        left_master_esc.setup(EscConfig::default()).unwrap();
+        left_master_esc.set_inverted(Self::LEFT_INVERTED);
+        left_master_esc.set_sensor_phase(Self::LEFT_SENSOR_INVERSION);
+
+        left_slave_esc.setup(EscConfig::default()).unwrap();
+        left_slave_esc.set_inverted(Self::LEFT_INVERTED);
+        left_slave_esc.follow(&mut left_master_esc).unwrap();
+
+        right_master_esc.setup(EscConfig::default()).unwrap();
+        right_master_esc.set_inverted(Self::RIGHT_INVERTED);
+        right_master_esc.set_sensor_phase(Self::RIGHT_SENSOR_INVERSION);
+
+        right_slave_esc.setup(EscConfig::default()).unwrap();
+        right_slave_esc.set_inverted(Self::RIGHT_INVERTED);
+        right_slave_esc.follow(&mut right_master_esc).unwrap();
+
+        let wheel_diameter = Length::new::<inch>(Self::WHEEL_DIAMETER_IN);
+        let mut left_master = Mechanism::linear(left_master_esc, Self::GEAR_RATIO, wheel_diameter);
+        left_master.set_position(Length::default()).unwrap();
+        let mut right_master =
+            Mechanism::linear(right_master_esc, Self::GEAR_RATIO, wheel_diameter);
+        right_master.set_position(Length::default()).unwrap();
 
         Drivetrain {
-            left_master_esc,
-            left_slave_esc,
-            right_master_esc,
-            right_slave_esc,
+            left_master,
+            left_slave: left_slave_esc,
+            right_master,
+            right_slave: right_slave_esc,
         }
     }
+
+    pub fn kinematics() -> DifferentialDriveKinematics {
+        DifferentialDriveKinematics::new(Length::new::<inch>(Self::TRACK_WIDTH_IN))
+    }
+
+    /// Total distance travelled by the left and right wheels since the encoders were zeroed
+    pub fn wheel_distances(&mut self) -> (Length, Length) {
+        (self.left_master.position(), self.right_master.position())
+    }
+
+    /// Empty characterization data, labelled for SysId's drivetrain analysis
+    pub fn sysid_data() -> SysIdData<DrivetrainSample> {
+        let wheel_circumference =
+            Length::new::<inch>(Self::WHEEL_DIAMETER_IN) * std::f64::consts::PI;
+        SysIdData::new(
+            characterization::Mechanism::Drivetrain,
+            "Meters",
+            wheel_circumference.get::<meter>(),
+        )
+    }
 }
 
-impl Consumer for Drivetrain {
+impl<E: Esc> Consumer for Drivetrain<E> {
     type Msg = DrivetrainMsg;
 
     fn output(&mut self, msg: Self::Msg) {
-        self.left_master_esc
-            .output_percent(msg.left.get::<ratio>() * Self::SPEED_FACTOR);
-        self.right_master_esc
-            .output_percent(msg.right.get::<ratio>() * Self::SPEED_FACTOR);
+        self.left_master.output_percent(msg.left.get::<ratio>());
+        self.right_master.output_percent(msg.right.get::<ratio>());
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        vec![DRIVETRAIN]
+    }
+}
+
+impl<E: Esc> Characterize for Drivetrain<E> {
+    type Sample = DrivetrainSample;
+
+    fn apply(&mut self, voltage: ElectricPotential) {
+        self.left_master.output_voltage(voltage);
+        self.right_master.output_voltage(voltage);
+    }
+
+    fn sample(&mut self, timestamp: Time) -> Self::Sample {
+        DrivetrainSample {
+            left: mechanism_sample(&mut self.left_master, timestamp),
+            right: mechanism_sample(&mut self.right_master, timestamp),
+            // No gyro yet, which only SysId's track width test needs
+            angle: Angle::default(),
+            angular_rate: AngularVelocity::default(),
+        }
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        vec![DRIVETRAIN]
+    }
+}
+
+/// Heading from how much farther the right wheels have travelled than the left, until there's
+/// a gyro to read instead
+///
+/// Drifts whenever the wheels slip, such as when turning in place.
+pub struct EncoderHeading<E> {
+    drivetrain: Arc<Mutex<Drivetrain<E>>>,
+}
+
+impl<E: Esc> EncoderHeading<E> {
+    pub fn new(drivetrain: Arc<Mutex<Drivetrain<E>>>) -> Self {
+        EncoderHeading { drivetrain }
+    }
+}
+
+impl<E: Esc> Producer for EncoderHeading<E> {
+    type Msg = Angle;
+
+    fn next(&self) -> Self::Msg {
+        let (left, right) = self.drivetrain.lock().unwrap().wheel_distances();
+        let track_width = Length::new::<inch>(Drivetrain::<E>::TRACK_WIDTH_IN);
+        Angle::new::<radian>((right - left).get::<meter>() / track_width.get::<meter>())
+    }
+}
+
+/// Field pose of the drivetrain, from its encoders and a heading source such as a gyro
+pub struct DrivetrainOdometry<E, H> {
+    drivetrain: Arc<Mutex<Drivetrain<E>>>,
+    heading: Arc<Mutex<H>>,
+    odometry: Mutex<DifferentialDriveOdometry>,
+}
+
+impl<E: Esc, H: Producer<Msg = Angle>> DrivetrainOdometry<E, H> {
+    pub fn new(
+        drivetrain: Arc<Mutex<Drivetrain<E>>>,
+        heading: Arc<Mutex<H>>,
+        initial: Pose2d,
+    ) -> Self {
+        let (left, right) = drivetrain.lock().unwrap().wheel_distances();
+        let odometry = DifferentialDriveOdometry::new(
+            Drivetrain::<E>::kinematics(),
+            Rotation2d::new(heading.lock().unwrap().next()),
+            left,
+            right,
+            initial,
+        );
+
+        DrivetrainOdometry {
+            drivetrain,
+            heading,
+            odometry: Mutex::new(odometry),
+        }
+    }
+
+    pub fn reset(&self, pose: Pose2d) {
+        let (left, right) = self.drivetrain.lock().unwrap().wheel_distances();
+        let heading = Rotation2d::new(self.heading.lock().unwrap().next());
+        self.odometry
+            .lock()
+            .unwrap()
+            .reset(pose, heading, left, right);
+    }
+}
+
+impl<E: Esc, H: Producer<Msg = Angle>> Producer for DrivetrainOdometry<E, H> {
+    type Msg = Pose2d;
+
+    fn next(&self) -> Self::Msg {
+        let (left, right) = self.drivetrain.lock().unwrap().wheel_distances();
+        let heading = Rotation2d::new(self.heading.lock().unwrap().next());
+        self.odometry.lock().unwrap().update(heading, left, right)
     }
 }
@@ -0,0 +1,323 @@
+use std::any;
+use std::sync::{Arc, Mutex};
+
+use tetanus_core::clock::Clock;
+use tetanus_core::consumer::Consumer;
+use tetanus_core::resource::Resource;
+use uom::si::f64::*;
+
+use crate::group::{Deadline, Parallel, Race, Sequence};
+
+/// Unit of robot behaviour run by the [`crate::scheduler::Scheduler`]
+///
+/// Once scheduled, `initialize` is called once, then `execute` and `is_finished` every tick until
+/// the command finishes or is interrupted, and finally `end`.
+pub trait Command: Send + Sync {
+    fn initialize(&mut self) {}
+
+    fn execute(&mut self) {}
+
+    fn is_finished(&mut self) -> bool {
+        false
+    }
+
+    fn end(&mut self, _interrupted: bool) {}
+
+    /// Resources this command needs exclusive use of while it runs
+    fn requirements(&self) -> Vec<Resource> {
+        Vec::new()
+    }
+
+    /// Whether scheduling another command with overlapping requirements may interrupt this one
+    fn is_interruptible(&self) -> bool {
+        true
+    }
+
+    fn name(&self) -> &'static str {
+        any::type_name::<Self>()
+    }
+}
+
+impl Command for Box<dyn Command> {
+    fn initialize(&mut self) {
+        (**self).initialize();
+    }
+
+    fn execute(&mut self) {
+        (**self).execute();
+    }
+
+    fn is_finished(&mut self) -> bool {
+        (**self).is_finished()
+    }
+
+    fn end(&mut self, interrupted: bool) {
+        (**self).end(interrupted);
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        (**self).requirements()
+    }
+
+    fn is_interruptible(&self) -> bool {
+        (**self).is_interruptible()
+    }
+
+    fn name(&self) -> &'static str {
+        (**self).name()
+    }
+}
+
+/// Combinators available on every command
+pub trait CommandExt: Command + Sized + 'static {
+    fn boxed(self) -> Box<dyn Command> {
+        Box::new(self)
+    }
+
+    fn and_then(self, next: impl Command + 'static) -> Sequence {
+        Sequence::new(vec![self.boxed(), next.boxed()])
+    }
+
+    fn alongside(self, other: impl Command + 'static) -> Parallel {
+        Parallel::new(vec![self.boxed(), other.boxed()])
+    }
+
+    fn race_with(self, other: impl Command + 'static) -> Race {
+        Race::new(vec![self.boxed(), other.boxed()])
+    }
+
+    /// Runs `other` alongside this command, interrupting it once this command finishes
+    fn deadline_for(self, other: impl Command + 'static) -> Deadline {
+        Deadline::new(self.boxed(), vec![other.boxed()])
+    }
+
+    fn with_timeout(self, timeout: Time, clock: impl Clock + 'static) -> Race {
+        self.race_with(WaitCommand::new(timeout, clock))
+    }
+}
+
+impl<C: Command + Sized + 'static> CommandExt for C {}
+
+/// Runs a function once and finishes immediately
+pub struct InstantCommand<F> {
+    f: F,
+    requirements: Vec<Resource>,
+}
+
+impl<F: FnMut() + Send + Sync> InstantCommand<F> {
+    pub fn new(f: F, requirements: &[Resource]) -> Self {
+        InstantCommand {
+            f,
+            requirements: requirements.to_vec(),
+        }
+    }
+}
+
+impl<F: FnMut() + Send + Sync> Command for InstantCommand<F> {
+    fn initialize(&mut self) {
+        (self.f)();
+    }
+
+    fn is_finished(&mut self) -> bool {
+        true
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        self.requirements.clone()
+    }
+}
+
+/// Runs a function every tick until interrupted
+pub struct RunCommand<F> {
+    f: F,
+    requirements: Vec<Resource>,
+}
+
+impl<F: FnMut() + Send + Sync> RunCommand<F> {
+    pub fn new(f: F, requirements: &[Resource]) -> Self {
+        RunCommand {
+            f,
+            requirements: requirements.to_vec(),
+        }
+    }
+}
+
+impl<F: FnMut() + Send + Sync> Command for RunCommand<F> {
+    fn execute(&mut self) {
+        (self.f)();
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        self.requirements.clone()
+    }
+}
+
+/// Sends the output of a function to a consumer every tick until interrupted
+///
+/// Requires the consumer's resources.
+pub struct OutputCommand<C, F> {
+    consumer: Arc<Mutex<C>>,
+    f: F,
+}
+
+impl<C, F> OutputCommand<C, F>
+where
+    C: Consumer,
+    F: FnMut() -> C::Msg + Send + Sync,
+{
+    pub fn new(consumer: Arc<Mutex<C>>, f: F) -> Self {
+        OutputCommand { consumer, f }
+    }
+}
+
+impl<C, F> Command for OutputCommand<C, F>
+where
+    C: Consumer,
+    F: FnMut() -> C::Msg + Send + Sync,
+{
+    fn execute(&mut self) {
+        let msg = (self.f)();
+        self.consumer.lock().unwrap().output(msg);
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        self.consumer.lock().unwrap().resources()
+    }
+}
+
+/// Command built from closures for each stage
+pub struct FunctionalCommand<I, E, F, N> {
+    on_init: I,
+    on_execute: E,
+    is_finished: F,
+    on_end: N,
+    requirements: Vec<Resource>,
+}
+
+impl<I, E, F, N> FunctionalCommand<I, E, F, N>
+where
+    I: FnMut() + Send + Sync,
+    E: FnMut() + Send + Sync,
+    F: FnMut() -> bool + Send + Sync,
+    N: FnMut(bool) + Send + Sync,
+{
+    pub fn new(
+        on_init: I,
+        on_execute: E,
+        is_finished: F,
+        on_end: N,
+        requirements: &[Resource],
+    ) -> Self {
+        FunctionalCommand {
+            on_init,
+            on_execute,
+            is_finished,
+            on_end,
+            requirements: requirements.to_vec(),
+        }
+    }
+}
+
+impl<I, E, F, N> Command for FunctionalCommand<I, E, F, N>
+where
+    I: FnMut() + Send + Sync,
+    E: FnMut() + Send + Sync,
+    F: FnMut() -> bool + Send + Sync,
+    N: FnMut(bool) + Send + Sync,
+{
+    fn initialize(&mut self) {
+        (self.on_init)();
+    }
+
+    fn execute(&mut self) {
+        (self.on_execute)();
+    }
+
+    fn is_finished(&mut self) -> bool {
+        (self.is_finished)()
+    }
+
+    fn end(&mut self, interrupted: bool) {
+        (self.on_end)(interrupted);
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        self.requirements.clone()
+    }
+}
+
+/// Finishes once a duration has passed since it was initialized
+pub struct WaitCommand<C> {
+    duration: Time,
+    clock: C,
+    start: Time,
+}
+
+impl<C: Clock> WaitCommand<C> {
+    pub fn new(duration: Time, clock: C) -> Self {
+        WaitCommand {
+            duration,
+            clock,
+            start: Time::default(),
+        }
+    }
+}
+
+impl<C: Clock> Command for WaitCommand<C> {
+    fn initialize(&mut self) {
+        self.start = self.clock.now();
+    }
+
+    fn is_finished(&mut self) -> bool {
+        self.clock.now() - self.start >= self.duration
+    }
+}
+
+/// Finishes once a condition becomes true
+pub struct WaitUntilCommand<F> {
+    condition: F,
+}
+
+impl<F: FnMut() -> bool + Send + Sync> WaitUntilCommand<F> {
+    pub fn new(condition: F) -> Self {
+        WaitUntilCommand { condition }
+    }
+}
+
+impl<F: FnMut() -> bool + Send + Sync> Command for WaitUntilCommand<F> {
+    fn is_finished(&mut self) -> bool {
+        (self.condition)()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scheduler::Scheduler;
+    use tetanus_core::testing::FakeClock;
+    use uom::si::time::millisecond;
+
+    #[test]
+    fn with_timeout_interrupts_after_duration() {
+        let clock = FakeClock::new();
+        let ticks = Arc::new(Mutex::new(0));
+        let mut scheduler = Scheduler::new();
+        let id = {
+            let ticks = ticks.clone();
+            scheduler
+                .schedule(
+                    RunCommand::new(move || *ticks.lock().unwrap() += 1, &[])
+                        .with_timeout(Time::new::<millisecond>(50.0), clock.clone()),
+                )
+                .unwrap()
+        };
+
+        for _ in 0..5 {
+            clock.advance(Time::new::<millisecond>(20.0));
+            scheduler.run();
+        }
+
+        assert!(!scheduler.is_scheduled(id));
+        assert_eq!(*ticks.lock().unwrap(), 3);
+    }
+}
@@ -0,0 +1,294 @@
+//! Commands composed out of other commands
+//!
+//! A group requires the union of its members' requirements and can only be interrupted if all of
+//! its members can. Members that run at the same time can't share requirements, since they'd both
+//! drive the same hardware every tick, so building such a group panics.
+
+use tetanus_core::resource::Resource;
+
+use crate::command::Command;
+
+fn union_requirements(commands: &[Box<dyn Command>]) -> Vec<Resource> {
+    let mut requirements: Vec<Resource> = commands.iter().flat_map(|c| c.requirements()).collect();
+    requirements.sort();
+    requirements.dedup();
+    requirements
+}
+
+fn all_interruptible(commands: &[Box<dyn Command>]) -> bool {
+    commands.iter().all(|c| c.is_interruptible())
+}
+
+/// Runs commands one after another
+pub struct Sequence {
+    commands: Vec<Box<dyn Command>>,
+    current: usize,
+}
+
+impl Sequence {
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Sequence {
+            current: commands.len(),
+            commands,
+        }
+    }
+}
+
+impl Command for Sequence {
+    fn initialize(&mut self) {
+        self.current = 0;
+        if let Some(command) = self.commands.first_mut() {
+            command.initialize();
+        }
+    }
+
+    fn execute(&mut self) {
+        let command = match self.commands.get_mut(self.current) {
+            Some(command) => command,
+            None => return,
+        };
+
+        command.execute();
+        if command.is_finished() {
+            command.end(false);
+            self.current += 1;
+            if let Some(next) = self.commands.get_mut(self.current) {
+                next.initialize();
+            }
+        }
+    }
+
+    fn is_finished(&mut self) -> bool {
+        self.current >= self.commands.len()
+    }
+
+    fn end(&mut self, interrupted: bool) {
+        if interrupted {
+            if let Some(command) = self.commands.get_mut(self.current) {
+                command.end(true);
+            }
+        }
+        self.current = self.commands.len();
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        union_requirements(&self.commands)
+    }
+
+    fn is_interruptible(&self) -> bool {
+        all_interruptible(&self.commands)
+    }
+}
+
+/// Members of a group running at the same time, tracking which are still going
+struct Running {
+    commands: Vec<Box<dyn Command>>,
+    running: Vec<bool>,
+}
+
+impl Running {
+    fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        let mut required: Vec<(Resource, &'static str)> = Vec::new();
+        for command in &commands {
+            for resource in command.requirements() {
+                if let Some((_, other)) = required.iter().find(|(r, _)| *r == resource) {
+                    panic!(
+                        "{} and {} both require {} but would run at the same time",
+                        other,
+                        command.name(),
+                        resource
+                    );
+                }
+                required.push((resource, command.name()));
+            }
+        }
+
+        Running {
+            running: vec![false; commands.len()],
+            commands,
+        }
+    }
+
+    fn initialize(&mut self) {
+        for (command, running) in self.commands.iter_mut().zip(&mut self.running) {
+            command.initialize();
+            *running = true;
+        }
+    }
+
+    /// Executes every running command, returning the indices of the ones that finished
+    fn execute(&mut self) -> Vec<usize> {
+        let mut finished = Vec::new();
+        for (i, (command, running)) in self.commands.iter_mut().zip(&mut self.running).enumerate() {
+            if !*running {
+                continue;
+            }
+
+            command.execute();
+            if command.is_finished() {
+                command.end(false);
+                *running = false;
+                finished.push(i);
+            }
+        }
+        finished
+    }
+
+    fn interrupt_all(&mut self) {
+        for (command, running) in self.commands.iter_mut().zip(&mut self.running) {
+            if *running {
+                command.end(true);
+                *running = false;
+            }
+        }
+    }
+
+    fn any_running(&self) -> bool {
+        self.running.iter().any(|r| *r)
+    }
+}
+
+/// Runs commands at the same time, finishing when all of them have
+pub struct Parallel {
+    group: Running,
+}
+
+impl Parallel {
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Parallel {
+            group: Running::new(commands),
+        }
+    }
+}
+
+impl Command for Parallel {
+    fn initialize(&mut self) {
+        self.group.initialize();
+    }
+
+    fn execute(&mut self) {
+        self.group.execute();
+    }
+
+    fn is_finished(&mut self) -> bool {
+        !self.group.any_running()
+    }
+
+    fn end(&mut self, _interrupted: bool) {
+        self.group.interrupt_all();
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        union_requirements(&self.group.commands)
+    }
+
+    fn is_interruptible(&self) -> bool {
+        all_interruptible(&self.group.commands)
+    }
+}
+
+/// Runs commands at the same time, finishing as soon as any one of them does
+pub struct Race {
+    group: Running,
+    finished: bool,
+}
+
+impl Race {
+    pub fn new(commands: Vec<Box<dyn Command>>) -> Self {
+        Race {
+            group: Running::new(commands),
+            finished: false,
+        }
+    }
+}
+
+impl Command for Race {
+    fn initialize(&mut self) {
+        self.finished = false;
+        self.group.initialize();
+    }
+
+    fn execute(&mut self) {
+        if !self.group.execute().is_empty() {
+            self.finished = true;
+        }
+    }
+
+    fn is_finished(&mut self) -> bool {
+        self.finished
+    }
+
+    fn end(&mut self, _interrupted: bool) {
+        self.group.interrupt_all();
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        union_requirements(&self.group.commands)
+    }
+
+    fn is_interruptible(&self) -> bool {
+        all_interruptible(&self.group.commands)
+    }
+}
+
+/// Runs commands at the same time, finishing when the first one (the deadline) does
+pub struct Deadline {
+    group: Running,
+    finished: bool,
+}
+
+impl Deadline {
+    pub fn new(deadline: Box<dyn Command>, others: Vec<Box<dyn Command>>) -> Self {
+        let mut commands = vec![deadline];
+        commands.extend(others);
+        Deadline {
+            group: Running::new(commands),
+            finished: false,
+        }
+    }
+}
+
+impl Command for Deadline {
+    fn initialize(&mut self) {
+        self.finished = false;
+        self.group.initialize();
+    }
+
+    fn execute(&mut self) {
+        if self.group.execute().contains(&0) {
+            self.finished = true;
+        }
+    }
+
+    fn is_finished(&mut self) -> bool {
+        self.finished
+    }
+
+    fn end(&mut self, _interrupted: bool) {
+        self.group.interrupt_all();
+    }
+
+    fn requirements(&self) -> Vec<Resource> {
+        union_requirements(&self.group.commands)
+    }
+
+    fn is_interruptible(&self) -> bool {
+        all_interruptible(&self.group.commands)
+    }
+}
+
+pub fn sequence(commands: Vec<Box<dyn Command>>) -> Sequence {
+    Sequence::new(commands)
+}
+
+pub fn parallel(commands: Vec<Box<dyn Command>>) -> Parallel {
+    Parallel::new(commands)
+}
+
+pub fn race(commands: Vec<Box<dyn Command>>) -> Race {
+    Race::new(commands)
+}
+
+pub fn deadline(deadline: Box<dyn Command>, others: Vec<Box<dyn Command>>) -> Deadline {
+    Deadline::new(deadline, others)
+}
@@ -0,0 +1,10 @@
+//! Command-based robot framework built on tetanus-core
+//!
+//! Commands describe what the robot should do over time, and the [`scheduler::Scheduler`] runs them
+//! every tick while making sure no two of them drive the same
+//! [`tetanus_core::resource::Resource`] at once.
+
+pub mod command;
+pub mod group;
+pub mod scheduler;
+pub mod trigger;
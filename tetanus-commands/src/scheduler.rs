@@ -0,0 +1,581 @@
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use tetanus_core::consumer::Consumer;
+use tetanus_core::resource::Resource;
+
+use crate::command::Command;
+use crate::trigger::{Binding, Trigger};
+
+/// Handle to a command that has been scheduled
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CommandId(u64);
+
+struct Scheduled {
+    id: CommandId,
+    command: Box<dyn Command>,
+    requirements: Vec<Resource>,
+    /// Resource this command is the default command for, so it can be put back when it ends
+    default_for: Option<Resource>,
+}
+
+type CommandFactory = Box<dyn FnMut() -> Box<dyn Command> + Send + Sync>;
+
+/// Command factory bound to a trigger's edges
+struct Bound {
+    trigger: Trigger,
+    binding: Binding,
+    factory: CommandFactory,
+    previous: bool,
+    /// Last command started by this binding
+    started: Option<CommandId>,
+}
+
+/// Runs commands, making sure no two running commands share a requirement
+///
+/// The scheduler is a [`Consumer`] of ticks, so it can be driven straight from the graph.
+#[derive(Default)]
+pub struct Scheduler {
+    scheduled: Vec<Scheduled>,
+    defaults: BTreeMap<Resource, Option<Box<dyn Command>>>,
+    bindings: Vec<Bound>,
+    next_id: u64,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts `command`, interrupting any running commands that share its requirements
+    ///
+    /// Returns `None` without starting it if one of those commands can't be interrupted.
+    pub fn schedule(&mut self, command: impl Command + 'static) -> Option<CommandId> {
+        self.schedule_boxed(Box::new(command), None)
+    }
+
+    /// Sets the command that runs whenever nothing else requires `resource`
+    pub fn set_default_command(
+        &mut self,
+        resource: Resource,
+        command: impl Command + 'static,
+    ) -> Result<()> {
+        if !command.requirements().contains(&resource) {
+            bail!(
+                "Default command {} for \"{}\" must require it",
+                command.name(),
+                resource
+            );
+        }
+
+        if let Some(i) = self.position(|s| s.default_for == Some(resource)) {
+            self.end(i, true);
+        }
+        self.defaults.insert(resource, Some(Box::new(command)));
+        Ok(())
+    }
+
+    pub fn cancel(&mut self, id: CommandId) {
+        if let Some(i) = self.position(|s| s.id == id) {
+            self.end(i, true);
+        }
+    }
+
+    pub fn cancel_all(&mut self) {
+        while !self.scheduled.is_empty() {
+            self.end(0, true);
+        }
+    }
+
+    pub fn is_scheduled(&self, id: CommandId) -> bool {
+        self.position(|s| s.id == id).is_some()
+    }
+
+    /// Name of the command currently holding each required resource
+    pub fn holders(&self) -> Vec<(Resource, &'static str)> {
+        let mut holders: Vec<_> = self
+            .scheduled
+            .iter()
+            .flat_map(|s| s.requirements.iter().map(move |r| (*r, s.command.name())))
+            .collect();
+        holders.sort();
+        holders
+    }
+
+    /// Polls trigger bindings, runs every scheduled command once, then starts default commands for
+    /// idle resources
+    pub fn run(&mut self) {
+        self.poll_bindings();
+
+        let mut i = 0;
+        while i < self.scheduled.len() {
+            let command = &mut self.scheduled[i].command;
+            command.execute();
+            if command.is_finished() {
+                self.end(i, false);
+            } else {
+                i += 1;
+            }
+        }
+
+        let idle: Vec<Resource> = self
+            .defaults
+            .iter()
+            .filter(|(resource, command)| command.is_some() && !self.is_required(**resource))
+            .map(|(resource, _)| *resource)
+            .collect();
+        for resource in idle {
+            if let Some(command) = self.defaults.get_mut(&resource).and_then(Option::take) {
+                // Put back by schedule_boxed if another of its requirements can't be interrupted
+                self.schedule_boxed(command, Some(resource));
+            }
+        }
+    }
+
+    pub(crate) fn bind(&mut self, trigger: Trigger, binding: Binding, factory: CommandFactory) {
+        self.bindings.push(Bound {
+            previous: trigger.get(),
+            trigger,
+            binding,
+            factory,
+            started: None,
+        });
+    }
+
+    fn poll_bindings(&mut self) {
+        let mut bindings = std::mem::take(&mut self.bindings);
+        for bound in &mut bindings {
+            let current = bound.trigger.get();
+            let rose = current && !bound.previous;
+            let fell = !current && bound.previous;
+            bound.previous = current;
+
+            match bound.binding {
+                Binding::OnTrue if rose => {
+                    self.schedule_boxed((bound.factory)(), None);
+                }
+                Binding::OnFalse if fell => {
+                    self.schedule_boxed((bound.factory)(), None);
+                }
+                Binding::WhileTrue if rose => {
+                    bound.started = self.schedule_boxed((bound.factory)(), None);
+                }
+                Binding::WhileTrue if fell => {
+                    if let Some(id) = bound.started.take() {
+                        self.cancel(id);
+                    }
+                }
+                Binding::ToggleOnTrue if rose => match bound.started.take() {
+                    Some(id) if self.is_scheduled(id) => self.cancel(id),
+                    _ => bound.started = self.schedule_boxed((bound.factory)(), None),
+                },
+                _ => {}
+            }
+        }
+        self.bindings = bindings;
+    }
+
+    fn schedule_boxed(
+        &mut self,
+        mut command: Box<dyn Command>,
+        default_for: Option<Resource>,
+    ) -> Option<CommandId> {
+        let requirements = command.requirements();
+        let conflicts = |s: &Scheduled| s.requirements.iter().any(|r| requirements.contains(r));
+
+        if self
+            .scheduled
+            .iter()
+            .any(|s| conflicts(s) && !s.command.is_interruptible())
+        {
+            if let Some(resource) = default_for {
+                self.defaults.insert(resource, Some(command));
+            }
+            return None;
+        }
+
+        while let Some(i) = self.position(conflicts) {
+            self.end(i, true);
+        }
+
+        let id = CommandId(self.next_id);
+        self.next_id += 1;
+
+        command.initialize();
+        self.scheduled.push(Scheduled {
+            id,
+            command,
+            requirements,
+            default_for,
+        });
+        Some(id)
+    }
+
+    fn end(&mut self, i: usize, interrupted: bool) {
+        let mut scheduled = self.scheduled.remove(i);
+        scheduled.command.end(interrupted);
+
+        if let Some(resource) = scheduled.default_for {
+            if let Some(slot @ None) = self.defaults.get_mut(&resource) {
+                *slot = Some(scheduled.command);
+            }
+        }
+    }
+
+    fn is_required(&self, resource: Resource) -> bool {
+        self.scheduled
+            .iter()
+            .any(|s| s.requirements.contains(&resource))
+    }
+
+    fn position(&self, f: impl Fn(&Scheduled) -> bool) -> Option<usize> {
+        self.scheduled.iter().position(f)
+    }
+}
+
+impl Consumer for Scheduler {
+    type Msg = ();
+
+    fn output(&mut self, _msg: Self::Msg) {
+        self.run();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::{CommandExt, OutputCommand, RunCommand};
+    use crate::group::{deadline, parallel, race, sequence};
+    use std::sync::{Arc, Mutex};
+    use tetanus_core::testing::Probe;
+
+    const DRIVE: Resource = Resource::new("drive");
+    const INTAKE: Resource = Resource::new("intake");
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Command that logs each stage and finishes after a number of executes
+    struct Step {
+        name: &'static str,
+        log: Log,
+        ticks: Option<usize>,
+        executed: usize,
+        requirements: Vec<Resource>,
+        interruptible: bool,
+    }
+
+    impl Step {
+        fn new(
+            name: &'static str,
+            log: &Log,
+            ticks: Option<usize>,
+            requirements: &[Resource],
+        ) -> Self {
+            Step {
+                name,
+                log: log.clone(),
+                ticks,
+                executed: 0,
+                requirements: requirements.to_vec(),
+                interruptible: true,
+            }
+        }
+
+        fn record(&self, stage: &str) {
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", self.name, stage));
+        }
+    }
+
+    impl Command for Step {
+        fn initialize(&mut self) {
+            self.executed = 0;
+            self.record("init");
+        }
+
+        fn execute(&mut self) {
+            self.executed += 1;
+            self.record("exec");
+        }
+
+        fn is_finished(&mut self) -> bool {
+            matches!(self.ticks, Some(t) if self.executed >= t)
+        }
+
+        fn end(&mut self, interrupted: bool) {
+            self.record(if interrupted { "interrupted" } else { "end" });
+        }
+
+        fn requirements(&self) -> Vec<Resource> {
+            self.requirements.clone()
+        }
+
+        fn is_interruptible(&self) -> bool {
+            self.interruptible
+        }
+
+        fn name(&self) -> &'static str {
+            self.name
+        }
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        log.lock().unwrap().drain(..).collect()
+    }
+
+    #[test]
+    fn command_lifecycle() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        let id = scheduler
+            .schedule(Step::new("a", &log, Some(2), &[]))
+            .unwrap();
+
+        scheduler.run();
+        assert!(scheduler.is_scheduled(id));
+        scheduler.run();
+        assert!(!scheduler.is_scheduled(id));
+
+        assert_eq!(take(&log), ["a init", "a exec", "a exec", "a end"]);
+    }
+
+    #[test]
+    fn conflicting_command_interrupts_running_one() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Step::new("a", &log, None, &[DRIVE]));
+        scheduler.schedule(Step::new("b", &log, None, &[INTAKE]));
+        scheduler.schedule(Step::new("c", &log, None, &[DRIVE]));
+
+        assert_eq!(take(&log), ["a init", "b init", "a interrupted", "c init"]);
+        assert_eq!(scheduler.holders(), [(DRIVE, "c"), (INTAKE, "b")]);
+    }
+
+    #[test]
+    fn uninterruptible_command_blocks_conflicts() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        let mut climb = Step::new("climb", &log, None, &[DRIVE]);
+        climb.interruptible = false;
+        scheduler.schedule(climb);
+
+        assert_eq!(
+            scheduler.schedule(Step::new("drive", &log, None, &[DRIVE])),
+            None
+        );
+        assert_eq!(scheduler.holders(), [(DRIVE, "climb")]);
+    }
+
+    #[test]
+    fn default_command_resumes_when_resource_is_free() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler
+            .set_default_command(DRIVE, Step::new("teleop", &log, None, &[DRIVE]))
+            .unwrap();
+
+        scheduler.run();
+        scheduler.schedule(Step::new("auto", &log, Some(1), &[DRIVE]));
+        scheduler.run();
+        scheduler.run();
+
+        assert_eq!(
+            take(&log),
+            [
+                "teleop init",
+                "teleop interrupted",
+                "auto init",
+                "auto exec",
+                "auto end",
+                "teleop init",
+                "teleop exec",
+            ]
+        );
+    }
+
+    #[test]
+    fn default_command_must_require_its_resource() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+
+        assert!(scheduler
+            .set_default_command(DRIVE, Step::new("idle", &log, None, &[INTAKE]))
+            .is_err());
+    }
+
+    #[test]
+    fn sequence_runs_commands_in_order() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(Step::new("a", &log, Some(1), &[]).and_then(Step::new(
+            "b",
+            &log,
+            Some(1),
+            &[],
+        )));
+
+        scheduler.run();
+        scheduler.run();
+
+        assert_eq!(
+            take(&log),
+            ["a init", "a exec", "a end", "b init", "b exec", "b end"]
+        );
+    }
+
+    #[test]
+    fn parallel_waits_for_all() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        let id = scheduler
+            .schedule(parallel(vec![
+                Step::new("a", &log, Some(1), &[]).boxed(),
+                Step::new("b", &log, Some(2), &[]).boxed(),
+            ]))
+            .unwrap();
+
+        scheduler.run();
+        assert!(scheduler.is_scheduled(id));
+        scheduler.run();
+        assert!(!scheduler.is_scheduled(id));
+
+        assert_eq!(
+            take(&log),
+            ["a init", "b init", "a exec", "a end", "b exec", "b exec", "b end"]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "a and b both require drive")]
+    fn concurrent_members_cannot_share_requirements() {
+        let log = Log::default();
+        deadline(
+            Step::new("a", &log, Some(1), &[DRIVE]).boxed(),
+            vec![
+                Step::new("b", &log, None, &[INTAKE, DRIVE]).boxed(),
+                Step::new("c", &log, None, &[]).boxed(),
+            ],
+        );
+    }
+
+    #[test]
+    fn sequence_members_can_share_requirements() {
+        let log = Log::default();
+        let group = sequence(vec![
+            Step::new("a", &log, Some(1), &[DRIVE]).boxed(),
+            Step::new("b", &log, Some(1), &[DRIVE, INTAKE]).boxed(),
+        ]);
+
+        assert_eq!(group.requirements(), [DRIVE, INTAKE]);
+    }
+
+    #[test]
+    fn race_interrupts_the_losers() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(race(vec![
+            Step::new("a", &log, None, &[]).boxed(),
+            Step::new("b", &log, Some(1), &[]).boxed(),
+        ]));
+
+        scheduler.run();
+
+        assert_eq!(
+            take(&log),
+            [
+                "a init",
+                "b init",
+                "a exec",
+                "b exec",
+                "b end",
+                "a interrupted"
+            ]
+        );
+    }
+
+    #[test]
+    fn deadline_ends_with_first_command() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(deadline(
+            Step::new("a", &log, Some(2), &[]).boxed(),
+            vec![
+                Step::new("b", &log, Some(1), &[]).boxed(),
+                Step::new("c", &log, None, &[]).boxed(),
+            ],
+        ));
+
+        scheduler.run();
+        scheduler.run();
+
+        assert_eq!(
+            take(&log),
+            [
+                "a init",
+                "b init",
+                "c init",
+                "a exec",
+                "b exec",
+                "b end",
+                "c exec",
+                "a exec",
+                "a end",
+                "c exec",
+                "c interrupted",
+            ]
+        );
+    }
+
+    #[test]
+    fn interrupting_a_group_interrupts_its_running_member() {
+        let log = Log::default();
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(sequence(vec![
+            Step::new("a", &log, Some(1), &[DRIVE]).boxed(),
+            Step::new("b", &log, None, &[INTAKE]).boxed(),
+        ]));
+        scheduler.run();
+        take(&log);
+
+        scheduler.schedule(Step::new("c", &log, None, &[DRIVE]));
+
+        assert_eq!(take(&log), ["b interrupted", "c init"]);
+    }
+
+    /// Probe that needs the drive, like a drivetrain subsystem
+    struct DriveProbe(Probe<'static, f64>);
+
+    impl Consumer for DriveProbe {
+        type Msg = f64;
+
+        fn output(&mut self, msg: Self::Msg) {
+            self.0.output(msg);
+        }
+
+        fn resources(&self) -> Vec<Resource> {
+            vec![DRIVE]
+        }
+    }
+
+    #[test]
+    fn output_command_drives_consumer_and_requires_its_resources() {
+        let drive = Arc::new(Mutex::new(DriveProbe(Probe::new())));
+        let command = OutputCommand::new(drive.clone(), || 0.5);
+        assert_eq!(command.requirements(), [DRIVE]);
+
+        let mut scheduler = Scheduler::new();
+        let id = scheduler.schedule(command).unwrap();
+        scheduler.run();
+        scheduler.run();
+
+        drive.lock().unwrap().0.assert_received(&[0.5, 0.5]);
+        let held: Vec<_> = scheduler.holders().into_iter().map(|(r, _)| r).collect();
+        assert_eq!(held, [DRIVE]);
+
+        // Anything else needing the drive takes it over
+        scheduler.schedule(RunCommand::new(|| {}, &[DRIVE]));
+        assert!(!scheduler.is_scheduled(id));
+    }
+}
@@ -0,0 +1,318 @@
+use std::ops::Not;
+use std::sync::{Arc, Mutex};
+
+use tetanus_core::clock::Clock;
+use tetanus_core::producer::Producer;
+use uom::si::f64::*;
+
+use crate::command::{Command, CommandExt};
+use crate::scheduler::Scheduler;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebounceType {
+    /// Only delay changes from false to true
+    Rising,
+    /// Only delay changes from true to false
+    Falling,
+    Both,
+}
+
+/// Boolean condition, such as a button being held, that commands can be bound to
+///
+/// Triggers are also [`Producer`]s, so they can be used as a boolean stream in the graph.
+#[derive(Clone)]
+pub struct Trigger {
+    condition: Arc<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl Trigger {
+    pub fn new(condition: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        Trigger {
+            condition: Arc::new(condition),
+        }
+    }
+
+    pub fn get(&self) -> bool {
+        (self.condition)()
+    }
+
+    pub fn and(&self, other: &Trigger) -> Trigger {
+        let (a, b) = (self.clone(), other.clone());
+        Trigger::new(move || a.get() && b.get())
+    }
+
+    pub fn or(&self, other: &Trigger) -> Trigger {
+        let (a, b) = (self.clone(), other.clone());
+        Trigger::new(move || a.get() || b.get())
+    }
+
+    /// Only lets a change through once the condition has held its new value for `time`
+    pub fn debounce(&self, time: Time, kind: DebounceType, clock: impl Clock + 'static) -> Trigger {
+        let inner = self.clone();
+        let state = Mutex::new((false, clock.now()));
+
+        Trigger::new(move || {
+            let input = inner.get();
+            let now = clock.now();
+            let mut state = state.lock().unwrap();
+            let (output, since) = &mut *state;
+
+            if input == *output {
+                *since = now;
+                return *output;
+            }
+
+            let delayed = match kind {
+                DebounceType::Rising => input,
+                DebounceType::Falling => !input,
+                DebounceType::Both => true,
+            };
+            if !delayed || now - *since >= time {
+                *output = input;
+                *since = now;
+            }
+            *output
+        })
+    }
+
+    /// Schedules a command from `factory` when the condition becomes true
+    pub fn on_true<C: Command + 'static>(
+        &self,
+        scheduler: &mut Scheduler,
+        factory: impl FnMut() -> C + Send + Sync + 'static,
+    ) -> &Self {
+        self.bind(scheduler, Binding::OnTrue, factory)
+    }
+
+    /// Schedules a command from `factory` when the condition becomes false
+    pub fn on_false<C: Command + 'static>(
+        &self,
+        scheduler: &mut Scheduler,
+        factory: impl FnMut() -> C + Send + Sync + 'static,
+    ) -> &Self {
+        self.bind(scheduler, Binding::OnFalse, factory)
+    }
+
+    /// Schedules a command from `factory` when the condition becomes true and cancels it when it
+    /// becomes false
+    pub fn while_true<C: Command + 'static>(
+        &self,
+        scheduler: &mut Scheduler,
+        factory: impl FnMut() -> C + Send + Sync + 'static,
+    ) -> &Self {
+        self.bind(scheduler, Binding::WhileTrue, factory)
+    }
+
+    /// Alternates between scheduling and cancelling a command from `factory` each time the
+    /// condition becomes true
+    pub fn toggle_on_true<C: Command + 'static>(
+        &self,
+        scheduler: &mut Scheduler,
+        factory: impl FnMut() -> C + Send + Sync + 'static,
+    ) -> &Self {
+        self.bind(scheduler, Binding::ToggleOnTrue, factory)
+    }
+
+    fn bind<C: Command + 'static>(
+        &self,
+        scheduler: &mut Scheduler,
+        binding: Binding,
+        mut factory: impl FnMut() -> C + Send + Sync + 'static,
+    ) -> &Self {
+        scheduler.bind(self.clone(), binding, Box::new(move || factory().boxed()));
+        self
+    }
+}
+
+impl Not for Trigger {
+    type Output = Trigger;
+
+    fn not(self) -> Self::Output {
+        Trigger::new(move || !self.get())
+    }
+}
+
+impl Producer for Trigger {
+    type Msg = bool;
+
+    fn next(&self) -> Self::Msg {
+        self.get()
+    }
+}
+
+/// How a command bound to a trigger reacts to the trigger's edges
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Binding {
+    OnTrue,
+    OnFalse,
+    WhileTrue,
+    ToggleOnTrue,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command::RunCommand;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use tetanus_core::node::{BaseNode, Node};
+    use tetanus_core::testing::{tick, FakeClock, Probe};
+    use uom::si::time::millisecond;
+
+    fn button() -> (Arc<AtomicBool>, Trigger) {
+        let pressed = Arc::new(AtomicBool::new(false));
+        let trigger = {
+            let pressed = pressed.clone();
+            Trigger::new(move || pressed.load(Ordering::SeqCst))
+        };
+        (pressed, trigger)
+    }
+
+    /// Factory for commands that count their executes
+    fn counter(
+        count: &Arc<AtomicUsize>,
+    ) -> impl FnMut() -> RunCommand<Box<dyn FnMut() + Send + Sync>> {
+        let count = count.clone();
+        move || {
+            let count = count.clone();
+            let f: Box<dyn FnMut() + Send + Sync> = Box::new(move || {
+                count.fetch_add(1, Ordering::SeqCst);
+            });
+            RunCommand::new(f, &[])
+        }
+    }
+
+    #[test]
+    fn combinators() {
+        let (a_pressed, a) = button();
+        let (b_pressed, b) = button();
+        let both = a.and(&b);
+        let either = a.or(&b);
+        let neither = !either.clone();
+
+        a_pressed.store(true, Ordering::SeqCst);
+        assert!(!both.get() && either.get() && !neither.get());
+
+        b_pressed.store(true, Ordering::SeqCst);
+        assert!(both.get());
+
+        a_pressed.store(false, Ordering::SeqCst);
+        b_pressed.store(false, Ordering::SeqCst);
+        assert!(neither.get());
+    }
+
+    #[test]
+    fn debounce_rising_delays_only_presses() {
+        let clock = FakeClock::new();
+        let (pressed, raw) = button();
+        let trigger = raw.debounce(
+            Time::new::<millisecond>(50.0),
+            DebounceType::Rising,
+            clock.clone(),
+        );
+
+        pressed.store(true, Ordering::SeqCst);
+        assert!(!trigger.get());
+        clock.advance(Time::new::<millisecond>(30.0));
+        assert!(!trigger.get());
+        clock.advance(Time::new::<millisecond>(30.0));
+        assert!(trigger.get());
+
+        pressed.store(false, Ordering::SeqCst);
+        assert!(!trigger.get());
+    }
+
+    #[test]
+    fn debounce_both_ignores_bounces() {
+        let clock = FakeClock::new();
+        let (pressed, raw) = button();
+        let trigger = raw.debounce(
+            Time::new::<millisecond>(50.0),
+            DebounceType::Both,
+            clock.clone(),
+        );
+
+        pressed.store(true, Ordering::SeqCst);
+        clock.advance(Time::new::<millisecond>(60.0));
+        assert!(trigger.get());
+
+        pressed.store(false, Ordering::SeqCst);
+        clock.advance(Time::new::<millisecond>(20.0));
+        assert!(trigger.get());
+        pressed.store(true, Ordering::SeqCst);
+        clock.advance(Time::new::<millisecond>(40.0));
+        assert!(trigger.get());
+    }
+
+    #[test]
+    fn on_true_schedules_on_each_press() {
+        let (pressed, trigger) = button();
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new();
+        trigger.on_true(&mut scheduler, counter(&count));
+
+        scheduler.run();
+        assert_eq!(count.load(Ordering::SeqCst), 0);
+
+        pressed.store(true, Ordering::SeqCst);
+        scheduler.run();
+        pressed.store(false, Ordering::SeqCst);
+        scheduler.run();
+
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn while_true_cancels_on_release() {
+        let (pressed, trigger) = button();
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new();
+        trigger.while_true(&mut scheduler, counter(&count));
+
+        pressed.store(true, Ordering::SeqCst);
+        scheduler.run();
+        scheduler.run();
+        pressed.store(false, Ordering::SeqCst);
+        scheduler.run();
+        scheduler.run();
+
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn toggle_on_true_alternates() {
+        let (pressed, trigger) = button();
+        let count = Arc::new(AtomicUsize::new(0));
+        let mut scheduler = Scheduler::new();
+        trigger.toggle_on_true(&mut scheduler, counter(&count));
+
+        let press = |scheduler: &mut Scheduler| {
+            pressed.store(true, Ordering::SeqCst);
+            scheduler.run();
+            pressed.store(false, Ordering::SeqCst);
+            scheduler.run();
+        };
+
+        press(&mut scheduler);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        press(&mut scheduler);
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        press(&mut scheduler);
+        assert_eq!(count.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn trigger_is_a_boolean_stream() {
+        let (pressed, trigger) = button();
+        let mut ticker = BaseNode::new();
+        let probe = Probe::new();
+        ticker
+            .produce(Arc::new(Mutex::new(trigger)))
+            .chain(probe.clone());
+
+        tick(&ticker, 1);
+        pressed.store(true, Ordering::SeqCst);
+        tick(&ticker, 1);
+
+        probe.assert_received(&[false, true]);
+    }
+}
@@ -0,0 +1,617 @@
+//! Feedforward characterization, like WPILib's SysId
+//!
+//! A [`SysIdRoutine`] runs the four standard tests on a mechanism: a slow (quasistatic) voltage
+//! ramp and a sudden (dynamic) voltage step, each forwards and backwards. The recorded data can be
+//! saved for the SysId tool to analyze, or fit on the robot for a quick estimate of the gains.
+//!
+//! Positions and velocities are recorded in base SI units, meters or radians.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context, Result};
+use nalgebra::{DMatrix, DVector};
+use serde_json::{json, Map, Value};
+use tetanus_core::clock::Clock;
+use tetanus_core::consumer::Consumer;
+use tetanus_core::resource::Resource;
+use uom::si::angle::radian;
+use uom::si::angular_velocity::radian_per_second;
+use uom::si::electric_potential::volt;
+use uom::si::f64::*;
+use uom::si::time::second;
+
+use crate::feedforward::{ArmFeedforward, ElevatorFeedforward, SimpleMotorFeedforward};
+use crate::units::SiValue;
+
+/// Samples slower than this, in base SI units, are left out of fits since static friction
+/// rather than `kS` holds the mechanism still
+const MIN_FIT_VELOCITY: f64 = 1e-3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SysIdTest {
+    QuasistaticForward,
+    QuasistaticReverse,
+    DynamicForward,
+    DynamicReverse,
+}
+
+impl SysIdTest {
+    /// In the order a [`SysIdRoutine`] runs them
+    pub const ALL: [SysIdTest; 4] = [
+        SysIdTest::QuasistaticForward,
+        SysIdTest::QuasistaticReverse,
+        SysIdTest::DynamicForward,
+        SysIdTest::DynamicReverse,
+    ];
+
+    /// Name of this test's data in a SysId data file
+    pub fn key(self) -> &'static str {
+        match self {
+            SysIdTest::QuasistaticForward => "slow-forward",
+            SysIdTest::QuasistaticReverse => "slow-backward",
+            SysIdTest::DynamicForward => "fast-forward",
+            SysIdTest::DynamicReverse => "fast-backward",
+        }
+    }
+
+    pub fn is_quasistatic(self) -> bool {
+        matches!(
+            self,
+            SysIdTest::QuasistaticForward | SysIdTest::QuasistaticReverse
+        )
+    }
+
+    fn direction(self) -> f64 {
+        match self {
+            SysIdTest::QuasistaticForward | SysIdTest::DynamicForward => 1.0,
+            SysIdTest::QuasistaticReverse | SysIdTest::DynamicReverse => -1.0,
+        }
+    }
+}
+
+/// Kind of mechanism, which decides the model SysId fits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mechanism {
+    /// No gravity load, like a flywheel or turret
+    Simple,
+    /// Constant gravity load
+    Elevator,
+    /// Gravity load varying with the cosine of the angle from horizontal
+    Arm,
+    Drivetrain,
+}
+
+impl Mechanism {
+    fn name(self) -> &'static str {
+        match self {
+            Mechanism::Simple => "Simple",
+            Mechanism::Elevator => "Elevator",
+            Mechanism::Arm => "Arm",
+            Mechanism::Drivetrain => "Drivetrain",
+        }
+    }
+}
+
+/// One measurement of a single motor or gearbox
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MechanismSample {
+    pub timestamp: Time,
+    /// Voltage actually applied to the motor
+    pub voltage: ElectricPotential,
+    pub position: f64,
+    pub velocity: f64,
+}
+
+/// One measurement of both sides of a differential drivetrain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DrivetrainSample {
+    pub left: MechanismSample,
+    pub right: MechanismSample,
+    pub angle: Angle,
+    pub angular_rate: AngularVelocity,
+}
+
+pub trait SysIdSample: Copy + Send + Sync {
+    /// Columns of this sample in a SysId data file
+    fn row(&self) -> Vec<f64>;
+
+    /// Each independently driven part, for fitting
+    fn sides(&self) -> Vec<MechanismSample>;
+}
+
+impl SysIdSample for MechanismSample {
+    fn row(&self) -> Vec<f64> {
+        vec![
+            self.timestamp.get::<second>(),
+            self.voltage.get::<volt>(),
+            self.position,
+            self.velocity,
+        ]
+    }
+
+    fn sides(&self) -> Vec<MechanismSample> {
+        vec![*self]
+    }
+}
+
+impl SysIdSample for DrivetrainSample {
+    fn row(&self) -> Vec<f64> {
+        vec![
+            self.left.timestamp.get::<second>(),
+            self.left.voltage.get::<volt>(),
+            self.right.voltage.get::<volt>(),
+            self.left.position,
+            self.right.position,
+            self.left.velocity,
+            self.right.velocity,
+            self.angle.get::<radian>(),
+            self.angular_rate.get::<radian_per_second>(),
+        ]
+    }
+
+    fn sides(&self) -> Vec<MechanismSample> {
+        vec![self.left, self.right]
+    }
+}
+
+/// Samples recorded from each test
+#[derive(Clone, Debug, PartialEq)]
+pub struct SysIdData<S> {
+    mechanism: Mechanism,
+    units: &'static str,
+    units_per_rotation: f64,
+    tests: BTreeMap<SysIdTest, Vec<S>>,
+}
+
+impl<S: SysIdSample> SysIdData<S> {
+    /// `units` is what SysId should label positions with, such as `"Meters"` or `"Radians"`, and
+    /// `units_per_rotation` how far one turn of the encoder moves the mechanism in those units
+    pub fn new(mechanism: Mechanism, units: &'static str, units_per_rotation: f64) -> Self {
+        SysIdData {
+            mechanism,
+            units,
+            units_per_rotation,
+            tests: BTreeMap::new(),
+        }
+    }
+
+    pub fn record(&mut self, test: SysIdTest, sample: S) {
+        self.tests.entry(test).or_default().push(sample);
+    }
+
+    pub fn samples(&self, test: SysIdTest) -> &[S] {
+        self.tests.get(&test).map_or(&[], Vec::as_slice)
+    }
+
+    /// Data in the JSON format SysId loads
+    pub fn to_json(&self) -> Result<String> {
+        let mut file = Map::new();
+        for &test in SysIdTest::ALL.iter() {
+            let rows: Vec<Vec<f64>> = self.samples(test).iter().map(SysIdSample::row).collect();
+            file.insert(test.key().to_string(), json!(rows));
+        }
+        file.insert("sysid".to_string(), json!(true));
+        file.insert("test".to_string(), json!(self.mechanism.name()));
+        file.insert("units".to_string(), json!(self.units));
+        file.insert(
+            "unitsPerRotation".to_string(),
+            json!(self.units_per_rotation),
+        );
+        Ok(serde_json::to_string(&Value::Object(file))?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("couldn't write characterization data {}", path.display()))
+    }
+
+    /// Least-squares fit of `V = kS·sgn(v) + kV·v + kA·a`, plus `kG` for elevators and arms
+    ///
+    /// Acceleration comes from differencing velocity, so this is noisier than SysId's own
+    /// analysis and best used as a starting point.
+    pub fn fit(&self) -> Result<FeedforwardFit> {
+        let gravity = matches!(self.mechanism, Mechanism::Elevator | Mechanism::Arm);
+        let columns = if gravity { 4 } else { 3 };
+        let mut regressors = Vec::new();
+        let mut voltages = Vec::new();
+
+        for samples in self.tests.values() {
+            let sides = samples.first().map_or(0, |sample| sample.sides().len());
+            for side in 0..sides {
+                let series: Vec<MechanismSample> =
+                    samples.iter().map(|sample| sample.sides()[side]).collect();
+                for window in series.windows(3) {
+                    let (before, sample, after) = (window[0], window[1], window[2]);
+                    let dt = (after.timestamp - before.timestamp).get::<second>();
+                    if dt <= 0.0 || sample.velocity.abs() < MIN_FIT_VELOCITY {
+                        continue;
+                    }
+                    let acceleration = (after.velocity - before.velocity) / dt;
+                    regressors.push(sample.velocity.signum());
+                    regressors.push(sample.velocity);
+                    regressors.push(acceleration);
+                    match self.mechanism {
+                        Mechanism::Elevator => regressors.push(1.0),
+                        Mechanism::Arm => regressors.push(sample.position.cos()),
+                        Mechanism::Simple | Mechanism::Drivetrain => {}
+                    }
+                    voltages.push(sample.voltage.get::<volt>());
+                }
+            }
+        }
+
+        if voltages.len() < columns {
+            bail!("not enough moving samples to fit a feedforward");
+        }
+        let x = DMatrix::from_row_slice(voltages.len(), columns, &regressors);
+        let y = DVector::from_vec(voltages);
+        let gains = match (x.transpose() * &x).cholesky() {
+            Some(normal) => normal.solve(&(x.transpose() * &y)),
+            None => bail!("characterization data doesn't vary enough to fit a feedforward"),
+        };
+
+        let residual = (&y - &x * &gains).norm_squared();
+        let mean = y.mean();
+        let total: f64 = y.iter().map(|v| (v - mean).powi(2)).sum();
+        Ok(FeedforwardFit {
+            ks: ElectricPotential::new::<volt>(gains[0]),
+            kv: gains[1],
+            ka: gains[2],
+            kg: ElectricPotential::new::<volt>(if gravity { gains[3] } else { 0.0 }),
+            r_squared: if total > 0.0 {
+                1.0 - residual / total
+            } else {
+                1.0
+            },
+        })
+    }
+}
+
+/// Gains estimated by [`SysIdData::fit`]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FeedforwardFit {
+    pub ks: ElectricPotential,
+    pub kv: f64,
+    pub ka: f64,
+    /// Zero unless the mechanism is an elevator or arm
+    pub kg: ElectricPotential,
+    /// How much of the variation in voltage the fit explains, 1.0 being all of it
+    pub r_squared: f64,
+}
+
+impl FeedforwardFit {
+    pub fn simple<V: SiValue, A: SiValue>(&self) -> SimpleMotorFeedforward<V, A> {
+        SimpleMotorFeedforward::new(self.ks, self.kv, self.ka)
+    }
+
+    pub fn elevator(&self) -> ElevatorFeedforward {
+        ElevatorFeedforward::new(self.ks, self.kg, self.kv, self.ka)
+    }
+
+    pub fn arm(&self) -> ArmFeedforward {
+        ArmFeedforward::new(self.ks, self.kg, self.kv, self.ka)
+    }
+}
+
+/// A mechanism a [`SysIdRoutine`] can drive by voltage and measure
+pub trait Characterize: Send + Sync {
+    type Sample: SysIdSample;
+
+    fn apply(&mut self, voltage: ElectricPotential);
+
+    fn sample(&mut self, timestamp: Time) -> Self::Sample;
+
+    /// Resources driven by [`Characterize::apply`]
+    fn resources(&self) -> Vec<Resource>;
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SysIdConfig {
+    /// Voltage added each second during quasistatic tests
+    pub quasistatic_ramp: ElectricPotential,
+    pub quasistatic_duration: Time,
+    pub dynamic_step: ElectricPotential,
+    pub dynamic_duration: Time,
+    /// Time at zero volts between tests, for the mechanism to stop
+    pub rest: Time,
+}
+
+impl Default for SysIdConfig {
+    fn default() -> Self {
+        SysIdConfig {
+            quasistatic_ramp: ElectricPotential::new::<volt>(1.0),
+            quasistatic_duration: Time::new::<second>(7.0),
+            dynamic_step: ElectricPotential::new::<volt>(7.0),
+            dynamic_duration: Time::new::<second>(2.0),
+            rest: Time::new::<second>(3.0),
+        }
+    }
+}
+
+impl SysIdConfig {
+    fn duration(&self, test: SysIdTest) -> Time {
+        if test.is_quasistatic() {
+            self.quasistatic_duration
+        } else {
+            self.dynamic_duration
+        }
+    }
+
+    /// Test running `elapsed` after the routine started and the voltage it applies, or `None`
+    /// while resting or once every test is done
+    fn schedule(&self, mut elapsed: Time) -> Option<(SysIdTest, ElectricPotential)> {
+        for &test in SysIdTest::ALL.iter() {
+            let duration = self.duration(test);
+            if elapsed < duration {
+                let voltage = if test.is_quasistatic() {
+                    self.quasistatic_ramp * elapsed.get::<second>()
+                } else {
+                    self.dynamic_step
+                };
+                return Some((test, voltage * test.direction()));
+            }
+            elapsed -= duration + self.rest;
+            if elapsed < Time::default() {
+                return None;
+            }
+        }
+        None
+    }
+
+    fn total(&self) -> Time {
+        SysIdTest::ALL
+            .iter()
+            .map(|&test| self.duration(test) + self.rest)
+            .fold(Time::default(), |total, time| total + time)
+    }
+}
+
+/// Runs every test on a mechanism in turn, one step per tick, recording as it goes
+///
+/// Times are measured from the first tick, so consume this only while characterizing, such as in
+/// test mode. The mechanism is left at zero volts once finished.
+pub struct SysIdRoutine<M: Characterize, C> {
+    mechanism: Arc<Mutex<M>>,
+    clock: C,
+    config: SysIdConfig,
+    data: SysIdData<M::Sample>,
+    start: Option<Time>,
+}
+
+impl<M: Characterize, C: Clock> SysIdRoutine<M, C> {
+    pub fn new(
+        mechanism: Arc<Mutex<M>>,
+        clock: C,
+        config: SysIdConfig,
+        data: SysIdData<M::Sample>,
+    ) -> Self {
+        SysIdRoutine {
+            mechanism,
+            clock,
+            config,
+            data,
+            start: None,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.start, Some(start) if self.clock.now() - start >= self.config.total())
+    }
+
+    pub fn data(&self) -> &SysIdData<M::Sample> {
+        &self.data
+    }
+
+    /// Forgets recorded data so the tests run again from the next tick
+    pub fn reset(&mut self) {
+        self.data.tests.clear();
+        self.start = None;
+    }
+}
+
+impl<M: Characterize, C: Clock> Consumer for SysIdRoutine<M, C> {
+    type Msg = ();
+
+    fn output(&mut self, _: ()) {
+        let now = self.clock.now();
+        let start = *self.start.get_or_insert(now);
+        let mut mechanism = self.mechanism.lock().unwrap();
+        match self.config.schedule(now - start) {
+            Some((test, voltage)) => {
+                mechanism.apply(voltage);
+                self.data.record(test, mechanism.sample(now));
+            }
+            None => mechanism.apply(ElectricPotential::default()),
+        }
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        self.mechanism.lock().unwrap().resources()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetanus_core::node::{BaseNode, Node};
+    use tetanus_core::testing::{assert_close, tick_every, FakeClock};
+
+    const KS: f64 = 0.3;
+    const KV: f64 = 2.0;
+    const KA: f64 = 0.4;
+    const KG: f64 = 0.8;
+
+    /// Mechanism following `V = kS·sgn(v) + kV·v + kA·a (+ kG)` exactly
+    struct Plant {
+        gravity: f64,
+        voltage: f64,
+        position: f64,
+        velocity: f64,
+        last: Option<Time>,
+    }
+
+    impl Plant {
+        fn new(gravity: f64) -> Self {
+            Plant {
+                gravity,
+                voltage: 0.0,
+                position: 0.0,
+                velocity: 0.0,
+                last: None,
+            }
+        }
+    }
+
+    impl Characterize for Plant {
+        type Sample = MechanismSample;
+
+        fn apply(&mut self, voltage: ElectricPotential) {
+            self.voltage = voltage.get::<volt>();
+        }
+
+        fn sample(&mut self, timestamp: Time) -> MechanismSample {
+            let dt = self
+                .last
+                .map_or(0.0, |last| (timestamp - last).get::<second>());
+            self.last = Some(timestamp);
+            // Integrate finely so the recorded samples look continuous
+            for _ in 0..20 {
+                let mut driving = self.voltage - self.gravity;
+                if self.velocity != 0.0 {
+                    driving -= KS * self.velocity.signum();
+                } else if driving.abs() <= KS {
+                    continue;
+                } else {
+                    driving -= KS * driving.signum();
+                }
+                let acceleration = (driving - KV * self.velocity) / KA;
+                let velocity = self.velocity + acceleration * dt / 20.0;
+                // Friction stops the mechanism rather than reversing it
+                self.velocity = if velocity * self.velocity < 0.0 {
+                    0.0
+                } else {
+                    velocity
+                };
+                self.position += self.velocity * dt / 20.0;
+            }
+            MechanismSample {
+                timestamp,
+                voltage: ElectricPotential::new::<volt>(self.voltage),
+                position: self.position,
+                velocity: self.velocity,
+            }
+        }
+
+        fn resources(&self) -> Vec<Resource> {
+            vec![Resource::new("plant")]
+        }
+    }
+
+    fn run(mechanism: Mechanism, gravity: f64) -> SysIdRoutine<Plant, FakeClock> {
+        let clock = FakeClock::new();
+        let mut routine = SysIdRoutine::new(
+            Arc::new(Mutex::new(Plant::new(gravity))),
+            clock.clone(),
+            SysIdConfig::default(),
+            SysIdData::new(mechanism, "Meters", 0.1),
+        );
+        let period = Time::new::<second>(0.005);
+        while !routine.is_finished() {
+            routine.output(());
+            clock.advance(period);
+        }
+        routine
+    }
+
+    #[test]
+    fn runs_ramps_and_steps_in_order() {
+        let config = SysIdConfig::default();
+        let at = |t: f64| config.schedule(Time::new::<second>(t));
+
+        let (test, voltage) = at(2.0).unwrap();
+        assert_eq!(test, SysIdTest::QuasistaticForward);
+        assert_close(voltage.get::<volt>(), 2.0, 1e-9);
+        assert_eq!(at(8.0), None);
+        let (test, voltage) = at(12.0).unwrap();
+        assert_eq!(test, SysIdTest::QuasistaticReverse);
+        assert_close(voltage.get::<volt>(), -2.0, 1e-9);
+        let (test, voltage) = at(20.5).unwrap();
+        assert_eq!(test, SysIdTest::DynamicForward);
+        assert_close(voltage.get::<volt>(), 7.0, 1e-9);
+        let (test, voltage) = at(25.5).unwrap();
+        assert_eq!(test, SysIdTest::DynamicReverse);
+        assert_close(voltage.get::<volt>(), -7.0, 1e-9);
+        assert_eq!(at(28.0), None);
+        assert_close(config.total().get::<second>(), 30.0, 1e-9);
+    }
+
+    #[test]
+    fn fits_simple_and_elevator_gains() {
+        let simple = run(Mechanism::Simple, 0.0).data().fit().unwrap();
+        assert_close(simple.ks.get::<volt>(), KS, 0.02);
+        assert_close(simple.kv, KV, 0.02);
+        assert_close(simple.ka, KA, 0.02);
+        assert_close(simple.kg.get::<volt>(), 0.0, 1e-12);
+        assert!(simple.r_squared > 0.99);
+
+        let elevator = run(Mechanism::Elevator, KG).data().fit().unwrap();
+        assert_close(elevator.ks.get::<volt>(), KS, 0.02);
+        assert_close(elevator.kg.get::<volt>(), KG, 0.02);
+        assert_close(elevator.kv, KV, 0.02);
+        assert_close(elevator.ka, KA, 0.02);
+    }
+
+    #[test]
+    fn exports_sysid_json() {
+        let routine = run(Mechanism::Simple, 0.0);
+        let json: Value = serde_json::from_str(&routine.data().to_json().unwrap()).unwrap();
+
+        assert_eq!(json["test"], "Simple");
+        assert_eq!(json["units"], "Meters");
+        assert_eq!(json["unitsPerRotation"], 0.1);
+        assert_eq!(json["sysid"], true);
+        for &test in SysIdTest::ALL.iter() {
+            let rows = json[test.key()].as_array().unwrap();
+            assert_eq!(rows.len(), routine.data().samples(test).len());
+            assert_eq!(rows[0].as_array().unwrap().len(), 4);
+        }
+        let slow = json["slow-forward"].as_array().unwrap();
+        assert!(slow[100][1].as_f64().unwrap() > 0.0);
+        assert!(json["fast-backward"][10][3].as_f64().unwrap() < 0.0);
+
+        let empty: SysIdData<MechanismSample> = SysIdData::new(Mechanism::Simple, "Meters", 1.0);
+        assert!(empty.fit().is_err());
+    }
+
+    #[test]
+    fn drives_from_a_ticker_and_claims_the_mechanism() {
+        let clock = FakeClock::new();
+        let plant = Arc::new(Mutex::new(Plant::new(0.0)));
+        let routine = Arc::new(Mutex::new(SysIdRoutine::new(
+            plant.clone(),
+            clock.clone(),
+            SysIdConfig::default(),
+            SysIdData::new(Mechanism::Simple, "Meters", 1.0),
+        )));
+        assert_eq!(
+            Consumer::resources(&*routine.lock().unwrap()),
+            vec![Resource::new("plant")]
+        );
+
+        let mut ticker = BaseNode::new();
+        ticker.consume(routine.clone());
+        tick_every(&ticker, &clock, Time::new::<second>(0.02), 100);
+
+        let routine = routine.lock().unwrap();
+        assert!(!routine.is_finished());
+        assert_eq!(
+            routine.data().samples(SysIdTest::QuasistaticForward).len(),
+            100
+        );
+        assert!(plant.lock().unwrap().velocity > 0.0);
+    }
+}
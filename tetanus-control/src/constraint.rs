@@ -0,0 +1,237 @@
+//! Limits on how fast a trajectory may go at each point
+
+use uom::si::acceleration::meter_per_second_squared;
+use uom::si::angular_velocity::radian_per_second;
+use uom::si::curvature::radian_per_meter;
+use uom::si::f64::*;
+use uom::si::velocity::meter_per_second;
+
+use crate::geometry::{Pose2d, Translation2d};
+use crate::kinematics::{ChassisSpeeds, DifferentialDriveKinematics, SwerveDriveKinematics};
+
+pub trait TrajectoryConstraint: Send + Sync {
+    /// Fastest the robot may go at `pose`, given the speed it would otherwise reach
+    fn max_velocity(&self, pose: Pose2d, curvature: Curvature, velocity: Velocity) -> Velocity;
+
+    /// Smallest and largest allowed acceleration at `pose` while moving at `velocity`
+    fn acceleration_limits(
+        &self,
+        _pose: Pose2d,
+        _curvature: Curvature,
+        _velocity: Velocity,
+    ) -> (Acceleration, Acceleration) {
+        unlimited_acceleration()
+    }
+}
+
+fn unlimited_acceleration() -> (Acceleration, Acceleration) {
+    (
+        Acceleration::new::<meter_per_second_squared>(f64::NEG_INFINITY),
+        Acceleration::new::<meter_per_second_squared>(f64::INFINITY),
+    )
+}
+
+/// Caps speed everywhere, mostly useful inside a [`RegionConstraint`]
+#[derive(Clone, Copy, Debug)]
+pub struct MaxVelocityConstraint {
+    max: Velocity,
+}
+
+impl MaxVelocityConstraint {
+    pub fn new(max: Velocity) -> Self {
+        MaxVelocityConstraint { max }
+    }
+}
+
+impl TrajectoryConstraint for MaxVelocityConstraint {
+    fn max_velocity(&self, _pose: Pose2d, _curvature: Curvature, _velocity: Velocity) -> Velocity {
+        self.max
+    }
+}
+
+/// Slows down through turns so sideways acceleration `v²κ` stays under `max`
+#[derive(Clone, Copy, Debug)]
+pub struct CentripetalAccelerationConstraint {
+    max: Acceleration,
+}
+
+impl CentripetalAccelerationConstraint {
+    pub fn new(max: Acceleration) -> Self {
+        CentripetalAccelerationConstraint { max }
+    }
+}
+
+impl TrajectoryConstraint for CentripetalAccelerationConstraint {
+    fn max_velocity(&self, _pose: Pose2d, curvature: Curvature, _velocity: Velocity) -> Velocity {
+        Velocity::new::<meter_per_second>(
+            (self.max.get::<meter_per_second_squared>()
+                / curvature.get::<radian_per_meter>().abs())
+            .sqrt(),
+        )
+    }
+}
+
+/// Keeps both sides of a differential drive under `max_speed`
+#[derive(Clone, Copy, Debug)]
+pub struct DifferentialDriveKinematicsConstraint {
+    kinematics: DifferentialDriveKinematics,
+    max_speed: Velocity,
+}
+
+impl DifferentialDriveKinematicsConstraint {
+    pub fn new(kinematics: DifferentialDriveKinematics, max_speed: Velocity) -> Self {
+        DifferentialDriveKinematicsConstraint {
+            kinematics,
+            max_speed,
+        }
+    }
+}
+
+impl TrajectoryConstraint for DifferentialDriveKinematicsConstraint {
+    fn max_velocity(&self, _pose: Pose2d, curvature: Curvature, velocity: Velocity) -> Velocity {
+        let mut wheels = self
+            .kinematics
+            .to_wheel_speeds(path_speeds(velocity, curvature, 1.0, 0.0));
+        wheels.desaturate(self.max_speed);
+        self.kinematics.to_chassis_speeds(wheels).vx
+    }
+}
+
+/// Keeps every swerve module under `max_speed`
+#[derive(Clone, Debug)]
+pub struct SwerveDriveKinematicsConstraint {
+    kinematics: SwerveDriveKinematics,
+    max_speed: Velocity,
+}
+
+impl SwerveDriveKinematicsConstraint {
+    pub fn new(kinematics: SwerveDriveKinematics, max_speed: Velocity) -> Self {
+        SwerveDriveKinematicsConstraint {
+            kinematics,
+            max_speed,
+        }
+    }
+}
+
+impl TrajectoryConstraint for SwerveDriveKinematicsConstraint {
+    fn max_velocity(&self, pose: Pose2d, curvature: Curvature, velocity: Velocity) -> Velocity {
+        let (cos, sin) = (pose.rotation.cos(), pose.rotation.sin());
+        let mut states = self
+            .kinematics
+            .to_module_states(path_speeds(velocity, curvature, cos, sin));
+        SwerveDriveKinematics::desaturate(&mut states, self.max_speed);
+        let chassis = self.kinematics.to_chassis_speeds(&states);
+        Velocity::new::<meter_per_second>(
+            chassis
+                .vx
+                .get::<meter_per_second>()
+                .hypot(chassis.vy.get::<meter_per_second>()),
+        )
+    }
+}
+
+/// Chassis speeds for following a path at `velocity` in the direction `(cos, sin)`
+fn path_speeds(velocity: Velocity, curvature: Curvature, cos: f64, sin: f64) -> ChassisSpeeds {
+    let v = velocity.get::<meter_per_second>();
+    ChassisSpeeds::new(
+        velocity * cos,
+        velocity * sin,
+        AngularVelocity::new::<radian_per_second>(v * curvature.get::<radian_per_meter>()),
+    )
+}
+
+/// Applies `constraint` only inside an axis-aligned rectangle on the field
+#[derive(Clone, Debug)]
+pub struct RegionConstraint<C> {
+    bottom_left: Translation2d,
+    top_right: Translation2d,
+    constraint: C,
+}
+
+impl<C: TrajectoryConstraint> RegionConstraint<C> {
+    /// Corners can be given in any order
+    pub fn new(corner: Translation2d, opposite: Translation2d, constraint: C) -> Self {
+        RegionConstraint {
+            bottom_left: Translation2d::new(corner.x.min(opposite.x), corner.y.min(opposite.y)),
+            top_right: Translation2d::new(corner.x.max(opposite.x), corner.y.max(opposite.y)),
+            constraint,
+        }
+    }
+
+    pub fn contains(&self, point: Translation2d) -> bool {
+        (self.bottom_left.x..=self.top_right.x).contains(&point.x)
+            && (self.bottom_left.y..=self.top_right.y).contains(&point.y)
+    }
+}
+
+impl<C: TrajectoryConstraint> TrajectoryConstraint for RegionConstraint<C> {
+    fn max_velocity(&self, pose: Pose2d, curvature: Curvature, velocity: Velocity) -> Velocity {
+        if self.contains(pose.translation) {
+            self.constraint.max_velocity(pose, curvature, velocity)
+        } else {
+            Velocity::new::<meter_per_second>(f64::INFINITY)
+        }
+    }
+
+    fn acceleration_limits(
+        &self,
+        pose: Pose2d,
+        curvature: Curvature,
+        velocity: Velocity,
+    ) -> (Acceleration, Acceleration) {
+        if self.contains(pose.translation) {
+            self.constraint
+                .acceleration_limits(pose, curvature, velocity)
+        } else {
+            unlimited_acceleration()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mps;
+    use tetanus_core::testing::assert_close;
+    use uom::si::length::meter;
+
+    fn curvature(k: f64) -> Curvature {
+        Curvature::new::<radian_per_meter>(k)
+    }
+
+    #[test]
+    fn turning_limits_differential_speed() {
+        let kinematics = DifferentialDriveKinematics::new(Length::new::<meter>(0.5));
+        let constraint = DifferentialDriveKinematicsConstraint::new(kinematics, mps(3.0));
+        let pose = Pose2d::default();
+
+        let straight = constraint.max_velocity(pose, curvature(0.0), mps(4.0));
+        let turning = constraint.max_velocity(pose, curvature(2.0), mps(4.0));
+
+        assert_close(straight.get::<meter_per_second>(), 3.0, 1e-9);
+        assert_close(turning.get::<meter_per_second>(), 2.0, 1e-9);
+
+        let max = Acceleration::new::<meter_per_second_squared>(2.0);
+        let centripetal = CentripetalAccelerationConstraint::new(max);
+        let limit = centripetal.max_velocity(pose, curvature(0.5), mps(4.0));
+        assert_close(limit.get::<meter_per_second>(), 2.0, 1e-9);
+    }
+
+    #[test]
+    fn region_only_applies_inside() {
+        let point =
+            |x: f64, y: f64| Translation2d::new(Length::new::<meter>(x), Length::new::<meter>(y));
+        let region = RegionConstraint::new(
+            point(2.0, 2.0),
+            point(1.0, 0.0),
+            MaxVelocityConstraint::new(mps(0.5)),
+        );
+        let at = |x: f64, y: f64| Pose2d::new(point(x, y), Default::default());
+
+        let inside = region.max_velocity(at(1.5, 1.0), curvature(0.0), mps(3.0));
+        let outside = region.max_velocity(at(3.0, 1.0), curvature(0.0), mps(3.0));
+
+        assert_close(inside.get::<meter_per_second>(), 0.5, 1e-9);
+        assert!(outside.get::<meter_per_second>().is_infinite());
+    }
+}
@@ -0,0 +1,342 @@
+//! Fusing wheel odometry with delayed vision measurements of the robot's pose
+//!
+//! Feed the estimator timestamped odometry poses as a [`Processor`] and vision poses, stamped
+//! with when the camera captured them, as a [`Consumer`]. Both sides can share one
+//! `Arc<Mutex<PoseEstimator>>` in the graph.
+
+use std::collections::VecDeque;
+
+use tetanus_core::consumer::Consumer;
+use tetanus_core::processor::Processor;
+use tetanus_core::rate::{Interpolate, Stamped};
+use uom::si::angle::radian;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::time::second;
+
+use crate::geometry::{Pose2d, Transform2d, Twist2d};
+
+/// How far off a pose may be along each axis, as one standard deviation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoseStdDevs {
+    pub x: Length,
+    pub y: Length,
+    pub heading: Angle,
+}
+
+impl PoseStdDevs {
+    pub fn new(x: Length, y: Length, heading: Angle) -> Self {
+        PoseStdDevs { x, y, heading }
+    }
+
+    fn variances(&self) -> [f64; 3] {
+        [
+            self.x.get::<meter>().powi(2),
+            self.y.get::<meter>().powi(2),
+            self.heading.get::<radian>().powi(2),
+        ]
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoseEstimatorConfig {
+    /// How much odometry is trusted
+    pub odometry_std_devs: PoseStdDevs,
+    /// How much vision is trusted, unless a measurement gives its own
+    pub vision_std_devs: PoseStdDevs,
+    /// Vision poses further than this from the estimate at the time are thrown out
+    pub max_translation_error: Length,
+    pub max_rotation_error: Angle,
+    /// How far back odometry is kept, which limits how late a vision pose may arrive
+    pub history: Time,
+}
+
+impl Default for PoseEstimatorConfig {
+    fn default() -> Self {
+        PoseEstimatorConfig {
+            odometry_std_devs: PoseStdDevs::new(
+                Length::new::<meter>(0.1),
+                Length::new::<meter>(0.1),
+                Angle::new::<radian>(0.1),
+            ),
+            vision_std_devs: PoseStdDevs::new(
+                Length::new::<meter>(0.9),
+                Length::new::<meter>(0.9),
+                Angle::new::<radian>(0.9),
+            ),
+            max_translation_error: Length::new::<meter>(1.0),
+            max_rotation_error: Angle::new::<radian>(0.5),
+            history: Time::new::<second>(1.5),
+        }
+    }
+}
+
+/// Robot pose from odometry, corrected by vision measurements from the past
+///
+/// Vision corrects the estimate at the time its image was taken, and the odometry since then is
+/// replayed on top of the correction.
+#[derive(Clone, Debug)]
+pub struct PoseEstimator {
+    config: PoseEstimatorConfig,
+    /// Odometry poses, oldest first
+    history: VecDeque<Stamped<Pose2d>>,
+    /// Where odometry's origin is on the field, once corrected by vision
+    origin: Pose2d,
+}
+
+impl PoseEstimator {
+    pub fn new(config: PoseEstimatorConfig) -> Self {
+        PoseEstimator {
+            config,
+            history: VecDeque::new(),
+            origin: Pose2d::default(),
+        }
+    }
+
+    pub fn pose(&self) -> Pose2d {
+        self.history
+            .back()
+            .map(|odometry| self.correct(odometry.value))
+            .unwrap_or(self.origin)
+    }
+
+    /// Estimate at `time`, between the odometry samples either side of it
+    pub fn pose_at(&self, time: Time) -> Option<Pose2d> {
+        self.odometry_at(time)
+            .map(|odometry| self.correct(odometry))
+    }
+
+    /// Makes the latest odometry pose read as `pose` on the field
+    pub fn reset(&mut self, pose: Pose2d) {
+        let latest = self.history.back().copied();
+        self.history.clear();
+        match latest {
+            Some(latest) => {
+                self.origin = pose.transform_by(offset(latest.value).inverse());
+                self.history.push_back(latest);
+            }
+            None => self.origin = pose,
+        }
+    }
+
+    /// Records the odometry pose at `odometry.timestamp`, returning the estimate
+    pub fn update(&mut self, odometry: Stamped<Pose2d>) -> Pose2d {
+        if matches!(self.history.back(), Some(latest) if odometry.timestamp < latest.timestamp) {
+            // The clock went backwards, so the history can't be searched by time any more
+            self.history.clear();
+        }
+        self.history.push_back(odometry);
+
+        let oldest = odometry.timestamp - self.config.history;
+        while self.history.len() > 1 && self.history[1].timestamp <= oldest {
+            self.history.pop_front();
+        }
+        self.correct(odometry.value)
+    }
+
+    /// Corrects towards `vision` with the configured standard deviations
+    ///
+    /// Returns whether the measurement was used. Measurements older than the odometry history or
+    /// too far from the estimate are ignored.
+    pub fn add_vision_measurement(&mut self, vision: Stamped<Pose2d>) -> bool {
+        self.add_vision_measurement_with_std_devs(vision, self.config.vision_std_devs)
+    }
+
+    /// Like [`add_vision_measurement`](Self::add_vision_measurement), for cameras whose accuracy
+    /// changes, such as with distance to the target
+    pub fn add_vision_measurement_with_std_devs(
+        &mut self,
+        vision: Stamped<Pose2d>,
+        std_devs: PoseStdDevs,
+    ) -> bool {
+        let odometry = match self.odometry_at(vision.timestamp) {
+            Some(odometry) => odometry,
+            None => return false,
+        };
+        let estimate = self.correct(odometry);
+
+        let error = vision.value.relative_to(estimate);
+        if error.translation.norm() > self.config.max_translation_error
+            || error.rotation.angle().abs() > self.config.max_rotation_error
+        {
+            return false;
+        }
+
+        let twist = estimate.log(vision.value);
+        let q = self.config.odometry_std_devs.variances();
+        let r = std_devs.variances();
+        // Steady-state Kalman gain for each axis, treating them as independent
+        let gain = |i: usize| {
+            if q[i] == 0.0 {
+                0.0
+            } else {
+                q[i] / (q[i] + (q[i] * r[i]).sqrt())
+            }
+        };
+        let corrected = estimate.exp(Twist2d::new(
+            twist.dx * gain(0),
+            twist.dy * gain(1),
+            twist.dtheta * gain(2),
+        ));
+
+        self.origin = corrected.transform_by(offset(odometry).inverse());
+        true
+    }
+
+    fn correct(&self, odometry: Pose2d) -> Pose2d {
+        self.origin.transform_by(offset(odometry))
+    }
+
+    fn odometry_at(&self, time: Time) -> Option<Pose2d> {
+        let (oldest, latest) = (self.history.front()?, self.history.back()?);
+        if time < oldest.timestamp {
+            return None;
+        }
+        if time >= latest.timestamp {
+            return Some(latest.value);
+        }
+
+        let after = self
+            .history
+            .partition_point(|sample| sample.timestamp <= time);
+        let (before, after) = (self.history[after - 1], self.history[after]);
+        let t = ((time - before.timestamp) / (after.timestamp - before.timestamp)).value;
+        Some(before.value.lerp(after.value, t))
+    }
+}
+
+/// Transform from odometry's origin to `odometry`
+fn offset(odometry: Pose2d) -> Transform2d {
+    Transform2d::new(odometry.translation, odometry.rotation)
+}
+
+impl Processor for PoseEstimator {
+    /// Odometry pose and when it was measured
+    type In = Stamped<Pose2d>;
+    type Out = Pose2d;
+
+    fn process(&mut self, odometry: Self::In) -> Self::Out {
+        self.update(odometry)
+    }
+}
+
+impl Consumer for PoseEstimator {
+    /// Vision pose and when its image was captured
+    type Msg = Stamped<Pose2d>;
+
+    fn output(&mut self, vision: Self::Msg) {
+        self.add_vision_measurement(vision);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rotation2d;
+    use crate::testing::ms;
+    use crate::testing::{assert_pose, pose};
+    use std::sync::{Arc, Mutex};
+    use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+    use tetanus_core::testing::Probe;
+
+    /// Trusts vision completely, so corrections are easy to check
+    fn trusting() -> PoseEstimator {
+        let config = PoseEstimatorConfig {
+            vision_std_devs: PoseStdDevs::new(
+                Length::default(),
+                Length::default(),
+                Angle::default(),
+            ),
+            ..PoseEstimatorConfig::default()
+        };
+        PoseEstimator::new(config)
+    }
+
+    /// Drives forward along x at 1 m/s, sampling odometry every 20 ms
+    fn drive(estimator: &mut PoseEstimator, from_ms: usize, to_ms: usize, drift: f64) {
+        for t in (from_ms..=to_ms).step_by(20) {
+            let x = t as f64 / 1000.0;
+            estimator.update(Stamped::new(pose(x, drift * x, 0.0), ms(t as f64)));
+        }
+    }
+
+    #[test]
+    fn latency_is_compensated_by_replaying_odometry() {
+        let mut estimator = trusting();
+        drive(&mut estimator, 0, 1000, 0.1);
+
+        // Taken 100 ms ago, when odometry read (0.9, 0.09) but the robot was really on y = 0
+        let accepted =
+            estimator.add_vision_measurement(Stamped::new(pose(0.9, 0.0, 0.0), ms(900.0)));
+
+        assert!(accepted);
+        assert_pose(
+            estimator.pose_at(ms(900.0)).unwrap(),
+            pose(0.9, 0.0, 0.0),
+            1e-9,
+        );
+        // The 10 cm travelled since then is kept, drift and all
+        assert_pose(estimator.pose(), pose(1.0, 0.01, 0.0), 1e-9);
+    }
+
+    #[test]
+    fn vision_is_weighted_against_odometry() {
+        let config = PoseEstimatorConfig {
+            odometry_std_devs: PoseStdDevs::new(
+                Length::new::<meter>(0.1),
+                Length::new::<meter>(0.1),
+                Angle::new::<radian>(0.1),
+            ),
+            vision_std_devs: PoseStdDevs::new(
+                Length::new::<meter>(0.1),
+                Length::new::<meter>(0.1),
+                Angle::new::<radian>(0.1),
+            ),
+            ..PoseEstimatorConfig::default()
+        };
+        let mut estimator = PoseEstimator::new(config);
+        estimator.update(Stamped::new(pose(0.0, 0.0, 0.0), ms(0.0)));
+
+        estimator.add_vision_measurement(Stamped::new(pose(0.0, 0.4, 0.0), ms(0.0)));
+
+        // Equal trust meets in the middle
+        assert_pose(estimator.pose(), pose(0.0, 0.2, 0.0), 1e-9);
+    }
+
+    #[test]
+    fn outliers_and_stale_measurements_are_rejected() {
+        let mut estimator = trusting();
+        drive(&mut estimator, 0, 3000, 0.0);
+
+        let far = Stamped::new(pose(2.0, 3.0, 0.0), ms(2900.0));
+        let turned = Stamped::new(pose(2.9, 0.0, 90.0), ms(2900.0));
+        let stale = Stamped::new(pose(1.0, 0.0, 0.0), ms(1000.0));
+        assert!(!estimator.add_vision_measurement(far));
+        assert!(!estimator.add_vision_measurement(turned));
+        assert!(!estimator.add_vision_measurement(stale));
+        assert_pose(estimator.pose(), pose(3.0, 0.0, 0.0), 1e-9);
+
+        estimator.reset(pose(5.0, 5.0, 90.0));
+        assert_pose(estimator.pose(), pose(5.0, 5.0, 90.0), 1e-9);
+        estimator.update(Stamped::new(pose(3.5, 0.0, 0.0), ms(3500.0)));
+        assert_pose(estimator.pose(), pose(5.0, 5.5, 90.0), 1e-9);
+    }
+
+    #[test]
+    fn vision_and_odometry_arrive_through_the_graph() {
+        let estimator = Arc::new(Mutex::new(trusting()));
+        let mut odometry = BaseNode::new();
+        let mut vision = BaseNode::new();
+        let probe = Probe::new();
+        odometry.process(estimator.clone()).chain(probe.clone());
+        vision.consume(estimator.clone());
+
+        odometry.send(Stamped::new(pose(1.0, 0.0, 0.0), ms(0.0)));
+        vision.send(Stamped::new(pose(1.0, 0.5, 0.0), ms(0.0)));
+        odometry.send(Stamped::new(pose(1.5, 0.0, 0.0), ms(20.0)));
+
+        assert_pose(probe.last().unwrap(), pose(1.5, 0.5, 0.0), 1e-9);
+        let heading = estimator.lock().unwrap().pose().rotation;
+        assert_eq!(heading, Rotation2d::default());
+    }
+}
@@ -0,0 +1,288 @@
+//! Voltage needed to follow a motion, from characterized motor gains
+//!
+//! `kv` and `ka` are in volts per base SI unit of velocity and acceleration, e.g. volts per
+//! meter per second for [`LinearFeedforward`].
+
+use std::marker::PhantomData;
+
+use uom::si::angle::radian;
+use uom::si::angular_acceleration::radian_per_second_squared;
+use uom::si::angular_velocity::radian_per_second;
+use uom::si::electric_potential::volt;
+use uom::si::f64::*;
+
+use crate::units::SiValue;
+
+/// Like `f64::signum`, but 0 for 0 so static friction isn't applied at rest
+fn sign(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+/// `kS·sgn(v) + kV·v + kA·a` for a motor with no gravity load, such as a flywheel or drivetrain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SimpleMotorFeedforward<V, A> {
+    pub ks: ElectricPotential,
+    pub kv: f64,
+    pub ka: f64,
+    units: PhantomData<(V, A)>,
+}
+
+pub type LinearFeedforward = SimpleMotorFeedforward<Velocity, Acceleration>;
+pub type AngularFeedforward = SimpleMotorFeedforward<AngularVelocity, AngularAcceleration>;
+
+impl<V: SiValue, A: SiValue> SimpleMotorFeedforward<V, A> {
+    pub fn new(ks: ElectricPotential, kv: f64, ka: f64) -> Self {
+        SimpleMotorFeedforward {
+            ks,
+            kv,
+            ka,
+            units: PhantomData,
+        }
+    }
+
+    pub fn calculate(&self, velocity: V, acceleration: A) -> ElectricPotential {
+        let (v, a) = (velocity.si(), acceleration.si());
+        volts(self.ks.get::<volt>() * sign(v) + self.kv * v + self.ka * a)
+    }
+
+    pub fn max_achievable_velocity(&self, max_voltage: ElectricPotential, acceleration: A) -> V {
+        let available = max_voltage.get::<volt>() - self.ks.get::<volt>();
+        V::from_si((available - self.ka * acceleration.si()) / self.kv)
+    }
+
+    pub fn min_achievable_velocity(&self, max_voltage: ElectricPotential, acceleration: A) -> V {
+        let available = -max_voltage.get::<volt>() + self.ks.get::<volt>();
+        V::from_si((available - self.ka * acceleration.si()) / self.kv)
+    }
+
+    pub fn max_achievable_acceleration(&self, max_voltage: ElectricPotential, velocity: V) -> A {
+        let v = velocity.si();
+        let available = max_voltage.get::<volt>() - self.ks.get::<volt>() * sign(v);
+        A::from_si((available - self.kv * v) / self.ka)
+    }
+
+    pub fn min_achievable_acceleration(&self, max_voltage: ElectricPotential, velocity: V) -> A {
+        self.max_achievable_acceleration(-max_voltage, velocity)
+    }
+}
+
+/// `kS·sgn(ω) + kG·cos(θ) + kV·ω + kA·α` for an arm, where `θ` is measured from horizontal
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ArmFeedforward {
+    pub ks: ElectricPotential,
+    pub kg: ElectricPotential,
+    pub kv: f64,
+    pub ka: f64,
+}
+
+impl ArmFeedforward {
+    pub fn new(ks: ElectricPotential, kg: ElectricPotential, kv: f64, ka: f64) -> Self {
+        ArmFeedforward { ks, kg, kv, ka }
+    }
+
+    pub fn calculate(
+        &self,
+        position: Angle,
+        velocity: AngularVelocity,
+        acceleration: AngularAcceleration,
+    ) -> ElectricPotential {
+        let v = velocity.get::<radian_per_second>();
+        let a = acceleration.get::<radian_per_second_squared>();
+        volts(self.ks.get::<volt>() * sign(v) + self.gravity(position) + self.kv * v + self.ka * a)
+    }
+
+    pub fn max_achievable_velocity(
+        &self,
+        max_voltage: ElectricPotential,
+        position: Angle,
+        acceleration: AngularAcceleration,
+    ) -> AngularVelocity {
+        let available = max_voltage.get::<volt>() - self.ks.get::<volt>() - self.gravity(position);
+        AngularVelocity::new::<radian_per_second>(
+            (available - self.ka * acceleration.get::<radian_per_second_squared>()) / self.kv,
+        )
+    }
+
+    pub fn min_achievable_velocity(
+        &self,
+        max_voltage: ElectricPotential,
+        position: Angle,
+        acceleration: AngularAcceleration,
+    ) -> AngularVelocity {
+        let available = -max_voltage.get::<volt>() + self.ks.get::<volt>() - self.gravity(position);
+        AngularVelocity::new::<radian_per_second>(
+            (available - self.ka * acceleration.get::<radian_per_second_squared>()) / self.kv,
+        )
+    }
+
+    pub fn max_achievable_acceleration(
+        &self,
+        max_voltage: ElectricPotential,
+        position: Angle,
+        velocity: AngularVelocity,
+    ) -> AngularAcceleration {
+        let v = velocity.get::<radian_per_second>();
+        let available =
+            max_voltage.get::<volt>() - self.ks.get::<volt>() * sign(v) - self.gravity(position);
+        AngularAcceleration::new::<radian_per_second_squared>((available - self.kv * v) / self.ka)
+    }
+
+    pub fn min_achievable_acceleration(
+        &self,
+        max_voltage: ElectricPotential,
+        position: Angle,
+        velocity: AngularVelocity,
+    ) -> AngularAcceleration {
+        self.max_achievable_acceleration(-max_voltage, position, velocity)
+    }
+
+    fn gravity(&self, position: Angle) -> f64 {
+        self.kg.get::<volt>() * position.get::<radian>().cos()
+    }
+}
+
+/// `kS·sgn(v) + kG + kV·v + kA·a` for an elevator, where `kG` holds up the carriage
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ElevatorFeedforward {
+    pub ks: ElectricPotential,
+    pub kg: ElectricPotential,
+    pub kv: f64,
+    pub ka: f64,
+}
+
+impl ElevatorFeedforward {
+    pub fn new(ks: ElectricPotential, kg: ElectricPotential, kv: f64, ka: f64) -> Self {
+        ElevatorFeedforward { ks, kg, kv, ka }
+    }
+
+    pub fn calculate(&self, velocity: Velocity, acceleration: Acceleration) -> ElectricPotential {
+        self.kg + self.motor().calculate(velocity, acceleration)
+    }
+
+    pub fn max_achievable_velocity(
+        &self,
+        max_voltage: ElectricPotential,
+        acceleration: Acceleration,
+    ) -> Velocity {
+        self.motor()
+            .max_achievable_velocity(max_voltage - self.kg, acceleration)
+    }
+
+    pub fn min_achievable_velocity(
+        &self,
+        max_voltage: ElectricPotential,
+        acceleration: Acceleration,
+    ) -> Velocity {
+        self.motor()
+            .min_achievable_velocity(max_voltage + self.kg, acceleration)
+    }
+
+    pub fn max_achievable_acceleration(
+        &self,
+        max_voltage: ElectricPotential,
+        velocity: Velocity,
+    ) -> Acceleration {
+        self.motor()
+            .max_achievable_acceleration(max_voltage - self.kg, velocity)
+    }
+
+    pub fn min_achievable_acceleration(
+        &self,
+        max_voltage: ElectricPotential,
+        velocity: Velocity,
+    ) -> Acceleration {
+        self.max_achievable_acceleration(-max_voltage, velocity)
+    }
+
+    fn motor(&self) -> LinearFeedforward {
+        SimpleMotorFeedforward::new(self.ks, self.kv, self.ka)
+    }
+}
+
+fn volts(value: f64) -> ElectricPotential {
+    ElectricPotential::new::<volt>(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mps;
+    use tetanus_core::testing::assert_close;
+    use uom::si::acceleration::meter_per_second_squared;
+    use uom::si::angle::degree;
+
+    fn simple() -> LinearFeedforward {
+        SimpleMotorFeedforward::new(volts(0.5), 2.0, 0.25)
+    }
+
+    fn mps2(a: f64) -> Acceleration {
+        Acceleration::new::<meter_per_second_squared>(a)
+    }
+
+    #[test]
+    fn simple_feedforward() {
+        let ff = simple();
+
+        assert_close(ff.calculate(mps(2.0), mps2(4.0)).get::<volt>(), 5.5, 1e-9);
+        assert_close(ff.calculate(mps(-2.0), mps2(0.0)).get::<volt>(), -4.5, 1e-9);
+        assert_close(ff.calculate(mps(0.0), mps2(0.0)).get::<volt>(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn achievable_limits_invert_calculate() {
+        let ff = simple();
+        let max = volts(12.0);
+
+        let v = ff.max_achievable_velocity(max, mps2(4.0));
+        assert_close(ff.calculate(v, mps2(4.0)).get::<volt>(), 12.0, 1e-9);
+
+        let v = ff.min_achievable_velocity(max, mps2(4.0));
+        assert_close(ff.calculate(v, mps2(4.0)).get::<volt>(), -12.0, 1e-9);
+
+        let a = ff.max_achievable_acceleration(max, mps(3.0));
+        assert_close(ff.calculate(mps(3.0), a).get::<volt>(), 12.0, 1e-9);
+
+        let a = ff.min_achievable_acceleration(max, mps(3.0));
+        assert_close(ff.calculate(mps(3.0), a).get::<volt>(), -12.0, 1e-9);
+    }
+
+    #[test]
+    fn arm_gravity_follows_cosine() {
+        let ff = ArmFeedforward::new(volts(0.1), volts(1.0), 1.0, 0.0);
+        let still = AngularVelocity::new::<radian_per_second>(0.0);
+        let alpha = AngularAcceleration::new::<radian_per_second_squared>(0.0);
+
+        let horizontal = ff.calculate(Angle::new::<degree>(0.0), still, alpha);
+        let angled = ff.calculate(Angle::new::<degree>(60.0), still, alpha);
+        let vertical = ff.calculate(Angle::new::<degree>(90.0), still, alpha);
+
+        assert_close(horizontal.get::<volt>(), 1.0, 1e-9);
+        assert_close(angled.get::<volt>(), 0.5, 1e-9);
+        assert_close(vertical.get::<volt>(), 0.0, 1e-9);
+
+        let position = Angle::new::<degree>(60.0);
+        let omega = ff.max_achievable_velocity(volts(12.0), position, alpha);
+        assert_close(
+            ff.calculate(position, omega, alpha).get::<volt>(),
+            12.0,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn elevator_adds_constant_gravity() {
+        let ff = ElevatorFeedforward::new(volts(0.5), volts(1.0), 2.0, 0.25);
+
+        assert_close(ff.calculate(mps(0.0), mps2(0.0)).get::<volt>(), 1.0, 1e-9);
+        assert_close(ff.calculate(mps(-1.0), mps2(0.0)).get::<volt>(), -1.5, 1e-9);
+
+        let v = ff.min_achievable_velocity(volts(12.0), mps2(0.0));
+        assert_close(ff.calculate(v, mps2(0.0)).get::<volt>(), -12.0, 1e-9);
+        let a = ff.max_achievable_acceleration(volts(12.0), mps(1.0));
+        assert_close(ff.calculate(mps(1.0), a).get::<volt>(), 12.0, 1e-9);
+    }
+}
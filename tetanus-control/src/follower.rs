@@ -0,0 +1,383 @@
+//! Controllers that turn the robot's pose and a trajectory into chassis speeds
+//!
+//! Each is a [`Processor`] so it can sit in the graph between odometry and the drivetrain.
+
+use std::f64::consts::PI;
+
+use tetanus_core::processor::Processor;
+use uom::si::angle::radian;
+use uom::si::angular_velocity::radian_per_second;
+use uom::si::curvature::radian_per_meter;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::velocity::meter_per_second;
+
+use crate::geometry::{Pose2d, Rotation2d};
+use crate::kinematics::ChassisSpeeds;
+use crate::pid::{PidController, ProfiledPidController};
+use crate::trajectory::{Trajectory, TrajectoryState};
+
+/// Nonlinear tracking controller for differential drives
+///
+/// `b` (> 0) is like a proportional gain and `zeta` (between 0 and 1) like a damping ratio; 2.0
+/// and 0.7 work well for most robots.
+#[derive(Clone, Copy, Debug)]
+pub struct RamseteController {
+    b: f64,
+    zeta: f64,
+}
+
+impl RamseteController {
+    pub fn new(b: f64, zeta: f64) -> Self {
+        RamseteController { b, zeta }
+    }
+
+    pub fn calculate(&self, current: Pose2d, desired: TrajectoryState) -> ChassisSpeeds {
+        let error = desired.pose.relative_to(current);
+        let (ex, ey) = (error.x().get::<meter>(), error.y().get::<meter>());
+        let etheta = error.rotation.radians();
+        let v = desired.velocity.get::<meter_per_second>();
+        let omega = v * desired.curvature.get::<radian_per_meter>();
+
+        let k = 2.0 * self.zeta * (omega * omega + self.b * v * v).sqrt();
+        ChassisSpeeds::new(
+            Velocity::new::<meter_per_second>(v * etheta.cos() + k * ex),
+            Velocity::default(),
+            AngularVelocity::new::<radian_per_second>(
+                omega + k * etheta + self.b * v * sinc(etheta) * ey,
+            ),
+        )
+    }
+}
+
+impl Default for RamseteController {
+    fn default() -> Self {
+        Self::new(2.0, 0.7)
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-9 {
+        1.0 - x * x / 6.0
+    } else {
+        x.sin() / x
+    }
+}
+
+impl Processor for RamseteController {
+    type In = (Pose2d, TrajectoryState);
+    type Out = ChassisSpeeds;
+
+    fn process(&mut self, (current, desired): Self::In) -> Self::Out {
+        self.calculate(current, desired)
+    }
+}
+
+/// Steers a differential drive along the arc to a point `lookahead` ahead on the trajectory
+///
+/// Simpler to tune than [`RamseteController`], but cuts corners on tight turns.
+#[derive(Clone, Debug)]
+pub struct PurePursuitController {
+    trajectory: Trajectory,
+    lookahead: Length,
+    /// Index of the closest state found so far, which only moves forward
+    progress: usize,
+}
+
+impl PurePursuitController {
+    pub fn new(trajectory: Trajectory, lookahead: Length) -> Self {
+        PurePursuitController {
+            trajectory,
+            lookahead,
+            progress: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.progress + 1 >= self.trajectory.states().len()
+    }
+
+    pub fn reset(&mut self) {
+        self.progress = 0;
+    }
+
+    pub fn calculate(&mut self, current: Pose2d) -> ChassisSpeeds {
+        let states = self.trajectory.states();
+        if states.is_empty() {
+            return ChassisSpeeds::default();
+        }
+        let lookahead = self.lookahead.get::<meter>();
+        let distance = |state: &TrajectoryState| {
+            current
+                .translation
+                .distance(state.pose.translation)
+                .get::<meter>()
+        };
+
+        let mut best = distance(&states[self.progress]);
+        for (i, state) in states.iter().enumerate().skip(self.progress + 1) {
+            let d = distance(state);
+            if d < best {
+                best = d;
+                self.progress = i;
+            } else if d > lookahead {
+                break;
+            }
+        }
+
+        let goal = states[self.progress..]
+            .iter()
+            .find(|state| distance(state) >= lookahead)
+            .unwrap_or_else(|| states.last().unwrap());
+        // Going as fast as whichever of the closest and goal states is faster gets the robot
+        // moving from rest at the start and still moving up to the stop at the end
+        let closest = states[self.progress].velocity;
+        let speed = if goal.velocity.abs() > closest.abs() {
+            goal.velocity
+        } else {
+            closest
+        };
+        let local = (goal.pose.translation - current.translation).rotate_by(-current.rotation);
+        let (x, y) = (local.x.get::<meter>(), local.y.get::<meter>());
+        let squared = x * x + y * y;
+        if squared < 1e-12 {
+            return ChassisSpeeds::default();
+        }
+
+        let curvature = 2.0 * y / squared;
+        ChassisSpeeds::new(
+            speed,
+            Velocity::default(),
+            AngularVelocity::new::<radian_per_second>(speed.get::<meter_per_second>() * curvature),
+        )
+    }
+}
+
+impl Processor for PurePursuitController {
+    type In = Pose2d;
+    type Out = ChassisSpeeds;
+
+    fn process(&mut self, current: Self::In) -> Self::Out {
+        self.calculate(current)
+    }
+}
+
+/// Trajectory follower for swerve drives, which can point anywhere while following the path
+///
+/// x and y are corrected by separate PID controllers on top of the trajectory's velocity, and
+/// the heading follows a profile to the desired heading.
+pub struct HolonomicDriveController {
+    x: PidController<Length, Velocity>,
+    y: PidController<Length, Velocity>,
+    theta: ProfiledPidController<Angle>,
+    tolerance: Pose2d,
+    error: Pose2d,
+}
+
+impl HolonomicDriveController {
+    pub fn new(
+        x: PidController<Length, Velocity>,
+        y: PidController<Length, Velocity>,
+        mut theta: ProfiledPidController<Angle>,
+    ) -> Self {
+        theta.enable_continuous_input(Angle::new::<radian>(-PI), Angle::new::<radian>(PI));
+        HolonomicDriveController {
+            x,
+            y,
+            theta,
+            tolerance: Pose2d::default(),
+            error: Pose2d::default(),
+        }
+    }
+
+    /// Largest x, y and heading error that counts as being on the trajectory
+    pub fn set_tolerance(&mut self, tolerance: Pose2d) {
+        self.tolerance = tolerance;
+    }
+
+    pub fn at_reference(&self) -> bool {
+        self.error.x().abs() <= self.tolerance.x().abs()
+            && self.error.y().abs() <= self.tolerance.y().abs()
+            && self.error.rotation.radians().abs() <= self.tolerance.rotation.radians().abs()
+    }
+
+    /// Restarts the heading profile from the robot's current heading
+    pub fn reset(&mut self, heading: Rotation2d) {
+        self.x.reset();
+        self.y.reset();
+        self.theta.reset(heading.angle());
+    }
+
+    /// `heading` is where the robot should face, independent of the direction of travel
+    pub fn calculate(
+        &mut self,
+        current: Pose2d,
+        desired: TrajectoryState,
+        heading: Rotation2d,
+    ) -> ChassisSpeeds {
+        let direction = desired.pose.rotation;
+        let vx =
+            desired.velocity * direction.cos() + self.x.calculate_to(current.x(), desired.pose.x());
+        let vy =
+            desired.velocity * direction.sin() + self.y.calculate_to(current.y(), desired.pose.y());
+        let omega = self
+            .theta
+            .calculate_to(current.rotation.angle(), heading.angle());
+
+        self.error = Pose2d::new(
+            desired.pose.translation - current.translation,
+            heading - current.rotation,
+        );
+        ChassisSpeeds::from_field_relative(vx, vy, omega, current.rotation)
+    }
+}
+
+impl Processor for HolonomicDriveController {
+    type In = (Pose2d, TrajectoryState, Rotation2d);
+    type Out = ChassisSpeeds;
+
+    fn process(&mut self, (current, desired, heading): Self::In) -> Self::Out {
+        self.calculate(current, desired, heading)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Twist2d;
+    use crate::profile::TrapezoidConstraints;
+    use crate::testing::{assert_position, pose};
+    use crate::trajectory::{generate_quintic, TrajectoryConfig};
+    use std::sync::{Arc, Mutex};
+    use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+    use tetanus_core::testing::{assert_close, Probe};
+    use uom::si::acceleration::meter_per_second_squared;
+    use uom::si::angle::degree;
+    use uom::si::angular_acceleration::radian_per_second_squared;
+    use uom::si::time::{millisecond, second};
+
+    const DT: f64 = 0.02;
+
+    fn s_curve() -> Trajectory {
+        let config = TrajectoryConfig::new(
+            Velocity::new::<meter_per_second>(2.0),
+            Acceleration::new::<meter_per_second_squared>(2.0),
+        );
+        generate_quintic(&[pose(0.0, 0.0, 0.0), pose(4.0, 2.0, 0.0)], &config).unwrap()
+    }
+
+    /// Moves the robot by robot-relative chassis speeds for one period
+    fn step(pose: Pose2d, speeds: ChassisSpeeds) -> Pose2d {
+        pose.exp(Twist2d::new(
+            speeds.vx * Time::new::<second>(DT),
+            speeds.vy * Time::new::<second>(DT),
+            Angle::new::<radian>(speeds.omega.get::<radian_per_second>() * DT),
+        ))
+    }
+
+    /// Time steps to run for, with a second afterwards to settle
+    fn ticks(trajectory: &Trajectory) -> usize {
+        (trajectory.total_time().get::<second>() / DT) as usize + 50
+    }
+
+    #[test]
+    fn ramsete_passes_through_reference_when_on_path() {
+        let trajectory = s_curve();
+        let desired = trajectory.sample(Time::new::<second>(1.0));
+
+        let speeds = RamseteController::default().calculate(desired.pose, desired);
+
+        assert_close(speeds.vx.value, desired.velocity.value, 1e-9);
+        assert_close(
+            speeds.omega.value,
+            desired.velocity.value * desired.curvature.value,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn ramsete_converges_from_offset_start() {
+        let trajectory = s_curve();
+        let controller = RamseteController::default();
+        let mut robot = pose(0.0, 0.3, 10.0);
+
+        for i in 0..ticks(&trajectory) {
+            let desired = trajectory.sample(Time::new::<second>(i as f64 * DT));
+            robot = step(robot, controller.calculate(robot, desired));
+        }
+
+        assert_position(robot, 4.0, 2.0, 0.05);
+    }
+
+    #[test]
+    fn pure_pursuit_reaches_the_end() {
+        let trajectory = s_curve();
+        let mut controller =
+            PurePursuitController::new(trajectory.clone(), Length::new::<meter>(0.5));
+        let mut robot = pose(0.0, -0.2, 0.0);
+
+        // Pure pursuit isn't tied to the trajectory's timing, so give it longer
+        for _ in 0..4 * ticks(&trajectory) {
+            robot = step(robot, controller.calculate(robot));
+            if controller.is_finished() {
+                break;
+            }
+        }
+
+        assert!(controller.is_finished());
+        assert_position(robot, 4.0, 2.0, 0.1);
+    }
+
+    #[test]
+    fn holonomic_follows_path_while_turning_to_heading() {
+        let trajectory = s_curve();
+        let period = Time::new::<millisecond>(20.0);
+        let theta = ProfiledPidController::new(
+            4.0,
+            0.0,
+            0.0,
+            TrapezoidConstraints {
+                max_velocity: AngularVelocity::new::<radian_per_second>(3.0),
+                max_acceleration: AngularAcceleration::new::<radian_per_second_squared>(6.0),
+            },
+            period,
+        );
+        let mut controller = HolonomicDriveController::new(
+            PidController::new(2.0, 0.0, 0.0, period),
+            PidController::new(2.0, 0.0, 0.0, period),
+            theta,
+        );
+        controller.set_tolerance(pose(0.05, 0.05, 2.0));
+        let mut robot = pose(0.2, 0.2, 0.0);
+        controller.reset(robot.rotation);
+        let heading = Rotation2d::new(Angle::new::<degree>(-90.0));
+
+        for i in 0..ticks(&trajectory) {
+            let desired = trajectory.sample(Time::new::<second>(i as f64 * DT));
+            robot = step(robot, controller.calculate(robot, desired, heading));
+        }
+
+        assert!(controller.at_reference());
+        assert_position(robot, 4.0, 2.0, 0.05);
+        assert_close(robot.rotation.angle().get::<degree>(), -90.0, 2.0);
+    }
+
+    #[test]
+    fn pure_pursuit_runs_as_a_node() {
+        let trajectory = s_curve();
+        let controller = PurePursuitController::new(trajectory, Length::new::<meter>(0.5));
+        let mut odometry = BaseNode::new();
+        let probe = Probe::new();
+        odometry
+            .process(Arc::new(Mutex::new(controller)))
+            .chain(probe.clone());
+
+        odometry.send(pose(0.0, 0.0, 0.0));
+
+        // Starting at rest on the path, it heads off towards the lookahead point
+        let speeds = probe.last().unwrap();
+        assert!(speeds.vx.get::<meter_per_second>() > 0.0);
+        assert!(speeds.omega.get::<radian_per_second>() > 0.0);
+        assert_eq!(probe.len(), 1);
+    }
+}
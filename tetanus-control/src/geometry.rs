@@ -0,0 +1,489 @@
+//! Field-relative 2D geometry
+//!
+//! Follows the usual FRC conventions: x is forward, y is left and angles are counterclockwise
+//! positive.
+
+use std::f64::consts::PI;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use serde::{Deserialize, Serialize};
+use tetanus_core::rate::Interpolate;
+use uom::si::angle::radian;
+use uom::si::f64::*;
+use uom::si::length::meter;
+
+/// Below this, series expansions are used instead of dividing by a tiny angle
+const EPSILON: f64 = 1e-9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Translation2d {
+    pub x: Length,
+    pub y: Length,
+}
+
+impl Translation2d {
+    pub fn new(x: Length, y: Length) -> Self {
+        Translation2d { x, y }
+    }
+
+    pub fn from_polar(distance: Length, angle: Rotation2d) -> Self {
+        Translation2d::new(distance * angle.cos(), distance * angle.sin())
+    }
+
+    pub fn norm(&self) -> Length {
+        Length::new::<meter>(self.x.get::<meter>().hypot(self.y.get::<meter>()))
+    }
+
+    /// Direction of this translation from the origin
+    pub fn angle(&self) -> Rotation2d {
+        Rotation2d::from_components(self.x.get::<meter>(), self.y.get::<meter>())
+    }
+
+    pub fn distance(&self, other: Translation2d) -> Length {
+        (other - *self).norm()
+    }
+
+    /// Rotates counterclockwise about the origin
+    pub fn rotate_by(&self, rotation: Rotation2d) -> Self {
+        let (cos, sin) = (rotation.cos(), rotation.sin());
+        Translation2d::new(self.x * cos - self.y * sin, self.x * sin + self.y * cos)
+    }
+}
+
+impl Add for Translation2d {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Translation2d::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Translation2d {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Translation2d::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl Neg for Translation2d {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Translation2d::new(-self.x, -self.y)
+    }
+}
+
+impl Mul<f64> for Translation2d {
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self {
+        Translation2d::new(self.x * scalar, self.y * scalar)
+    }
+}
+
+impl Div<f64> for Translation2d {
+    type Output = Self;
+
+    fn div(self, scalar: f64) -> Self {
+        Translation2d::new(self.x / scalar, self.y / scalar)
+    }
+}
+
+impl Interpolate for Translation2d {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+/// Rotation in the plane
+///
+/// Angles are wrapped into (-π, π], so rotations a whole turn apart are equal.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Rotation2d {
+    angle: Angle,
+}
+
+impl Rotation2d {
+    pub fn new(angle: Angle) -> Self {
+        let radians = PI - (PI - angle.get::<radian>()).rem_euclid(2.0 * PI);
+        Rotation2d {
+            angle: Angle::new::<radian>(radians),
+        }
+    }
+
+    /// Rotation pointing along the vector `(x, y)`
+    pub fn from_components(x: f64, y: f64) -> Self {
+        Rotation2d::new(Angle::new::<radian>(y.atan2(x)))
+    }
+
+    pub fn angle(&self) -> Angle {
+        self.angle
+    }
+
+    pub fn radians(&self) -> f64 {
+        self.angle.get::<radian>()
+    }
+
+    pub fn cos(&self) -> f64 {
+        self.radians().cos()
+    }
+
+    pub fn sin(&self) -> f64 {
+        self.radians().sin()
+    }
+
+    pub fn tan(&self) -> f64 {
+        self.radians().tan()
+    }
+
+    pub fn rotate_by(&self, other: Rotation2d) -> Self {
+        let (cos, sin) = (self.cos(), self.sin());
+        let (other_cos, other_sin) = (other.cos(), other.sin());
+        Rotation2d::from_components(
+            cos * other_cos - sin * other_sin,
+            cos * other_sin + sin * other_cos,
+        )
+    }
+}
+
+impl Add for Rotation2d {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        self.rotate_by(other)
+    }
+}
+
+impl Sub for Rotation2d {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self.rotate_by(-other)
+    }
+}
+
+impl Neg for Rotation2d {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Rotation2d::new(-self.angle)
+    }
+}
+
+impl Mul<f64> for Rotation2d {
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self {
+        let angle = self.radians() * scalar;
+        Rotation2d::from_components(angle.cos(), angle.sin())
+    }
+}
+
+impl Interpolate for Rotation2d {
+    /// Takes the shorter way around
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+/// Position and heading on the field
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Pose2d {
+    pub translation: Translation2d,
+    pub rotation: Rotation2d,
+}
+
+impl Pose2d {
+    pub fn new(translation: Translation2d, rotation: Rotation2d) -> Self {
+        Pose2d {
+            translation,
+            rotation,
+        }
+    }
+
+    pub fn from_xy_angle(x: Length, y: Length, angle: Angle) -> Self {
+        Pose2d::new(Translation2d::new(x, y), Rotation2d::new(angle))
+    }
+
+    pub fn x(&self) -> Length {
+        self.translation.x
+    }
+
+    pub fn y(&self) -> Length {
+        self.translation.y
+    }
+
+    /// Applies `transform` in this pose's frame
+    pub fn transform_by(&self, transform: Transform2d) -> Self {
+        Pose2d::new(
+            self.translation + transform.translation.rotate_by(self.rotation),
+            transform.rotation + self.rotation,
+        )
+    }
+
+    /// This pose as seen from `other`
+    pub fn relative_to(&self, other: Pose2d) -> Self {
+        let transform = Transform2d::between(other, *self);
+        Pose2d::new(transform.translation, transform.rotation)
+    }
+
+    /// Pose reached by following `twist` from this pose along a constant curvature arc
+    pub fn exp(&self, twist: Twist2d) -> Self {
+        let dx = twist.dx.get::<meter>();
+        let dy = twist.dy.get::<meter>();
+        let dtheta = twist.dtheta.get::<radian>();
+        let (sin, cos) = dtheta.sin_cos();
+
+        let (s, c) = if dtheta.abs() < EPSILON {
+            (1.0 - dtheta * dtheta / 6.0, 0.5 * dtheta)
+        } else {
+            (sin / dtheta, (1.0 - cos) / dtheta)
+        };
+
+        self.transform_by(Transform2d::new(
+            Translation2d::new(
+                Length::new::<meter>(dx * s - dy * c),
+                Length::new::<meter>(dx * c + dy * s),
+            ),
+            Rotation2d::from_components(cos, sin),
+        ))
+    }
+
+    /// Twist that takes this pose to `end`, the inverse of [`Pose2d::exp`]
+    pub fn log(&self, end: Pose2d) -> Twist2d {
+        let transform = end.relative_to(*self);
+        let dtheta = transform.rotation.radians();
+        let half_dtheta = dtheta / 2.0;
+        let cos_minus_one = dtheta.cos() - 1.0;
+
+        let half_theta_by_tan_of_half_dtheta = if cos_minus_one.abs() < EPSILON {
+            1.0 - dtheta * dtheta / 12.0
+        } else {
+            -(half_dtheta * dtheta.sin()) / cos_minus_one
+        };
+
+        let translation = transform.translation.rotate_by(Rotation2d::from_components(
+            half_theta_by_tan_of_half_dtheta,
+            -half_dtheta,
+        )) * half_theta_by_tan_of_half_dtheta.hypot(half_dtheta);
+
+        Twist2d {
+            dx: translation.x,
+            dy: translation.y,
+            dtheta: Angle::new::<radian>(dtheta),
+        }
+    }
+}
+
+impl Add<Transform2d> for Pose2d {
+    type Output = Self;
+
+    fn add(self, transform: Transform2d) -> Self {
+        self.transform_by(transform)
+    }
+}
+
+impl Sub for Pose2d {
+    type Output = Transform2d;
+
+    /// Transform that takes `other` to this pose
+    fn sub(self, other: Self) -> Transform2d {
+        Transform2d::between(other, self)
+    }
+}
+
+impl Interpolate for Pose2d {
+    /// Follows the constant curvature arc between the two poses
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self.exp(self.log(other) * t)
+    }
+}
+
+/// Change from one pose to another, expressed in the first pose's frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Transform2d {
+    pub translation: Translation2d,
+    pub rotation: Rotation2d,
+}
+
+impl Transform2d {
+    pub fn new(translation: Translation2d, rotation: Rotation2d) -> Self {
+        Transform2d {
+            translation,
+            rotation,
+        }
+    }
+
+    pub fn between(initial: Pose2d, last: Pose2d) -> Self {
+        Transform2d::new(
+            (last.translation - initial.translation).rotate_by(-initial.rotation),
+            last.rotation - initial.rotation,
+        )
+    }
+
+    pub fn inverse(&self) -> Self {
+        Transform2d::new(
+            (-self.translation).rotate_by(-self.rotation),
+            -self.rotation,
+        )
+    }
+}
+
+impl Add for Transform2d {
+    type Output = Self;
+
+    /// This transform followed by `other`
+    fn add(self, other: Self) -> Self {
+        Transform2d::between(
+            Pose2d::default(),
+            Pose2d::default().transform_by(self).transform_by(other),
+        )
+    }
+}
+
+/// Change in pose along a constant curvature arc, in the starting pose's frame
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Twist2d {
+    pub dx: Length,
+    pub dy: Length,
+    pub dtheta: Angle,
+}
+
+impl Twist2d {
+    pub fn new(dx: Length, dy: Length, dtheta: Angle) -> Self {
+        Twist2d { dx, dy, dtheta }
+    }
+}
+
+impl Mul<f64> for Twist2d {
+    type Output = Self;
+
+    fn mul(self, scalar: f64) -> Self {
+        Twist2d::new(self.dx * scalar, self.dy * scalar, self.dtheta * scalar)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_pose, deg, m, pose};
+    use std::f64::consts::{FRAC_PI_2, PI};
+    use tetanus_core::testing::assert_close;
+    use uom::si::angle::degree;
+
+    #[test]
+    fn rotations_wrap_around() {
+        let sum = Rotation2d::new(deg(170.0)) + Rotation2d::new(deg(20.0));
+
+        assert_close(sum.angle().get::<degree>(), -170.0, 1e-9);
+        assert_close(
+            Rotation2d::new(deg(160.0))
+                .lerp(Rotation2d::new(deg(-170.0)), 0.5)
+                .angle()
+                .get::<degree>(),
+            175.0,
+            1e-9,
+        );
+        assert_close(
+            (Rotation2d::new(deg(120.0)) * 2.0).angle().get::<degree>(),
+            -120.0,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn rotations_are_stored_wrapped() {
+        assert_eq!(Rotation2d::new(deg(360.0)), Rotation2d::new(deg(0.0)));
+        assert_close(Rotation2d::new(deg(-540.0)).radians(), PI, 1e-9);
+        assert_close(Rotation2d::new(deg(270.0)).radians(), -FRAC_PI_2, 1e-9);
+        assert_eq!((-Rotation2d::new(Angle::new::<radian>(PI))).radians(), PI);
+    }
+
+    #[test]
+    fn translation_rotates_about_origin() {
+        let rotated = Translation2d::new(m(2.0), m(0.0)).rotate_by(Rotation2d::new(deg(90.0)));
+
+        assert_close(rotated.x.get::<meter>(), 0.0, 1e-9);
+        assert_close(rotated.y.get::<meter>(), 2.0, 1e-9);
+        assert_close(rotated.angle().radians(), FRAC_PI_2, 1e-9);
+        assert_close(rotated.norm().get::<meter>(), 2.0, 1e-9);
+    }
+
+    #[test]
+    fn transforms_compose_and_invert() {
+        let start = pose(1.0, 2.0, 90.0);
+        let transform = Transform2d::new(
+            Translation2d::new(m(1.0), m(0.0)),
+            Rotation2d::new(deg(45.0)),
+        );
+
+        let end = start + transform;
+        assert_pose(end, pose(1.0, 3.0, 135.0), 1e-9);
+        assert_pose(end + transform.inverse(), start, 1e-9);
+        assert_pose(start + (transform + transform), end + transform, 1e-9);
+
+        let between = end - start;
+        assert_pose(start + between, end, 1e-9);
+    }
+
+    #[test]
+    fn relative_to_expresses_pose_in_other_frame() {
+        let robot = pose(2.0, 2.0, 90.0);
+        let target = pose(2.0, 5.0, 0.0);
+
+        assert_pose(target.relative_to(robot), pose(3.0, 0.0, -90.0), 1e-9);
+    }
+
+    #[test]
+    fn exp_follows_an_arc() {
+        let quarter_circle = Twist2d::new(m(PI / 2.0), m(0.0), deg(90.0));
+
+        assert_pose(
+            Pose2d::default().exp(quarter_circle),
+            pose(1.0, 1.0, 90.0),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn log_inverts_exp() {
+        let start = pose(1.0, -2.0, 30.0);
+        for end in [
+            pose(4.0, 1.0, 75.0),
+            pose(-2.0, 3.0, 30.0),
+            pose(1.0, -2.0, 200.0),
+        ] {
+            assert_pose(start.exp(start.log(end)), end, 1e-9);
+        }
+    }
+
+    #[test]
+    fn pose_interpolation_follows_the_arc() {
+        let start = Pose2d::default();
+        let end = pose(1.0, 1.0, 90.0);
+
+        let halfway = start.lerp(end, 0.5);
+
+        assert_pose(
+            halfway,
+            pose((PI / 4.0).sin(), 1.0 - (PI / 4.0).cos(), 45.0),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn serializes_in_base_units() {
+        let json = serde_json::to_string(&pose(1.0, 2.0, 180.0)).unwrap();
+
+        assert_eq!(
+            json,
+            format!(
+                r#"{{"translation":{{"x":1.0,"y":2.0}},"rotation":{{"angle":{}}}}}"#,
+                PI
+            )
+        );
+        let parsed: Pose2d = serde_json::from_str(&json).unwrap();
+        assert_pose(parsed, pose(1.0, 2.0, 180.0), 1e-9);
+    }
+}
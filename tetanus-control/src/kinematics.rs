@@ -0,0 +1,423 @@
+//! Conversions between chassis motion and wheel motion
+
+use std::f64::consts::PI;
+
+use serde::{Deserialize, Serialize};
+use uom::si::angle::radian;
+use uom::si::angular_velocity::radian_per_second;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::velocity::meter_per_second;
+
+use crate::geometry::{Rotation2d, Translation2d, Twist2d};
+
+/// Robot-relative velocity of the chassis, with x forward and y left
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChassisSpeeds {
+    pub vx: Velocity,
+    pub vy: Velocity,
+    pub omega: AngularVelocity,
+}
+
+impl ChassisSpeeds {
+    pub fn new(vx: Velocity, vy: Velocity, omega: AngularVelocity) -> Self {
+        ChassisSpeeds { vx, vy, omega }
+    }
+
+    /// Converts field-relative speeds to robot-relative ones given the robot's heading
+    pub fn from_field_relative(
+        vx: Velocity,
+        vy: Velocity,
+        omega: AngularVelocity,
+        heading: Rotation2d,
+    ) -> Self {
+        let (cos, sin) = (heading.cos(), heading.sin());
+        ChassisSpeeds::new(vx * cos + vy * sin, -vx * sin + vy * cos, omega)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct DifferentialDriveWheelSpeeds {
+    pub left: Velocity,
+    pub right: Velocity,
+}
+
+impl DifferentialDriveWheelSpeeds {
+    pub fn new(left: Velocity, right: Velocity) -> Self {
+        DifferentialDriveWheelSpeeds { left, right }
+    }
+
+    /// Scales both sides down equally so neither exceeds `max`, keeping the turn the same shape
+    pub fn desaturate(&mut self, max: Velocity) {
+        let fastest = self.left.abs().max(self.right.abs());
+        if fastest > max {
+            let scale = (max / fastest).value;
+            self.left *= scale;
+            self.right *= scale;
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DifferentialDriveKinematics {
+    /// Distance between the left and right wheels
+    pub track_width: Length,
+}
+
+impl DifferentialDriveKinematics {
+    pub fn new(track_width: Length) -> Self {
+        DifferentialDriveKinematics { track_width }
+    }
+
+    pub fn to_chassis_speeds(&self, wheels: DifferentialDriveWheelSpeeds) -> ChassisSpeeds {
+        let (left, right) = (
+            wheels.left.get::<meter_per_second>(),
+            wheels.right.get::<meter_per_second>(),
+        );
+        ChassisSpeeds::new(
+            Velocity::new::<meter_per_second>((left + right) / 2.0),
+            Velocity::default(),
+            AngularVelocity::new::<radian_per_second>(
+                (right - left) / self.track_width.get::<meter>(),
+            ),
+        )
+    }
+
+    /// Ignores `vy`, which a differential drive can't follow
+    pub fn to_wheel_speeds(&self, chassis: ChassisSpeeds) -> DifferentialDriveWheelSpeeds {
+        let vx = chassis.vx.get::<meter_per_second>();
+        let turn = chassis.omega.get::<radian_per_second>() * self.track_width.get::<meter>() / 2.0;
+        DifferentialDriveWheelSpeeds::new(
+            Velocity::new::<meter_per_second>(vx - turn),
+            Velocity::new::<meter_per_second>(vx + turn),
+        )
+    }
+
+    /// Chassis motion from the distance each side travelled
+    pub fn to_twist(&self, left: Length, right: Length) -> Twist2d {
+        let (left, right) = (left.get::<meter>(), right.get::<meter>());
+        Twist2d::new(
+            Length::new::<meter>((left + right) / 2.0),
+            Length::default(),
+            Angle::new::<radian>((right - left) / self.track_width.get::<meter>()),
+        )
+    }
+}
+
+/// Speed and direction of a swerve module's wheel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SwerveModuleState {
+    pub speed: Velocity,
+    pub angle: Rotation2d,
+}
+
+impl SwerveModuleState {
+    pub fn new(speed: Velocity, angle: Rotation2d) -> Self {
+        SwerveModuleState { speed, angle }
+    }
+
+    /// Equivalent state that needs the module to turn at most 90° from `current`, reversing the
+    /// wheel if needed
+    pub fn optimize(self, current: Rotation2d) -> Self {
+        if (self.angle - current).cos() < 0.0 {
+            SwerveModuleState::new(
+                -self.speed,
+                self.angle + Rotation2d::new(Angle::new::<radian>(PI)),
+            )
+        } else {
+            self
+        }
+    }
+}
+
+/// Total distance driven by a swerve module's wheel and its current direction
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SwerveModulePosition {
+    pub distance: Length,
+    pub angle: Rotation2d,
+}
+
+impl SwerveModulePosition {
+    pub fn new(distance: Length, angle: Rotation2d) -> Self {
+        SwerveModulePosition { distance, angle }
+    }
+}
+
+/// Kinematics for any number of swerve modules, in the order they were given
+#[derive(Clone, Debug, PartialEq)]
+pub struct SwerveDriveKinematics {
+    /// Module positions relative to the centre of the robot
+    modules: Vec<Translation2d>,
+}
+
+impl SwerveDriveKinematics {
+    pub fn new(modules: Vec<Translation2d>) -> Self {
+        assert!(
+            modules.len() >= 2,
+            "swerve drive needs at least two modules"
+        );
+        SwerveDriveKinematics { modules }
+    }
+
+    pub fn modules(&self) -> &[Translation2d] {
+        &self.modules
+    }
+
+    pub fn to_module_states(&self, chassis: ChassisSpeeds) -> Vec<SwerveModuleState> {
+        self.to_module_states_about(chassis, Translation2d::default())
+    }
+
+    /// Rotates about `center` instead of the middle of the robot, e.g. about a corner to spin
+    /// around a defender
+    pub fn to_module_states_about(
+        &self,
+        chassis: ChassisSpeeds,
+        center: Translation2d,
+    ) -> Vec<SwerveModuleState> {
+        let vx = chassis.vx.get::<meter_per_second>();
+        let vy = chassis.vy.get::<meter_per_second>();
+        let omega = chassis.omega.get::<radian_per_second>();
+
+        self.modules
+            .iter()
+            .map(|module| {
+                let offset = *module - center;
+                let x = vx - omega * offset.y.get::<meter>();
+                let y = vy + omega * offset.x.get::<meter>();
+                SwerveModuleState::new(
+                    Velocity::new::<meter_per_second>(x.hypot(y)),
+                    Rotation2d::from_components(x, y),
+                )
+            })
+            .collect()
+    }
+
+    /// Least squares fit of the chassis speeds to the module states
+    pub fn to_chassis_speeds(&self, states: &[SwerveModuleState]) -> ChassisSpeeds {
+        let vectors = states
+            .iter()
+            .map(|s| s.speed.get::<meter_per_second>())
+            .zip(states.iter().map(|s| s.angle));
+        let (vx, vy, omega) = self.fit(vectors);
+        ChassisSpeeds::new(
+            Velocity::new::<meter_per_second>(vx),
+            Velocity::new::<meter_per_second>(vy),
+            AngularVelocity::new::<radian_per_second>(omega),
+        )
+    }
+
+    /// Chassis motion from the distance each module travelled and the direction it ended up in
+    pub fn to_twist(&self, deltas: &[SwerveModulePosition]) -> Twist2d {
+        let vectors = deltas
+            .iter()
+            .map(|d| d.distance.get::<meter>())
+            .zip(deltas.iter().map(|d| d.angle));
+        let (dx, dy, dtheta) = self.fit(vectors);
+        Twist2d::new(
+            Length::new::<meter>(dx),
+            Length::new::<meter>(dy),
+            Angle::new::<radian>(dtheta),
+        )
+    }
+
+    /// Scales every module down equally so none exceeds `max`
+    pub fn desaturate(states: &mut [SwerveModuleState], max: Velocity) {
+        let fastest = states
+            .iter()
+            .map(|s| s.speed.abs())
+            .fold(Velocity::default(), Velocity::max);
+        if fastest > max {
+            let scale = (max / fastest).value;
+            for state in states {
+                state.speed *= scale;
+            }
+        }
+    }
+
+    /// Solves the normal equations for `(x, y, θ)` given each module's motion as a magnitude and
+    /// direction
+    fn fit(&self, vectors: impl Iterator<Item = (f64, Rotation2d)>) -> (f64, f64, f64) {
+        let mut normal = [[0.0; 3]; 3];
+        let mut rhs = [0.0; 3];
+        let mut count = 0;
+
+        for (module, (magnitude, angle)) in self.modules.iter().zip(vectors) {
+            let (x, y) = (module.x.get::<meter>(), module.y.get::<meter>());
+            let (u, v) = (magnitude * angle.cos(), magnitude * angle.sin());
+            let rows = [[1.0, 0.0, -y], [0.0, 1.0, x]];
+            for (row, b) in rows.iter().zip(&[u, v]) {
+                for i in 0..3 {
+                    for j in 0..3 {
+                        normal[i][j] += row[i] * row[j];
+                    }
+                    rhs[i] += row[i] * b;
+                }
+            }
+            count += 1;
+        }
+        assert_eq!(count, self.modules.len(), "expected one value per module");
+
+        let [x, y, theta] = solve3(normal, rhs);
+        (x, y, theta)
+    }
+}
+
+/// Cramer's rule for a 3x3 system
+fn solve3(a: [[f64; 3]; 3], b: [f64; 3]) -> [f64; 3] {
+    let det = |m: [[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let denominator = det(a);
+    let mut solution = [0.0; 3];
+    for (column, value) in solution.iter_mut().enumerate() {
+        let mut replaced = a;
+        for (row, b) in replaced.iter_mut().zip(&b) {
+            row[column] = *b;
+        }
+        *value = det(replaced) / denominator;
+    }
+    solution
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::mps;
+    use tetanus_core::testing::assert_close;
+    use uom::si::angle::degree;
+
+    fn kinematics() -> DifferentialDriveKinematics {
+        DifferentialDriveKinematics::new(Length::new::<meter>(0.5))
+    }
+
+    #[test]
+    fn wheel_speeds_round_trip_through_chassis_speeds() {
+        let chassis = ChassisSpeeds::new(
+            mps(2.0),
+            mps(0.0),
+            AngularVelocity::new::<radian_per_second>(4.0),
+        );
+
+        let wheels = kinematics().to_wheel_speeds(chassis);
+        assert_close(wheels.left.get::<meter_per_second>(), 1.0, 1e-9);
+        assert_close(wheels.right.get::<meter_per_second>(), 3.0, 1e-9);
+
+        let back = kinematics().to_chassis_speeds(wheels);
+        assert_close(back.vx.get::<meter_per_second>(), 2.0, 1e-9);
+        assert_close(back.omega.get::<radian_per_second>(), 4.0, 1e-9);
+    }
+
+    #[test]
+    fn desaturate_keeps_ratio() {
+        let mut wheels = DifferentialDriveWheelSpeeds::new(mps(-2.0), mps(4.0));
+
+        wheels.desaturate(mps(3.0));
+
+        assert_close(wheels.left.get::<meter_per_second>(), -1.5, 1e-9);
+        assert_close(wheels.right.get::<meter_per_second>(), 3.0, 1e-9);
+    }
+
+    #[test]
+    fn field_relative_speeds_rotate_into_robot_frame() {
+        let heading = Rotation2d::new(Angle::new::<radian>(std::f64::consts::FRAC_PI_2));
+
+        let speeds = ChassisSpeeds::from_field_relative(
+            mps(1.0),
+            mps(0.0),
+            AngularVelocity::default(),
+            heading,
+        );
+
+        assert_close(speeds.vx.get::<meter_per_second>(), 0.0, 1e-9);
+        assert_close(speeds.vy.get::<meter_per_second>(), -1.0, 1e-9);
+    }
+
+    fn square() -> SwerveDriveKinematics {
+        let corner =
+            |x: f64, y: f64| Translation2d::new(Length::new::<meter>(x), Length::new::<meter>(y));
+        SwerveDriveKinematics::new(vec![
+            corner(0.3, 0.3),
+            corner(0.3, -0.3),
+            corner(-0.3, 0.3),
+            corner(-0.3, -0.3),
+        ])
+    }
+
+    #[test]
+    fn swerve_spins_in_place_with_tangent_modules() {
+        let chassis = ChassisSpeeds::new(
+            mps(0.0),
+            mps(0.0),
+            AngularVelocity::new::<radian_per_second>(1.0),
+        );
+
+        let states = square().to_module_states(chassis);
+
+        for (state, degrees) in states.iter().zip(&[135.0, 45.0, -135.0, -45.0]) {
+            assert_close(
+                state.speed.get::<meter_per_second>(),
+                0.3 * 2f64.sqrt(),
+                1e-9,
+            );
+            assert_close(state.angle.angle().get::<degree>(), *degrees, 1e-9);
+        }
+
+        let back = square().to_chassis_speeds(&states);
+        assert_close(back.vx.get::<meter_per_second>(), 0.0, 1e-9);
+        assert_close(back.vy.get::<meter_per_second>(), 0.0, 1e-9);
+        assert_close(back.omega.get::<radian_per_second>(), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn swerve_rotates_about_overridden_center() {
+        let chassis = ChassisSpeeds::new(
+            mps(0.0),
+            mps(0.0),
+            AngularVelocity::new::<radian_per_second>(2.0),
+        );
+        let front_left = square().modules()[0];
+
+        let states = square().to_module_states_about(chassis, front_left);
+
+        assert_close(states[0].speed.get::<meter_per_second>(), 0.0, 1e-9);
+        assert_close(
+            states[3].speed.get::<meter_per_second>(),
+            2.0 * 0.6 * 2f64.sqrt(),
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn optimize_reverses_instead_of_turning_far() {
+        let target = SwerveModuleState::new(mps(2.0), Rotation2d::new(Angle::new::<degree>(170.0)));
+
+        let kept = target.optimize(Rotation2d::new(Angle::new::<degree>(100.0)));
+        let flipped = target.optimize(Rotation2d::new(Angle::new::<degree>(0.0)));
+
+        assert_eq!(kept, target);
+        assert_close(flipped.speed.get::<meter_per_second>(), -2.0, 1e-9);
+        assert_close(flipped.angle.angle().get::<degree>(), -10.0, 1e-9);
+    }
+
+    #[test]
+    fn swerve_desaturate_scales_every_module() {
+        let angle = Rotation2d::default();
+        let mut states = vec![
+            SwerveModuleState::new(mps(1.0), angle),
+            SwerveModuleState::new(mps(-5.0), angle),
+            SwerveModuleState::new(mps(2.5), angle),
+        ];
+
+        SwerveDriveKinematics::desaturate(&mut states, mps(4.0));
+
+        let speeds: Vec<f64> = states
+            .iter()
+            .map(|s| s.speed.get::<meter_per_second>())
+            .collect();
+        for (actual, expected) in speeds.iter().zip(&[0.8, -4.0, 2.0]) {
+            assert_close(*actual, *expected, 1e-9);
+        }
+    }
+}
@@ -0,0 +1,25 @@
+//! Controllers and math for robot motion, independent of any hardware
+//!
+//! Everything here works on uom quantities and can be tested off the robot.
+
+pub mod characterization;
+pub mod constraint;
+pub mod estimator;
+pub mod feedforward;
+pub mod follower;
+pub mod geometry;
+pub mod kinematics;
+pub mod lookup;
+pub mod motor;
+pub mod odometry;
+pub mod paths;
+pub mod pid;
+pub mod profile;
+pub mod sensor;
+pub mod shaping;
+pub mod spline;
+pub mod state_space;
+#[cfg(test)]
+mod testing;
+pub mod trajectory;
+pub mod units;
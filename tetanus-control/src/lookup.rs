@@ -0,0 +1,364 @@
+//! Calibration tables interpolated at runtime, like shooter speed against distance
+//!
+//! Tables on disk hold plain numbers, so loading takes functions that give them units, e.g.
+//! `|x| Length::new::<meter>(x)`.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use crate::units::SiValue;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Interpolation {
+    /// Straight lines between points
+    Linear,
+    /// Smooth curves that never overshoot between points, so a table that only goes up still
+    /// only goes up
+    MonotoneCubic,
+}
+
+/// Table from keys to values, interpolated between points and clamped past either end
+#[derive(Clone, Debug, PartialEq)]
+pub struct InterpolatingMap<K, V> {
+    keys: Vec<K>,
+    values: Vec<V>,
+    interpolation: Interpolation,
+    /// Slope at each point for [`Interpolation::MonotoneCubic`], in base SI units
+    tangents: Vec<f64>,
+}
+
+impl<K: SiValue, V: SiValue> InterpolatingMap<K, V> {
+    /// Points can be in any order, but each key must only appear once
+    pub fn new(
+        points: impl IntoIterator<Item = (K, V)>,
+        interpolation: Interpolation,
+    ) -> Result<Self> {
+        let mut points: Vec<(K, V)> = points.into_iter().collect();
+        if points.is_empty() {
+            bail!("a lookup table needs at least one point");
+        }
+        if points
+            .iter()
+            .any(|(k, v)| !k.si().is_finite() || !v.si().is_finite())
+        {
+            bail!("lookup table has a value that isn't a finite number");
+        }
+        points.sort_by(|a, b| a.0.si().partial_cmp(&b.0.si()).unwrap());
+        if let Some(pair) = points
+            .windows(2)
+            .find(|pair| pair[0].0.si() == pair[1].0.si())
+        {
+            bail!("lookup table has key {} more than once", pair[0].0.si());
+        }
+
+        let (keys, values): (Vec<K>, Vec<V>) = points.into_iter().unzip();
+        let tangents = match interpolation {
+            Interpolation::Linear => Vec::new(),
+            Interpolation::MonotoneCubic => monotone_tangents(&keys, &values),
+        };
+        Ok(InterpolatingMap {
+            keys,
+            values,
+            interpolation,
+            tangents,
+        })
+    }
+
+    pub fn linear(points: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        Self::new(points, Interpolation::Linear)
+    }
+
+    pub fn monotone_cubic(points: impl IntoIterator<Item = (K, V)>) -> Result<Self> {
+        Self::new(points, Interpolation::MonotoneCubic)
+    }
+
+    /// Reads a CSV table with a header row, keys in the first column and values in the second
+    pub fn from_csv(
+        text: &str,
+        interpolation: Interpolation,
+        key: impl Fn(f64) -> K,
+        value: impl Fn(f64) -> V,
+    ) -> Result<Self> {
+        let rows = csv::Reader::from_reader(text.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<(f64, f64)>, _>>()?;
+        Self::new(
+            rows.into_iter().map(|(k, v)| (key(k), value(v))),
+            interpolation,
+        )
+    }
+
+    /// Reads the table called `name` from TOML holding `[key, value]` pairs, such as
+    ///
+    /// ```toml
+    /// # Distance in meters to flywheel speed in RPM
+    /// shooter_speed = [[1.5, 3000.0], [3.0, 3600.0], [4.5, 4400.0]]
+    /// ```
+    pub fn from_toml(
+        text: &str,
+        name: &str,
+        interpolation: Interpolation,
+        key: impl Fn(f64) -> K,
+        value: impl Fn(f64) -> V,
+    ) -> Result<Self> {
+        // Only the requested table is read, so other settings can share the file
+        let document: toml::Value = text.parse()?;
+        let rows: Vec<(f64, f64)> = match document.get(name) {
+            Some(table) => table
+                .clone()
+                .try_into()
+                .with_context(|| format!("lookup table {} isn't a list of pairs", name))?,
+            None => bail!("no lookup table called {}", name),
+        };
+        Self::new(
+            rows.into_iter().map(|(k, v)| (key(k), value(v))),
+            interpolation,
+        )
+    }
+
+    pub fn load_csv(
+        path: impl AsRef<Path>,
+        interpolation: Interpolation,
+        key: impl Fn(f64) -> K,
+        value: impl Fn(f64) -> V,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read lookup table {}", path.display()))?;
+        Self::from_csv(&text, interpolation, key, value)
+            .with_context(|| format!("invalid lookup table {}", path.display()))
+    }
+
+    pub fn load_toml(
+        path: impl AsRef<Path>,
+        name: &str,
+        interpolation: Interpolation,
+        key: impl Fn(f64) -> K,
+        value: impl Fn(f64) -> V,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("couldn't read lookup tables {}", path.display()))?;
+        Self::from_toml(&text, name, interpolation, key, value)
+            .with_context(|| format!("invalid lookup tables {}", path.display()))
+    }
+
+    /// Value at `key`, clamped to the ends of the table
+    ///
+    /// A NaN key, like a distance computed with no target in view, gives the first value.
+    pub fn get(&self, key: K) -> V {
+        let x = key.si();
+        let last = self.keys.len() - 1;
+        if x.is_nan() || x <= self.keys[0].si() {
+            return self.values[0];
+        }
+        if x >= self.keys[last].si() {
+            return self.values[last];
+        }
+
+        let i = self.keys.partition_point(|k| k.si() <= x) - 1;
+        let (x0, x1) = (self.keys[i].si(), self.keys[i + 1].si());
+        let (y0, y1) = (self.values[i].si(), self.values[i + 1].si());
+        let h = x1 - x0;
+        let t = (x - x0) / h;
+        V::from_si(match self.interpolation {
+            Interpolation::Linear => y0 + (y1 - y0) * t,
+            Interpolation::MonotoneCubic => {
+                let (t2, t3) = (t * t, t * t * t);
+                (2.0 * t3 - 3.0 * t2 + 1.0) * y0
+                    + (t3 - 2.0 * t2 + t) * h * self.tangents[i]
+                    + (-2.0 * t3 + 3.0 * t2) * y1
+                    + (t3 - t2) * h * self.tangents[i + 1]
+            }
+        })
+    }
+
+    /// Function looking up keys in this table, for [`Node::map`](tetanus_core::node::Node::map)
+    pub fn into_fn(self) -> impl Fn(K) -> V + Clone + Send + Sync
+    where
+        K: Send + Sync,
+        V: Send + Sync,
+    {
+        move |key| self.get(key)
+    }
+}
+
+/// Fritsch-Carlson tangents, limited so the curve between points stays monotonic
+fn monotone_tangents<K: SiValue, V: SiValue>(keys: &[K], values: &[V]) -> Vec<f64> {
+    let n = keys.len();
+    if n < 2 {
+        return vec![0.0; n];
+    }
+    let secants: Vec<f64> = (0..n - 1)
+        .map(|i| (values[i + 1].si() - values[i].si()) / (keys[i + 1].si() - keys[i].si()))
+        .collect();
+
+    let mut tangents = Vec::with_capacity(n);
+    tangents.push(secants[0]);
+    for pair in secants.windows(2) {
+        tangents.push(if pair[0] * pair[1] <= 0.0 {
+            0.0
+        } else {
+            (pair[0] + pair[1]) / 2.0
+        });
+    }
+    tangents.push(secants[n - 2]);
+
+    for (i, &secant) in secants.iter().enumerate() {
+        if secant == 0.0 {
+            tangents[i] = 0.0;
+            tangents[i + 1] = 0.0;
+            continue;
+        }
+        let (alpha, beta) = (tangents[i] / secant, tangents[i + 1] / secant);
+        let magnitude = alpha.hypot(beta);
+        if magnitude > 3.0 {
+            tangents[i] = 3.0 / magnitude * alpha * secant;
+            tangents[i + 1] = 3.0 / magnitude * beta * secant;
+        }
+    }
+    tangents
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::m;
+    use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+    use tetanus_core::testing::{assert_close, Probe};
+    use uom::si::angle::degree;
+    use uom::si::angular_velocity::revolution_per_minute;
+    use uom::si::f64::*;
+
+    fn rpm(value: f64) -> AngularVelocity {
+        AngularVelocity::new::<revolution_per_minute>(value)
+    }
+
+    fn shooter(interpolation: Interpolation) -> InterpolatingMap<Length, AngularVelocity> {
+        InterpolatingMap::new(
+            vec![
+                (m(3.0), rpm(3600.0)),
+                (m(1.5), rpm(3000.0)),
+                (m(4.5), rpm(4400.0)),
+                (m(6.0), rpm(4400.0)),
+            ],
+            interpolation,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn linear_interpolates_and_clamps() {
+        let table = shooter(Interpolation::Linear);
+
+        assert_close(
+            table.get(m(2.25)).get::<revolution_per_minute>(),
+            3300.0,
+            1e-9,
+        );
+        assert_close(
+            table.get(m(3.0)).get::<revolution_per_minute>(),
+            3600.0,
+            1e-9,
+        );
+        assert_close(
+            table.get(m(0.0)).get::<revolution_per_minute>(),
+            3000.0,
+            1e-9,
+        );
+        assert_close(
+            table.get(m(10.0)).get::<revolution_per_minute>(),
+            4400.0,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn non_finite_keys_give_an_end_of_the_table() {
+        for interpolation in [Interpolation::Linear, Interpolation::MonotoneCubic].iter() {
+            let table = shooter(*interpolation);
+            let rpm_at = |x: f64| table.get(m(x)).get::<revolution_per_minute>();
+
+            assert_close(rpm_at(f64::NAN), 3000.0, 1e-9);
+            assert_close(rpm_at(f64::NEG_INFINITY), 3000.0, 1e-9);
+            assert_close(rpm_at(f64::INFINITY), 4400.0, 1e-9);
+        }
+    }
+
+    #[test]
+    fn monotone_cubic_is_smooth_without_overshoot() {
+        let table = shooter(Interpolation::MonotoneCubic);
+
+        let samples: Vec<f64> = (0..=600)
+            .map(|i| {
+                table
+                    .get(m(i as f64 / 100.0))
+                    .get::<revolution_per_minute>()
+            })
+            .collect();
+        assert!(samples.windows(2).all(|pair| pair[1] >= pair[0] - 1e-9));
+        assert!(samples.iter().all(|&speed| speed <= 4400.0 + 1e-9));
+        assert_close(
+            table.get(m(4.5)).get::<revolution_per_minute>(),
+            4400.0,
+            1e-9,
+        );
+        // Curves between points rather than going straight
+        let middle = table.get(m(2.25)).get::<revolution_per_minute>();
+        assert!((middle - 3300.0).abs() > 1.0);
+    }
+
+    #[test]
+    fn loads_from_csv_and_toml() {
+        let csv = "distance_m,hood_deg\n1.0,20.0\n2.0,30.0\n";
+        let hood = InterpolatingMap::from_csv(csv, Interpolation::Linear, m, Angle::new::<degree>)
+            .unwrap();
+        assert_close(hood.get(m(1.5)).get::<degree>(), 25.0, 1e-9);
+
+        let toml = "version = 2\n\
+            # meters to rpm\n\
+            shooter_speed = [[1.5, 3000.0], [3.0, 3600.0]]\n\
+            other = [[0.0, 1.0]]\n\
+            [metadata]\n\
+            robot = \"r2021\"\n";
+        let speed =
+            InterpolatingMap::from_toml(toml, "shooter_speed", Interpolation::Linear, m, rpm)
+                .unwrap();
+        assert_close(
+            speed.get(m(2.25)).get::<revolution_per_minute>(),
+            3300.0,
+            1e-9,
+        );
+
+        let missing = InterpolatingMap::from_toml(toml, "hood", Interpolation::Linear, m, rpm);
+        assert_eq!(
+            missing.unwrap_err().to_string(),
+            "no lookup table called hood"
+        );
+        let invalid = InterpolatingMap::from_toml(toml, "version", Interpolation::Linear, m, rpm);
+        assert!(invalid.is_err());
+        let repeated = InterpolatingMap::from_csv("x,y\n1,2\n1,3\n", Interpolation::Linear, m, rpm);
+        assert!(repeated.unwrap_err().to_string().contains("more than once"));
+    }
+
+    #[test]
+    fn works_as_a_map_node() {
+        let mut distance = BaseNode::new();
+        let probe = Probe::new();
+        distance
+            .map(shooter(Interpolation::Linear).into_fn())
+            .chain(probe.clone());
+
+        distance.send(m(2.25));
+
+        assert_close(
+            probe.last().unwrap().get::<revolution_per_minute>(),
+            3300.0,
+            1e-9,
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Brushed and brushless DC motor constants, for building models of mechanisms
+
+use uom::si::angular_velocity::{radian_per_second, revolution_per_minute};
+use uom::si::electric_current::ampere;
+use uom::si::electric_potential::volt;
+use uom::si::electrical_resistance::ohm;
+use uom::si::f64::*;
+use uom::si::torque::newton_meter;
+
+/// A motor, or several identical motors geared together, from its datasheet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DcMotor {
+    pub nominal_voltage: ElectricPotential,
+    pub stall_torque: Torque,
+    pub stall_current: ElectricCurrent,
+    pub free_current: ElectricCurrent,
+    pub free_speed: AngularVelocity,
+}
+
+impl DcMotor {
+    /// `count` motors sharing the load, each with the given datasheet values
+    pub fn new(
+        nominal_voltage: ElectricPotential,
+        stall_torque: Torque,
+        stall_current: ElectricCurrent,
+        free_current: ElectricCurrent,
+        free_speed: AngularVelocity,
+        count: usize,
+    ) -> Self {
+        let count = count as f64;
+        DcMotor {
+            nominal_voltage,
+            stall_torque: stall_torque * count,
+            stall_current: stall_current * count,
+            free_current: free_current * count,
+            free_speed,
+        }
+    }
+
+    pub fn falcon_500(count: usize) -> Self {
+        Self::new(
+            ElectricPotential::new::<volt>(12.0),
+            Torque::new::<newton_meter>(4.69),
+            ElectricCurrent::new::<ampere>(257.0),
+            ElectricCurrent::new::<ampere>(1.5),
+            AngularVelocity::new::<revolution_per_minute>(6380.0),
+            count,
+        )
+    }
+
+    pub fn vex_775_pro(count: usize) -> Self {
+        Self::new(
+            ElectricPotential::new::<volt>(12.0),
+            Torque::new::<newton_meter>(0.71),
+            ElectricCurrent::new::<ampere>(134.0),
+            ElectricCurrent::new::<ampere>(0.7),
+            AngularVelocity::new::<revolution_per_minute>(18730.0),
+            count,
+        )
+    }
+
+    pub fn neo(count: usize) -> Self {
+        Self::new(
+            ElectricPotential::new::<volt>(12.0),
+            Torque::new::<newton_meter>(2.6),
+            ElectricCurrent::new::<ampere>(105.0),
+            ElectricCurrent::new::<ampere>(1.8),
+            AngularVelocity::new::<revolution_per_minute>(5676.0),
+            count,
+        )
+    }
+
+    pub fn resistance(&self) -> ElectricalResistance {
+        self.nominal_voltage / self.stall_current
+    }
+
+    /// Speed per volt of back EMF, in radians per second per volt
+    pub fn kv(&self) -> f64 {
+        let back_emf = self.nominal_voltage - self.resistance() * self.free_current;
+        self.free_speed.get::<radian_per_second>() / back_emf.get::<volt>()
+    }
+
+    /// Torque per amp, in newton meters per amp
+    pub fn kt(&self) -> f64 {
+        self.stall_torque.get::<newton_meter>() / self.stall_current.get::<ampere>()
+    }
+
+    /// Current drawn while spinning at `speed` with `voltage` applied
+    pub fn current(&self, speed: AngularVelocity, voltage: ElectricPotential) -> ElectricCurrent {
+        let back_emf = speed.get::<radian_per_second>() / self.kv();
+        ElectricCurrent::new::<ampere>(
+            (voltage.get::<volt>() - back_emf) / self.resistance().get::<ohm>(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetanus_core::testing::assert_close;
+
+    #[test]
+    fn falcon_constants_match_datasheet() {
+        let falcon = DcMotor::falcon_500(1);
+
+        assert_close(falcon.resistance().get::<ohm>(), 12.0 / 257.0, 1e-12);
+        assert_close(falcon.kt(), 4.69 / 257.0, 1e-12);
+        assert_close(falcon.kv(), 56.0, 0.05);
+        let stalled = falcon.current(
+            AngularVelocity::default(),
+            ElectricPotential::new::<volt>(12.0),
+        );
+        assert_close(stalled.get::<ampere>(), 257.0, 1e-9);
+
+        let pair = DcMotor::falcon_500(2);
+        assert_close(
+            pair.resistance().get::<ohm>(),
+            falcon.resistance().get::<ohm>() / 2.0,
+            1e-12,
+        );
+        assert_close(pair.kv(), falcon.kv(), 1e-9);
+    }
+}
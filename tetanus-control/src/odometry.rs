@@ -0,0 +1,229 @@
+//! Field pose tracking from wheel encoders and a heading source
+
+use uom::si::f64::*;
+
+use crate::geometry::{Pose2d, Rotation2d, Twist2d};
+use crate::kinematics::{DifferentialDriveKinematics, SwerveDriveKinematics, SwerveModulePosition};
+
+/// Tracks a differential drive's pose from the distance each side has travelled
+///
+/// The heading source is trusted over the encoders for rotation, and is offset so it doesn't need
+/// to be zeroed when the pose is reset.
+#[derive(Clone, Copy, Debug)]
+pub struct DifferentialDriveOdometry {
+    kinematics: DifferentialDriveKinematics,
+    pose: Pose2d,
+    heading_offset: Rotation2d,
+    previous_heading: Rotation2d,
+    previous_left: Length,
+    previous_right: Length,
+}
+
+impl DifferentialDriveOdometry {
+    pub fn new(
+        kinematics: DifferentialDriveKinematics,
+        heading: Rotation2d,
+        left: Length,
+        right: Length,
+        initial: Pose2d,
+    ) -> Self {
+        DifferentialDriveOdometry {
+            kinematics,
+            pose: initial,
+            heading_offset: initial.rotation - heading,
+            previous_heading: initial.rotation,
+            previous_left: left,
+            previous_right: right,
+        }
+    }
+
+    pub fn pose(&self) -> Pose2d {
+        self.pose
+    }
+
+    pub fn reset(&mut self, pose: Pose2d, heading: Rotation2d, left: Length, right: Length) {
+        *self = Self::new(self.kinematics, heading, left, right, pose);
+    }
+
+    /// Integrates the change since the last update, taking total distances for each side
+    pub fn update(&mut self, heading: Rotation2d, left: Length, right: Length) -> Pose2d {
+        let encoder_twist = self
+            .kinematics
+            .to_twist(left - self.previous_left, right - self.previous_right);
+        let heading = heading + self.heading_offset;
+        let twist = Twist2d::new(
+            encoder_twist.dx,
+            encoder_twist.dy,
+            (heading - self.previous_heading).angle(),
+        );
+
+        self.pose = Pose2d::new(self.pose.exp(twist).translation, heading);
+        self.previous_heading = heading;
+        self.previous_left = left;
+        self.previous_right = right;
+        self.pose
+    }
+}
+
+/// Tracks a swerve drive's pose from each module's distance and direction
+///
+/// Like [`DifferentialDriveOdometry`], rotation comes from the heading source.
+#[derive(Clone, Debug)]
+pub struct SwerveDriveOdometry {
+    kinematics: SwerveDriveKinematics,
+    pose: Pose2d,
+    heading_offset: Rotation2d,
+    previous_heading: Rotation2d,
+    previous_positions: Vec<SwerveModulePosition>,
+}
+
+impl SwerveDriveOdometry {
+    pub fn new(
+        kinematics: SwerveDriveKinematics,
+        heading: Rotation2d,
+        positions: &[SwerveModulePosition],
+        initial: Pose2d,
+    ) -> Self {
+        assert_eq!(
+            positions.len(),
+            kinematics.modules().len(),
+            "expected one position per module"
+        );
+        SwerveDriveOdometry {
+            kinematics,
+            pose: initial,
+            heading_offset: initial.rotation - heading,
+            previous_heading: initial.rotation,
+            previous_positions: positions.to_vec(),
+        }
+    }
+
+    pub fn pose(&self) -> Pose2d {
+        self.pose
+    }
+
+    pub fn reset(&mut self, pose: Pose2d, heading: Rotation2d, positions: &[SwerveModulePosition]) {
+        *self = Self::new(self.kinematics.clone(), heading, positions, pose);
+    }
+
+    /// Integrates the change since the last update, taking total distances for each module
+    pub fn update(&mut self, heading: Rotation2d, positions: &[SwerveModulePosition]) -> Pose2d {
+        let deltas: Vec<SwerveModulePosition> = positions
+            .iter()
+            .zip(&self.previous_positions)
+            .map(|(current, previous)| {
+                SwerveModulePosition::new(current.distance - previous.distance, current.angle)
+            })
+            .collect();
+        let module_twist = self.kinematics.to_twist(&deltas);
+        let heading = heading + self.heading_offset;
+        let twist = Twist2d::new(
+            module_twist.dx,
+            module_twist.dy,
+            (heading - self.previous_heading).angle(),
+        );
+
+        self.pose = Pose2d::new(self.pose.exp(twist).translation, heading);
+        self.previous_heading = heading;
+        self.previous_positions = positions.to_vec();
+        self.pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Translation2d;
+    use crate::testing::m;
+    use std::f64::consts::PI;
+    use tetanus_core::testing::assert_close;
+    use uom::si::angle::{degree, radian};
+    use uom::si::length::meter;
+
+    fn heading(degrees: f64) -> Rotation2d {
+        Rotation2d::new(Angle::new::<degree>(degrees))
+    }
+
+    fn odometry(initial: Pose2d) -> DifferentialDriveOdometry {
+        let kinematics = DifferentialDriveKinematics::new(m(0.5));
+        DifferentialDriveOdometry::new(kinematics, heading(0.0), m(0.0), m(0.0), initial)
+    }
+
+    #[test]
+    fn straight_line_follows_heading() {
+        let mut odometry = odometry(Pose2d::from_xy_angle(
+            m(1.0),
+            m(1.0),
+            Angle::new::<degree>(90.0),
+        ));
+
+        let pose = odometry.update(heading(0.0), m(2.0), m(2.0));
+
+        assert_close(pose.x().get::<meter>(), 1.0, 1e-9);
+        assert_close(pose.y().get::<meter>(), 3.0, 1e-9);
+        assert_close(pose.rotation.angle().get::<degree>(), 90.0, 1e-9);
+    }
+
+    #[test]
+    fn arc_integrates_in_small_steps() {
+        let mut odometry = odometry(Pose2d::default());
+        let radius = 1.0;
+        let track = 0.5;
+        let steps = 50;
+
+        let mut pose = Pose2d::default();
+        for i in 1..=steps {
+            let theta = PI / 2.0 * i as f64 / steps as f64;
+            pose = odometry.update(
+                Rotation2d::new(Angle::new::<radian>(theta)),
+                m((radius - track / 2.0) * theta),
+                m((radius + track / 2.0) * theta),
+            );
+        }
+
+        assert_close(pose.x().get::<meter>(), 1.0, 1e-9);
+        assert_close(pose.y().get::<meter>(), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn reset_restarts_from_new_pose() {
+        let mut odometry = odometry(Pose2d::default());
+        odometry.update(heading(30.0), m(5.0), m(4.0));
+
+        odometry.reset(Pose2d::default(), heading(30.0), m(5.0), m(4.0));
+        let pose = odometry.update(heading(30.0), m(6.0), m(5.0));
+
+        assert_close(pose.x().get::<meter>(), 1.0, 1e-9);
+        assert_close(pose.y().get::<meter>(), 0.0, 1e-9);
+        assert_close(pose.rotation.radians(), 0.0, 1e-9);
+    }
+
+    fn swerve_odometry() -> SwerveDriveOdometry {
+        let corner = |x: f64, y: f64| Translation2d::new(m(x), m(y));
+        let kinematics = SwerveDriveKinematics::new(vec![
+            corner(0.3, 0.3),
+            corner(0.3, -0.3),
+            corner(-0.3, 0.3),
+            corner(-0.3, -0.3),
+        ]);
+        let start = [SwerveModulePosition::default(); 4];
+        SwerveDriveOdometry::new(kinematics, heading(0.0), &start, Pose2d::default())
+    }
+
+    #[test]
+    fn swerve_strafes_while_holding_heading() {
+        let mut odometry = swerve_odometry();
+
+        let positions = [SwerveModulePosition::new(m(2.0), heading(-90.0)); 4];
+        let pose = odometry.update(heading(0.0), &positions);
+
+        assert_close(pose.x().get::<meter>(), 0.0, 1e-9);
+        assert_close(pose.y().get::<meter>(), -2.0, 1e-9);
+
+        let positions = [SwerveModulePosition::new(m(3.0), heading(0.0)); 4];
+        let pose = odometry.update(heading(0.0), &positions);
+
+        assert_close(pose.x().get::<meter>(), 1.0, 1e-9);
+        assert_close(pose.y().get::<meter>(), -2.0, 1e-9);
+    }
+}
@@ -0,0 +1,569 @@
+//! Loading paths drawn in PathWeaver and PathPlanner
+//!
+//! Path files are deployed alongside the robot code, so on the robot they are found with
+//! [`deploy_path`]. Everything loads from any path, which is how the tests use them.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+use tetanus_core::rate::Interpolate;
+use uom::si::acceleration::meter_per_second_squared;
+use uom::si::angle::{degree, radian};
+use uom::si::curvature::radian_per_meter;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::time::second;
+use uom::si::velocity::meter_per_second;
+
+use crate::geometry::{Pose2d, Rotation2d, Translation2d};
+use crate::spline::{ControlVector, Spline};
+use crate::trajectory::{self, Trajectory, TrajectoryConfig, TrajectoryState};
+
+/// Where files in the project's `deploy` directory end up on the roboRIO
+pub const DEPLOY_DIRECTORY: &str = "/home/lvuser/deploy";
+
+/// Full path to a file deployed to the roboRIO, e.g. `deploy_path("pathplanner/Auto.path")`
+pub fn deploy_path(name: impl AsRef<Path>) -> PathBuf {
+    Path::new(DEPLOY_DIRECTORY).join(name)
+}
+
+fn read(path: &Path, kind: &str) -> Result<String> {
+    fs::read_to_string(path).with_context(|| format!("couldn't read {} {}", kind, path.display()))
+}
+
+fn meters(x: f64, y: f64) -> Translation2d {
+    Translation2d::new(Length::new::<meter>(x), Length::new::<meter>(y))
+}
+
+/// One row of a PathWeaver `.path` file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PathWeaverWaypoint {
+    #[serde(rename = "X")]
+    pub x: f64,
+    #[serde(rename = "Y")]
+    pub y: f64,
+    #[serde(rename = "Tangent X")]
+    pub tangent_x: f64,
+    #[serde(rename = "Tangent Y")]
+    pub tangent_y: f64,
+    /// Whether the heading here was set by hand, rather than left for the spline to choose
+    #[serde(rename = "Fixed Theta")]
+    pub fixed_heading: bool,
+    #[serde(rename = "Reversed")]
+    pub reversed: bool,
+    #[serde(rename = "Name")]
+    pub name: String,
+}
+
+impl PathWeaverWaypoint {
+    /// Tangent lengths only shape the path in PathWeaver, so just the direction is kept
+    pub fn pose(&self) -> Pose2d {
+        Pose2d::new(
+            meters(self.x, self.y),
+            Rotation2d::from_components(self.tangent_x, self.tangent_y),
+        )
+    }
+}
+
+/// A path drawn in PathWeaver, in meters with PathWeaver's field coordinates
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathWeaverPath {
+    pub waypoints: Vec<PathWeaverWaypoint>,
+}
+
+impl PathWeaverPath {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&read(path, "PathWeaver path")?)
+            .with_context(|| format!("invalid PathWeaver path {}", path.display()))
+    }
+
+    /// Parses the CSV contents of a `.path` file
+    pub fn parse(text: &str) -> Result<Self> {
+        let waypoints = csv::Reader::from_reader(text.as_bytes())
+            .deserialize()
+            .collect::<Result<Vec<PathWeaverWaypoint>, _>>()?;
+        if waypoints.len() < 2 {
+            bail!("a path needs at least two waypoints");
+        }
+        if waypoints
+            .iter()
+            .any(|w| w.tangent_x == 0.0 && w.tangent_y == 0.0)
+        {
+            bail!("every waypoint needs a tangent");
+        }
+        Ok(PathWeaverPath { waypoints })
+    }
+
+    /// Quintic splines through every heading if any were fixed in between, otherwise clamped
+    /// cubic splines that only keep the start and end headings
+    pub fn generate(&self, mut config: TrajectoryConfig) -> Result<Trajectory> {
+        config.reversed = self.waypoints[0].reversed;
+        let interior = &self.waypoints[1..self.waypoints.len() - 1];
+        if interior.iter().any(|w| w.fixed_heading) {
+            let poses: Vec<Pose2d> = self.waypoints.iter().map(|w| w.pose()).collect();
+            trajectory::generate_quintic(&poses, &config)
+        } else {
+            let interior: Vec<Translation2d> = interior.iter().map(|w| meters(w.x, w.y)).collect();
+            trajectory::generate_clamped_cubic(
+                self.waypoints[0].pose(),
+                &interior,
+                self.waypoints[self.waypoints.len() - 1].pose(),
+                &config,
+            )
+        }
+    }
+}
+
+/// A trajectory PathWeaver has already generated, from a `.wpilib.json` file in its output
+pub fn load_wpilib_trajectory(path: impl AsRef<Path>) -> Result<Trajectory> {
+    let path = path.as_ref();
+    parse_wpilib_trajectory(&read(path, "WPILib trajectory")?)
+        .with_context(|| format!("invalid WPILib trajectory {}", path.display()))
+}
+
+pub fn parse_wpilib_trajectory(text: &str) -> Result<Trajectory> {
+    #[derive(Deserialize)]
+    struct Rotation {
+        radians: f64,
+    }
+    #[derive(Deserialize)]
+    struct Point {
+        x: f64,
+        y: f64,
+    }
+    #[derive(Deserialize)]
+    struct Pose {
+        translation: Point,
+        rotation: Rotation,
+    }
+    #[derive(Deserialize)]
+    struct State {
+        time: f64,
+        velocity: f64,
+        acceleration: f64,
+        pose: Pose,
+        curvature: f64,
+    }
+
+    let states: Vec<State> = serde_json::from_str(text)?;
+    if states.is_empty() {
+        bail!("trajectory has no states");
+    }
+    if states.windows(2).any(|pair| pair[1].time < pair[0].time) {
+        bail!("trajectory states are out of order");
+    }
+    Ok(Trajectory::new(
+        states
+            .into_iter()
+            .map(|s| TrajectoryState {
+                time: Time::new::<second>(s.time),
+                velocity: Velocity::new::<meter_per_second>(s.velocity),
+                acceleration: Acceleration::new::<meter_per_second_squared>(s.acceleration),
+                pose: Pose2d::new(
+                    meters(s.pose.translation.x, s.pose.translation.y),
+                    Rotation2d::new(Angle::new::<radian>(s.pose.rotation.radians)),
+                ),
+                curvature: Curvature::new::<radian_per_meter>(s.curvature),
+            })
+            .collect(),
+    ))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub struct PathPlannerPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathPlannerWaypoint {
+    pub anchor_point: PathPlannerPoint,
+    pub prev_control: Option<PathPlannerPoint>,
+    pub next_control: Option<PathPlannerPoint>,
+    /// Which way a swerve drive should face here, in degrees
+    #[serde(default)]
+    pub holonomic_angle: f64,
+    #[serde(default)]
+    pub is_reversal: bool,
+    /// Top speed from here to the next waypoint, in meters per second
+    #[serde(default)]
+    pub vel_override: Option<f64>,
+    #[serde(default)]
+    pub is_stop_point: bool,
+}
+
+/// Named events to run once the robot gets to `position`
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+pub struct PathPlannerMarker {
+    /// Waypoint index, with the fraction of the way to the next waypoint after the point
+    pub position: f64,
+    pub names: Vec<String>,
+}
+
+/// A path drawn in PathPlanner, from a `.path` file
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathPlannerPath {
+    pub waypoints: Vec<PathPlannerWaypoint>,
+    /// In meters per second, replacing the config's if set
+    #[serde(default)]
+    pub max_velocity: Option<f64>,
+    /// In meters per second squared, replacing the config's if set
+    #[serde(default)]
+    pub max_acceleration: Option<f64>,
+    #[serde(default)]
+    pub is_reversed: Option<bool>,
+    #[serde(default)]
+    pub markers: Vec<PathPlannerMarker>,
+}
+
+impl PathPlannerPath {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        Self::parse(&read(path, "PathPlanner path")?)
+            .with_context(|| format!("invalid PathPlanner path {}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let path: PathPlannerPath = serde_json::from_str(text)?;
+        path.validate()?;
+        Ok(path)
+    }
+
+    fn validate(&self) -> Result<()> {
+        let count = self.waypoints.len();
+        if count < 2 {
+            bail!("a path needs at least two waypoints");
+        }
+        for (i, waypoint) in self.waypoints.iter().enumerate() {
+            if i + 1 < count && waypoint.next_control.is_none() {
+                bail!("waypoint {} has no next control point", i);
+            }
+            if i > 0 && waypoint.prev_control.is_none() {
+                bail!("waypoint {} has no previous control point", i);
+            }
+            if i > 0 && i + 1 < count && (waypoint.is_reversal || waypoint.is_stop_point) {
+                bail!(
+                    "waypoint {} stops the robot, which isn't supported; split the path there",
+                    i
+                );
+            }
+            if matches!(waypoint.vel_override, Some(v) if v <= 0.0) {
+                bail!("waypoint {} has a velocity override that isn't positive", i);
+            }
+        }
+        for marker in &self.markers {
+            if !(0.0..=(count - 1) as f64).contains(&marker.position) {
+                bail!(
+                    "marker {:?} at {} is off the end of the path",
+                    marker.names,
+                    marker.position
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Cubic Bézier curves between consecutive waypoints, as drawn
+    fn splines(&self) -> Vec<Spline> {
+        let control =
+            |anchor: PathPlannerPoint, tangent_from: PathPlannerPoint, sign: f64| ControlVector {
+                x: [anchor.x, sign * 3.0 * (anchor.x - tangent_from.x), 0.0],
+                y: [anchor.y, sign * 3.0 * (anchor.y - tangent_from.y), 0.0],
+            };
+        self.waypoints
+            .windows(2)
+            .map(|pair| {
+                Spline::cubic_hermite(
+                    control(pair[0].anchor_point, pair[0].next_control.unwrap(), -1.0),
+                    control(pair[1].anchor_point, pair[1].prev_control.unwrap(), 1.0),
+                )
+            })
+            .collect()
+    }
+
+    pub fn generate(&self, mut config: TrajectoryConfig) -> Result<PathPlannerTrajectory> {
+        self.validate()?;
+        if let Some(max) = self.max_velocity {
+            config.max_velocity = Velocity::new::<meter_per_second>(max);
+        }
+        if let Some(max) = self.max_acceleration {
+            config.max_acceleration = Acceleration::new::<meter_per_second_squared>(max);
+        }
+        if let Some(reversed) = self.is_reversed {
+            config.reversed = reversed;
+        }
+
+        let splines = self.splines();
+        let limits: Vec<Option<Velocity>> = self
+            .waypoints
+            .iter()
+            .map(|w| w.vel_override.map(Velocity::new::<meter_per_second>))
+            .collect();
+        let (trajectory, starts) =
+            trajectory::generate_with_speed_limits(&splines, &limits, &config)?;
+        let states = trajectory.states();
+
+        let mut rotations: Vec<(Time, Rotation2d)> = starts
+            .iter()
+            .zip(&self.waypoints)
+            .map(|(&start, w)| (states[start].time, holonomic_rotation(w)))
+            .collect();
+        rotations.push((
+            trajectory.total_time(),
+            holonomic_rotation(self.waypoints.last().unwrap()),
+        ));
+
+        let mut markers: Vec<EventMarker> = self
+            .markers
+            .iter()
+            .map(|marker| {
+                let segment = (marker.position.floor() as usize).min(splines.len() - 1);
+                let target = splines[segment]
+                    .point(marker.position - segment as f64)
+                    .pose
+                    .translation;
+                let end = starts.get(segment + 1).copied().unwrap_or(states.len() - 1);
+                // Segments are monotonic in time, so the nearest state within the right one
+                // gives when the robot passes the marker
+                let nearest = states[starts[segment]..=end]
+                    .iter()
+                    .min_by(|a, b| {
+                        let distance = |s: &TrajectoryState| s.pose.translation.distance(target);
+                        distance(a).partial_cmp(&distance(b)).unwrap()
+                    })
+                    .unwrap();
+                EventMarker {
+                    time: nearest.time,
+                    names: marker.names.clone(),
+                }
+            })
+            .collect();
+        markers.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap());
+
+        Ok(PathPlannerTrajectory {
+            trajectory,
+            markers,
+            rotations,
+        })
+    }
+}
+
+fn holonomic_rotation(waypoint: &PathPlannerWaypoint) -> Rotation2d {
+    Rotation2d::new(Angle::new::<degree>(waypoint.holonomic_angle))
+}
+
+/// Named events along a trajectory
+#[derive(Clone, Debug, PartialEq)]
+pub struct EventMarker {
+    pub time: Time,
+    pub names: Vec<String>,
+}
+
+/// A PathPlanner path turned into a trajectory, along with its events and swerve headings
+#[derive(Clone, Debug, PartialEq)]
+pub struct PathPlannerTrajectory {
+    pub trajectory: Trajectory,
+    /// Sorted by time
+    pub markers: Vec<EventMarker>,
+    /// Heading at each waypoint, by when the robot gets there
+    rotations: Vec<(Time, Rotation2d)>,
+}
+
+impl PathPlannerTrajectory {
+    /// Which way a swerve drive should face at `time`, turning evenly between waypoints
+    pub fn rotation(&self, time: Time) -> Rotation2d {
+        let after = self.rotations.partition_point(|(t, _)| *t < time);
+        if after == 0 {
+            return self.rotations[0].1;
+        }
+        if after == self.rotations.len() {
+            return self.rotations[after - 1].1;
+        }
+        let ((start, from), (end, to)) = (self.rotations[after - 1], self.rotations[after]);
+        let span = (end - start).get::<second>();
+        if span <= 0.0 {
+            return to;
+        }
+        from.lerp(to, (time - start).get::<second>() / span)
+    }
+
+    /// Markers passed after `after` and up to and including `until`, for firing events each loop
+    pub fn markers_between(&self, after: Time, until: Time) -> impl Iterator<Item = &EventMarker> {
+        self.markers
+            .iter()
+            .filter(move |m| m.time > after && m.time <= until)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::assert_position;
+    use tetanus_core::testing::assert_close;
+
+    fn config() -> TrajectoryConfig {
+        TrajectoryConfig::new(
+            Velocity::new::<meter_per_second>(3.0),
+            Acceleration::new::<meter_per_second_squared>(2.0),
+        )
+    }
+
+    /// File holding `contents` to load from, removed when dropped
+    struct TempFile(PathBuf);
+
+    impl TempFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let name = format!("tetanus-paths-{}-{}", std::process::id(), name);
+            let path = std::env::temp_dir().join(name);
+            fs::write(&path, contents).unwrap();
+            TempFile(path)
+        }
+    }
+
+    impl Drop for TempFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    const PATHWEAVER: &str = "\
+X,Y,Tangent X,Tangent Y,Fixed Theta,Reversed,Name
+1.0,1.0,2.0,0.0,true,false,
+3.0,2.0,1.0,1.0,false,false,Middle
+5.0,2.0,2.0,0.0,true,false,
+";
+
+    const PATHPLANNER: &str = r#"{
+      "waypoints": [
+        {
+          "anchorPoint": {"x": 0.0, "y": 0.0},
+          "prevControl": null,
+          "nextControl": {"x": 1.0, "y": 0.0},
+          "holonomicAngle": 0.0,
+          "isReversal": false,
+          "velOverride": null,
+          "isLocked": false
+        },
+        {
+          "anchorPoint": {"x": 3.0, "y": 1.0},
+          "prevControl": {"x": 2.0, "y": 1.0},
+          "nextControl": {"x": 4.0, "y": 1.0},
+          "holonomicAngle": 90.0,
+          "isReversal": false,
+          "velOverride": 1.0,
+          "isLocked": false
+        },
+        {
+          "anchorPoint": {"x": 6.0, "y": 1.0},
+          "prevControl": {"x": 5.0, "y": 1.0},
+          "nextControl": null,
+          "holonomicAngle": 90.0,
+          "isReversal": false,
+          "velOverride": null,
+          "isLocked": false
+        }
+      ],
+      "maxVelocity": 2.5,
+      "maxAcceleration": null,
+      "isReversed": null,
+      "markers": [{"position": 1.5, "names": ["intake", "shoot"]}]
+    }"#;
+
+    #[test]
+    fn pathweaver_path_goes_through_waypoints() {
+        let file = TempFile::new("pathweaver.path", PATHWEAVER);
+        let path = PathWeaverPath::load(&file.0).unwrap();
+
+        assert_eq!(path.waypoints.len(), 3);
+        assert_eq!(path.waypoints[1].name, "Middle");
+        let trajectory = path.generate(config()).unwrap();
+        assert_position(trajectory.initial_pose(), 1.0, 1.0, 1e-6);
+        assert_position(trajectory.states().last().unwrap().pose, 5.0, 2.0, 1e-6);
+        assert!(trajectory.states().iter().any(|s| s
+            .pose
+            .translation
+            .distance(meters(3.0, 2.0))
+            .get::<meter>()
+            < 1e-6));
+    }
+
+    #[test]
+    fn wpilib_trajectory_keeps_states() {
+        let json = r#"[
+          {"acceleration": 1.0, "curvature": 0.0, "time": 0.0, "velocity": 0.0,
+           "pose": {"rotation": {"radians": 0.0}, "translation": {"x": 1.0, "y": 2.0}}},
+          {"acceleration": 0.0, "curvature": 0.5, "time": 1.0, "velocity": 1.0,
+           "pose": {"rotation": {"radians": 1.0}, "translation": {"x": 1.5, "y": 2.0}}}
+        ]"#;
+
+        let file = TempFile::new("output.wpilib.json", json);
+        let trajectory = load_wpilib_trajectory(&file.0).unwrap();
+
+        assert_eq!(trajectory.states().len(), 2);
+        assert_close(trajectory.total_time().get::<second>(), 1.0, 1e-9);
+        let last = trajectory.states()[1];
+        assert_position(last.pose, 1.5, 2.0, 1e-6);
+        assert_close(last.pose.rotation.radians(), 1.0, 1e-9);
+        assert_close(last.curvature.get::<radian_per_meter>(), 0.5, 1e-9);
+    }
+
+    #[test]
+    fn pathplanner_path_has_overrides_markers_and_rotations() {
+        let file = TempFile::new("pathplanner.path", PATHPLANNER);
+        let path = PathPlannerPath::load(&file.0).unwrap();
+        let generated = path.generate(config()).unwrap();
+        let trajectory = &generated.trajectory;
+
+        assert_position(trajectory.initial_pose(), 0.0, 0.0, 1e-6);
+        assert_position(trajectory.states().last().unwrap().pose, 6.0, 1.0, 1e-6);
+        for state in trajectory.states() {
+            let speed = state.velocity.get::<meter_per_second>();
+            assert!(speed <= 2.5 + 1e-9);
+            if state.pose.x().get::<meter>() > 3.0 + 1e-6 {
+                assert!(speed <= 1.0 + 1e-9);
+            }
+        }
+
+        assert_eq!(generated.markers.len(), 1);
+        let marker = &generated.markers[0];
+        assert_eq!(marker.names, ["intake", "shoot"]);
+        let at_marker = trajectory.sample(marker.time).pose;
+        assert_close(at_marker.x().get::<meter>(), 4.5, 0.1);
+        let fired = |after: Time, until: Time| generated.markers_between(after, until).count();
+        assert_eq!(fired(Time::default(), marker.time), 1);
+        assert_eq!(fired(marker.time, trajectory.total_time()), 0);
+
+        let rotation = |t: Time| generated.rotation(t).angle().get::<degree>();
+        assert_close(rotation(Time::default()), 0.0, 1e-9);
+        assert_close(rotation(trajectory.total_time()), 90.0, 1e-9);
+        let middle = trajectory.total_time() / 4.0;
+        assert!(rotation(middle) > 0.0 && rotation(middle) < 90.0);
+    }
+
+    #[test]
+    fn malformed_files_are_explained() {
+        let missing = PathPlannerPath::load("/nonexistent/Auto.path").unwrap_err();
+        assert!(format!("{:#}", missing).contains("/nonexistent/Auto.path"));
+
+        let broken = PATHPLANNER.replace(
+            r#""nextControl": {"x": 1.0, "y": 0.0}"#,
+            "\"nextControl\": null",
+        );
+        let error = PathPlannerPath::parse(&broken).unwrap_err();
+        assert_eq!(error.to_string(), "waypoint 0 has no next control point");
+
+        let error = PathPlannerPath::parse("{\"waypoints\": [").unwrap_err();
+        assert!(error.to_string().contains("EOF"));
+
+        let error = PathWeaverPath::parse(&PATHWEAVER.replace("true", "yes")).unwrap_err();
+        assert!(error.to_string().contains("line: 2"));
+
+        let error = parse_wpilib_trajectory("[]").unwrap_err();
+        assert_eq!(error.to_string(), "trajectory has no states");
+    }
+}
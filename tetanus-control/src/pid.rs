@@ -0,0 +1,434 @@
+use std::marker::PhantomData;
+
+use tetanus_core::processor::Processor;
+use uom::si::f64::*;
+use uom::si::time::second;
+
+use crate::profile::{MotionProfile, ProfileState, TrapezoidConstraints, TrapezoidProfile};
+use crate::units::{input_modulus, Motion, SiValue};
+
+/// PID controller run at a fixed period, from a measured input `I` to an output `O`
+///
+/// Gains convert base SI units of the error to base SI units of the output, e.g. radians per
+/// second per radian for heading hold that outputs an angular velocity.
+pub struct PidController<I, O> {
+    kp: f64,
+    ki: f64,
+    kd: f64,
+    period: Time,
+    setpoint: I,
+    /// Range of a wrap-around input such as an angle
+    continuous: Option<(f64, f64)>,
+    integrator_range: (f64, f64),
+    izone: f64,
+    derivative_time_constant: Time,
+    error_tolerance: f64,
+    derivative_tolerance: f64,
+    error: f64,
+    derivative: f64,
+    /// Error integrated over time, not yet multiplied by `ki`
+    integral: f64,
+    prev_error: Option<f64>,
+    output: PhantomData<O>,
+}
+
+impl<I: SiValue, O: SiValue> PidController<I, O> {
+    pub fn new(kp: f64, ki: f64, kd: f64, period: Time) -> Self {
+        PidController {
+            kp,
+            ki,
+            kd,
+            period,
+            setpoint: I::from_si(0.0),
+            continuous: None,
+            integrator_range: (f64::NEG_INFINITY, f64::INFINITY),
+            izone: f64::INFINITY,
+            derivative_time_constant: Time::default(),
+            error_tolerance: 0.05,
+            derivative_tolerance: f64::INFINITY,
+            error: 0.0,
+            derivative: 0.0,
+            integral: 0.0,
+            prev_error: None,
+            output: PhantomData,
+        }
+    }
+
+    pub fn set_gains(&mut self, kp: f64, ki: f64, kd: f64) {
+        self.kp = kp;
+        self.ki = ki;
+        self.kd = kd;
+    }
+
+    pub fn setpoint(&self) -> I {
+        self.setpoint
+    }
+
+    pub fn set_setpoint(&mut self, setpoint: I) {
+        self.setpoint = setpoint;
+    }
+
+    /// Treats `min` and `max` as the same point, so the error always takes the shorter way around
+    pub fn enable_continuous_input(&mut self, min: I, max: I) {
+        self.continuous = Some((min.si(), max.si()));
+    }
+
+    pub fn disable_continuous_input(&mut self) {
+        self.continuous = None;
+    }
+
+    /// Limits how much the integral term can contribute to the output
+    pub fn set_integrator_range(&mut self, min: O, max: O) {
+        self.integrator_range = (min.si(), max.si());
+    }
+
+    /// Only accumulates the integral while the error is within `izone`, resetting it otherwise
+    pub fn set_izone(&mut self, izone: I) {
+        self.izone = izone.si().abs();
+    }
+
+    /// Low-pass filters the derivative with the given time constant
+    pub fn set_derivative_filter(&mut self, time_constant: Time) {
+        self.derivative_time_constant = time_constant;
+    }
+
+    pub fn set_tolerance(&mut self, error: I) {
+        self.error_tolerance = error.si().abs();
+    }
+
+    /// Largest rate of change of the error, in base SI units per second, that counts as settled
+    pub fn set_derivative_tolerance(&mut self, per_second: f64) {
+        self.derivative_tolerance = per_second.abs();
+    }
+
+    pub fn error(&self) -> I {
+        I::from_si(self.error)
+    }
+
+    /// Whether the last calculated error and its rate of change are within tolerance
+    pub fn at_setpoint(&self) -> bool {
+        self.prev_error.is_some()
+            && self.error.abs() <= self.error_tolerance
+            && self.derivative.abs() <= self.derivative_tolerance
+    }
+
+    pub fn calculate(&mut self, measurement: I) -> O {
+        let dt = self.period.get::<second>();
+        let error = match self.continuous {
+            Some((min, max)) => {
+                let half_range = (max - min) / 2.0;
+                input_modulus(
+                    self.setpoint.si() - measurement.si(),
+                    -half_range,
+                    half_range,
+                )
+            }
+            None => self.setpoint.si() - measurement.si(),
+        };
+
+        let raw_derivative = self.prev_error.map_or(0.0, |prev| (error - prev) / dt);
+        let tau = self.derivative_time_constant.get::<second>();
+        self.derivative = if tau > 0.0 && self.prev_error.is_some() {
+            self.derivative + dt / (tau + dt) * (raw_derivative - self.derivative)
+        } else {
+            raw_derivative
+        };
+
+        if error.abs() > self.izone {
+            self.integral = 0.0;
+        } else {
+            self.integral += error * dt;
+            if self.ki != 0.0 {
+                let (min, max) = self.integrator_range;
+                let (a, b) = (min / self.ki, max / self.ki);
+                self.integral = self.integral.clamp(a.min(b), a.max(b));
+            }
+        }
+
+        self.error = error;
+        self.prev_error = Some(error);
+        O::from_si(self.kp * error + self.ki * self.integral + self.kd * self.derivative)
+    }
+
+    /// Like [`PidController::calculate`], but first changes the setpoint
+    pub fn calculate_to(&mut self, measurement: I, setpoint: I) -> O {
+        self.set_setpoint(setpoint);
+        self.calculate(measurement)
+    }
+
+    /// Clears the integral and derivative history
+    pub fn reset(&mut self) {
+        self.error = 0.0;
+        self.derivative = 0.0;
+        self.integral = 0.0;
+        self.prev_error = None;
+    }
+}
+
+impl<I, O> Processor for PidController<I, O>
+where
+    I: SiValue + Send + Sync,
+    O: SiValue + Send + Sync,
+{
+    type In = I;
+    type Out = O;
+
+    fn process(&mut self, msg: Self::In) -> Self::Out {
+        self.calculate(msg)
+    }
+}
+
+/// PID controller that chases a trapezoid profile towards its goal, instead of jumping straight to
+/// it
+///
+/// The output is only the PID correction; add the [`ProfiledPidController::setpoint`] velocity as
+/// feedforward if needed.
+pub struct ProfiledPidController<P: Motion> {
+    pid: PidController<P, P::Velocity>,
+    constraints: TrapezoidConstraints<P>,
+    period: Time,
+    goal: ProfileState<P>,
+    setpoint: ProfileState<P>,
+    continuous: Option<(f64, f64)>,
+}
+
+impl<P: Motion> ProfiledPidController<P> {
+    pub fn new(
+        kp: f64,
+        ki: f64,
+        kd: f64,
+        constraints: TrapezoidConstraints<P>,
+        period: Time,
+    ) -> Self {
+        let rest = ProfileState::new(P::from_si(0.0), P::Velocity::from_si(0.0));
+        ProfiledPidController {
+            pid: PidController::new(kp, ki, kd, period),
+            constraints,
+            period,
+            goal: rest,
+            setpoint: rest,
+            continuous: None,
+        }
+    }
+
+    pub fn pid(&mut self) -> &mut PidController<P, P::Velocity> {
+        &mut self.pid
+    }
+
+    pub fn goal(&self) -> ProfileState<P> {
+        self.goal
+    }
+
+    pub fn set_goal(&mut self, goal: P) {
+        self.goal = ProfileState::new(goal, P::Velocity::from_si(0.0));
+    }
+
+    pub fn setpoint(&self) -> ProfileState<P> {
+        self.setpoint
+    }
+
+    pub fn set_constraints(&mut self, constraints: TrapezoidConstraints<P>) {
+        self.constraints = constraints;
+    }
+
+    pub fn enable_continuous_input(&mut self, min: P, max: P) {
+        self.continuous = Some((min.si(), max.si()));
+        self.pid.enable_continuous_input(min, max);
+    }
+
+    pub fn disable_continuous_input(&mut self) {
+        self.continuous = None;
+        self.pid.disable_continuous_input();
+    }
+
+    pub fn set_tolerance(&mut self, error: P) {
+        self.pid.set_tolerance(error);
+    }
+
+    /// Whether the profile has reached the goal and the measurement is within tolerance of it
+    pub fn at_goal(&self) -> bool {
+        self.setpoint.position == self.goal.position
+            && self.setpoint.velocity == self.goal.velocity
+            && self.pid.at_setpoint()
+    }
+
+    pub fn calculate(&mut self, measurement: P) -> P::Velocity {
+        if let Some((min, max)) = self.continuous {
+            // Move the goal and setpoint next to the measurement so the profile goes the short way
+            let half_range = (max - min) / 2.0;
+            let nearest = |position: P| {
+                P::from_si(
+                    measurement.si()
+                        + input_modulus(position.si() - measurement.si(), -half_range, half_range),
+                )
+            };
+            self.goal.position = nearest(self.goal.position);
+            self.setpoint.position = nearest(self.setpoint.position);
+        }
+
+        let profile = TrapezoidProfile::new(self.constraints, self.setpoint, self.goal);
+        self.setpoint = profile.state_at(self.period);
+        self.pid.calculate_to(measurement, self.setpoint.position)
+    }
+
+    /// Like [`ProfiledPidController::calculate`], but first changes the goal
+    pub fn calculate_to(&mut self, measurement: P, goal: P) -> P::Velocity {
+        self.set_goal(goal);
+        self.calculate(measurement)
+    }
+
+    /// Restarts the profile from `measurement` at rest
+    pub fn reset(&mut self, measurement: P) {
+        self.pid.reset();
+        self.setpoint = ProfileState::new(measurement, P::Velocity::from_si(0.0));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+    use tetanus_core::testing::{assert_close, Probe};
+    use uom::si::angle::degree;
+    use uom::si::angular_acceleration::radian_per_second_squared;
+    use uom::si::angular_velocity::radian_per_second;
+    use uom::si::time::millisecond;
+
+    fn period() -> Time {
+        Time::new::<millisecond>(20.0)
+    }
+
+    #[test]
+    fn proportional_output_has_units() {
+        let mut pid: PidController<Angle, AngularVelocity> =
+            PidController::new(2.0, 0.0, 0.0, period());
+        pid.set_setpoint(Angle::new::<degree>(90.0));
+
+        let output = pid.calculate(Angle::new::<degree>(0.0));
+
+        assert_close(
+            output.get::<radian_per_second>(),
+            std::f64::consts::PI,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn continuous_input_takes_the_short_way_around() {
+        let mut pid: PidController<Angle, f64> = PidController::new(1.0, 0.0, 0.0, period());
+        pid.enable_continuous_input(Angle::new::<degree>(0.0), Angle::new::<degree>(360.0));
+
+        pid.calculate_to(Angle::new::<degree>(350.0), Angle::new::<degree>(10.0));
+        assert_close(pid.error().get::<degree>(), 20.0, 1e-9);
+
+        pid.calculate_to(Angle::new::<degree>(10.0), Angle::new::<degree>(350.0));
+        assert_close(pid.error().get::<degree>(), -20.0, 1e-9);
+    }
+
+    #[test]
+    fn integrator_range_clamps_integral_term() {
+        let mut pid: PidController<f64, f64> = PidController::new(0.0, 10.0, 0.0, period());
+        pid.set_integrator_range(-0.5, 0.5);
+        pid.set_setpoint(1.0);
+
+        for _ in 0..100 {
+            pid.calculate(0.0);
+        }
+
+        assert_close(pid.calculate(0.0), 0.5, 1e-9);
+        assert_close(pid.calculate(2.0), 0.3, 1e-9);
+    }
+
+    #[test]
+    fn izone_resets_integral_for_large_errors() {
+        let mut pid: PidController<f64, f64> = PidController::new(0.0, 1.0, 0.0, period());
+        pid.set_izone(0.5);
+        pid.set_setpoint(0.2);
+
+        pid.calculate(0.0);
+        assert_close(pid.calculate(0.0), 0.008, 1e-9);
+        assert_close(pid.calculate(-1.0), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn derivative_filter_smooths_steps() {
+        let mut raw: PidController<f64, f64> = PidController::new(0.0, 0.0, 1.0, period());
+        let mut filtered: PidController<f64, f64> = PidController::new(0.0, 0.0, 1.0, period());
+        filtered.set_derivative_filter(Time::new::<millisecond>(80.0));
+
+        for pid in [&mut raw, &mut filtered] {
+            pid.calculate(0.0);
+        }
+
+        assert_close(raw.calculate(-0.1), 5.0, 1e-9);
+        assert_close(filtered.calculate(-0.1), 1.0, 1e-9);
+    }
+
+    #[test]
+    fn at_setpoint_checks_error_and_derivative() {
+        let mut pid: PidController<f64, f64> = PidController::new(1.0, 0.0, 0.0, period());
+        pid.set_tolerance(0.1);
+        pid.set_derivative_tolerance(1.0);
+        pid.set_setpoint(1.0);
+        assert!(!pid.at_setpoint());
+
+        pid.calculate(0.5);
+        assert!(!pid.at_setpoint());
+        pid.calculate(0.95);
+        assert!(!pid.at_setpoint());
+        pid.calculate(0.96);
+        assert!(pid.at_setpoint());
+
+        pid.reset();
+        assert!(!pid.at_setpoint());
+    }
+
+    #[test]
+    fn controller_runs_as_a_node() {
+        let pid: PidController<f64, f64> = PidController::new(0.5, 0.0, 0.0, period());
+        let pid = Arc::new(Mutex::new(pid));
+        pid.lock().unwrap().set_setpoint(4.0);
+
+        let mut measurements = BaseNode::new();
+        let probe = Probe::new();
+        measurements.process(pid).chain(probe.clone());
+        measurements.send(0.0);
+        measurements.send(2.0);
+
+        probe.assert_received(&[2.0, 1.0]);
+    }
+
+    #[test]
+    fn profiled_controller_moves_setpoint_gradually() {
+        let constraints = TrapezoidConstraints {
+            max_velocity: 1.0,
+            max_acceleration: 2.0,
+        };
+        let mut pid = ProfiledPidController::new(1.0, 0.0, 0.0, constraints, period());
+        pid.reset(0.0);
+
+        let output = pid.calculate_to(0.0, 10.0);
+
+        assert_close(pid.setpoint().position, 0.0004, 1e-9);
+        assert_close(pid.setpoint().velocity, 0.04, 1e-9);
+        assert_close(output, 0.0004, 1e-9);
+        assert!(!pid.at_goal());
+    }
+
+    #[test]
+    fn profiled_controller_wraps_continuous_goal() {
+        let constraints = TrapezoidConstraints {
+            max_velocity: AngularVelocity::new::<radian_per_second>(1.0),
+            max_acceleration: AngularAcceleration::new::<radian_per_second_squared>(2.0),
+        };
+        let mut pid = ProfiledPidController::new(1.0, 0.0, 0.0, constraints, period());
+        pid.enable_continuous_input(Angle::new::<degree>(-180.0), Angle::new::<degree>(180.0));
+        pid.reset(Angle::new::<degree>(170.0));
+
+        pid.calculate_to(Angle::new::<degree>(170.0), Angle::new::<degree>(-170.0));
+
+        assert!(pid.setpoint().velocity.get::<radian_per_second>() > 0.0);
+        assert_close(pid.goal().position.get::<degree>(), 190.0, 1e-9);
+    }
+}
@@ -0,0 +1,597 @@
+//! Trapezoidal and jerk-limited (S-curve) motion profiles
+//!
+//! Both profiles can start from any position, velocity and acceleration, so they can be re-planned
+//! mid-motion from wherever the mechanism currently is.
+
+use tetanus_core::clock::Clock;
+use tetanus_core::producer::Producer;
+use uom::si::f64::*;
+use uom::si::time::second;
+
+use crate::units::{Motion, SiValue};
+
+/// Position, velocity and acceleration along a profile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ProfileState<P: Motion> {
+    pub position: P,
+    pub velocity: P::Velocity,
+    pub acceleration: P::Acceleration,
+}
+
+impl<P: Motion> ProfileState<P> {
+    pub fn new(position: P, velocity: P::Velocity) -> Self {
+        ProfileState {
+            position,
+            velocity,
+            acceleration: P::Acceleration::from_si(0.0),
+        }
+    }
+
+    fn kinematic(&self) -> Kinematic {
+        Kinematic {
+            p: self.position.si(),
+            v: self.velocity.si(),
+            a: self.acceleration.si(),
+        }
+    }
+
+    fn from_kinematic(k: Kinematic) -> Self {
+        ProfileState {
+            position: P::from_si(k.p),
+            velocity: P::Velocity::from_si(k.v),
+            acceleration: P::Acceleration::from_si(k.a),
+        }
+    }
+}
+
+/// Profile that can be sampled at any time since it started
+pub trait MotionProfile: Send + Sync {
+    type Position: Motion;
+
+    fn state_at(&self, t: Time) -> ProfileState<Self::Position>;
+
+    fn total_time(&self) -> Time;
+
+    fn goal(&self) -> ProfileState<Self::Position>;
+
+    /// Plans a new profile from `current` to `goal`, with time starting again from zero
+    fn replan(&mut self, current: ProfileState<Self::Position>, goal: ProfileState<Self::Position>);
+
+    fn is_finished(&self, t: Time) -> bool {
+        t >= self.total_time()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TrapezoidConstraints<P: Motion> {
+    pub max_velocity: P::Velocity,
+    pub max_acceleration: P::Acceleration,
+}
+
+/// Profile that accelerates at the limit, cruises at the velocity limit if it has time to, and
+/// then decelerates at the limit
+pub struct TrapezoidProfile<P: Motion> {
+    constraints: TrapezoidConstraints<P>,
+    plan: Plan,
+}
+
+impl<P: Motion> TrapezoidProfile<P> {
+    pub fn new(
+        constraints: TrapezoidConstraints<P>,
+        initial: ProfileState<P>,
+        goal: ProfileState<P>,
+    ) -> Self {
+        let mut profile = TrapezoidProfile {
+            constraints,
+            plan: Plan::default(),
+        };
+        profile.replan(initial, goal);
+        profile
+    }
+
+    fn limits(&self) -> Limits {
+        Limits::new(
+            self.constraints.max_velocity.si(),
+            self.constraints.max_acceleration.si(),
+            None,
+        )
+    }
+}
+
+impl<P: Motion> MotionProfile for TrapezoidProfile<P> {
+    type Position = P;
+
+    fn state_at(&self, t: Time) -> ProfileState<P> {
+        ProfileState::from_kinematic(self.plan.state_at(t.get::<second>()))
+    }
+
+    fn total_time(&self) -> Time {
+        Time::new::<second>(self.plan.duration())
+    }
+
+    fn goal(&self) -> ProfileState<P> {
+        ProfileState::from_kinematic(self.plan.goal)
+    }
+
+    fn replan(&mut self, current: ProfileState<P>, goal: ProfileState<P>) {
+        self.plan = Plan::new(current.kinematic(), goal.kinematic(), self.limits());
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SCurveConstraints<P: Motion> {
+    pub max_velocity: P::Velocity,
+    pub max_acceleration: P::Acceleration,
+    pub max_jerk: P::Jerk,
+}
+
+/// Like [`TrapezoidProfile`], but also limits jerk so acceleration ramps instead of stepping
+pub struct SCurveProfile<P: Motion> {
+    constraints: SCurveConstraints<P>,
+    plan: Plan,
+}
+
+impl<P: Motion> SCurveProfile<P> {
+    pub fn new(
+        constraints: SCurveConstraints<P>,
+        initial: ProfileState<P>,
+        goal: ProfileState<P>,
+    ) -> Self {
+        let mut profile = SCurveProfile {
+            constraints,
+            plan: Plan::default(),
+        };
+        profile.replan(initial, goal);
+        profile
+    }
+
+    fn limits(&self) -> Limits {
+        Limits::new(
+            self.constraints.max_velocity.si(),
+            self.constraints.max_acceleration.si(),
+            Some(self.constraints.max_jerk.si()),
+        )
+    }
+}
+
+impl<P: Motion> MotionProfile for SCurveProfile<P> {
+    type Position = P;
+
+    fn state_at(&self, t: Time) -> ProfileState<P> {
+        ProfileState::from_kinematic(self.plan.state_at(t.get::<second>()))
+    }
+
+    fn total_time(&self) -> Time {
+        Time::new::<second>(self.plan.duration())
+    }
+
+    fn goal(&self) -> ProfileState<P> {
+        ProfileState::from_kinematic(self.plan.goal)
+    }
+
+    fn replan(&mut self, current: ProfileState<P>, goal: ProfileState<P>) {
+        self.plan = Plan::new(current.kinematic(), goal.kinematic(), self.limits());
+    }
+}
+
+/// Producer of a profile's setpoint at the current time
+///
+/// Time is measured from when the producer was created or last given a new goal.
+pub struct ProfileProducer<M, C> {
+    profile: M,
+    clock: C,
+    start: Time,
+}
+
+impl<M: MotionProfile, C: Clock> ProfileProducer<M, C> {
+    pub fn new(profile: M, clock: C) -> Self {
+        ProfileProducer {
+            start: clock.now(),
+            profile,
+            clock,
+        }
+    }
+
+    /// Re-plans from the current setpoint to `goal`
+    pub fn set_goal(&mut self, goal: ProfileState<M::Position>) {
+        let now = self.clock.now();
+        let current = self.profile.state_at(now - self.start);
+        self.profile.replan(current, goal);
+        self.start = now;
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.profile.is_finished(self.elapsed())
+    }
+
+    pub fn profile(&self) -> &M {
+        &self.profile
+    }
+
+    fn elapsed(&self) -> Time {
+        self.clock.now() - self.start
+    }
+}
+
+impl<M: MotionProfile, C: Clock> Producer for ProfileProducer<M, C> {
+    type Msg = ProfileState<M::Position>;
+
+    fn next(&self) -> Self::Msg {
+        self.profile.state_at(self.elapsed())
+    }
+}
+
+/// Position, velocity and acceleration in base SI units
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Kinematic {
+    p: f64,
+    v: f64,
+    a: f64,
+}
+
+/// Stretch of a profile with constant jerk
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Segment {
+    duration: f64,
+    /// Acceleration at the start of the segment
+    acceleration: f64,
+    jerk: f64,
+}
+
+impl Segment {
+    fn advance(&self, from: Kinematic, t: f64) -> Kinematic {
+        let (a, j) = (self.acceleration, self.jerk);
+        Kinematic {
+            p: from.p + from.v * t + a * t * t / 2.0 + j * t * t * t / 6.0,
+            v: from.v + a * t + j * t * t / 2.0,
+            a: a + j * t,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Limits {
+    velocity: f64,
+    acceleration: f64,
+    /// No limit means acceleration can change instantly
+    jerk: Option<f64>,
+}
+
+impl Limits {
+    fn new(velocity: f64, acceleration: f64, jerk: Option<f64>) -> Self {
+        assert!(
+            velocity > 0.0 && acceleration > 0.0 && jerk.iter().all(|&j| j > 0.0),
+            "profile constraints must be positive"
+        );
+        Limits {
+            velocity,
+            acceleration,
+            jerk,
+        }
+    }
+
+    /// Fastest way from `(v0, a0)` to moving at `v1` with no acceleration
+    fn velocity_change(&self, v0: f64, a0: f64, v1: f64) -> Vec<Segment> {
+        let j = match self.jerk {
+            Some(j) => j,
+            None if v1 == v0 => return Vec::new(),
+            None => {
+                return vec![Segment {
+                    duration: (v1 - v0).abs() / self.acceleration,
+                    acceleration: self.acceleration.copysign(v1 - v0),
+                    jerk: 0.0,
+                }]
+            }
+        };
+
+        // Velocity reached by ramping the acceleration straight to zero
+        let v_stop = v0 + a0 * a0.abs() / (2.0 * j);
+        if v1 == v_stop {
+            return without_empty(vec![Segment {
+                duration: a0.abs() / j,
+                acceleration: a0,
+                jerk: -j.copysign(a0),
+            }]);
+        }
+
+        // Work in the direction of the change, ramping to a peak acceleration, holding it, and
+        // ramping back down
+        let d = (v1 - v_stop).signum();
+        let (a0, dv) = (d * a0, d * (v1 - v0));
+        let mut peak = ((2.0 * j * dv + a0 * a0) / 2.0).sqrt();
+        let mut hold = 0.0;
+        if peak > self.acceleration {
+            peak = self.acceleration;
+            let ramp_up = (peak * peak - a0 * a0) / (2.0 * j.copysign(peak - a0));
+            let ramp_down = peak * peak / (2.0 * j);
+            hold = (dv - ramp_up - ramp_down) / peak;
+        }
+
+        without_empty(vec![
+            Segment {
+                duration: (peak - a0).abs() / j,
+                acceleration: d * a0,
+                jerk: d * j.copysign(peak - a0),
+            },
+            Segment {
+                duration: hold,
+                acceleration: d * peak,
+                jerk: 0.0,
+            },
+            Segment {
+                duration: peak / j,
+                acceleration: d * peak,
+                jerk: -d * j,
+            },
+        ])
+    }
+}
+
+fn without_empty(segments: Vec<Segment>) -> Vec<Segment> {
+    segments.into_iter().filter(|s| s.duration > 0.0).collect()
+}
+
+fn integrate(from: Kinematic, segments: &[Segment]) -> Kinematic {
+    segments.iter().fold(from, |state, segment| {
+        segment.advance(state, segment.duration)
+    })
+}
+
+/// Segments from a start state to a goal, planned by searching for the peak velocity
+#[derive(Clone, Debug, Default)]
+struct Plan {
+    start: Kinematic,
+    segments: Vec<Segment>,
+    goal: Kinematic,
+}
+
+impl Plan {
+    fn new(start: Kinematic, goal: Kinematic, limits: Limits) -> Self {
+        let vmax = limits.velocity;
+        let start = Kinematic {
+            a: if limits.jerk.is_some() { start.a } else { 0.0 },
+            ..start
+        };
+        let goal = Kinematic {
+            v: goal.v.clamp(-vmax, vmax),
+            a: 0.0,
+            ..goal
+        };
+        let distance = goal.p - start.p;
+
+        // Speed up (or slow down) to `peak`, optionally cruise there, then change to the goal
+        // velocity
+        let through = |peak: f64, cruise: f64| {
+            let mut segments = limits.velocity_change(start.v, start.a, peak);
+            if cruise > 0.0 {
+                segments.push(Segment {
+                    duration: cruise,
+                    acceleration: 0.0,
+                    jerk: 0.0,
+                });
+            }
+            segments.extend(limits.velocity_change(peak, 0.0, goal.v));
+            segments
+        };
+        let covered = |peak: f64| {
+            let relative = Kinematic { p: 0.0, ..start };
+            integrate(relative, &through(peak, 0.0)).p
+        };
+        let bisect = |mut low: f64, mut high: f64| {
+            for _ in 0..100 {
+                let mid = (low + high) / 2.0;
+                if covered(mid) < distance {
+                    low = mid;
+                } else {
+                    high = mid;
+                }
+            }
+            (low + high) / 2.0
+        };
+
+        // Distance covered grows with the peak velocity above the higher of the start and goal
+        // velocities, and below the lower of them
+        let v_stop = match limits.jerk {
+            Some(j) => start.v + start.a * start.a.abs() / (2.0 * j),
+            None => start.v,
+        };
+        let upper = v_stop.max(goal.v).clamp(-vmax, vmax);
+        let lower = v_stop.min(goal.v).clamp(-vmax, vmax);
+
+        let segments = if covered(upper) <= distance {
+            if covered(vmax) <= distance {
+                through(vmax, (distance - covered(vmax)) / vmax)
+            } else {
+                through(bisect(upper, vmax), 0.0)
+            }
+        } else if covered(lower) >= distance {
+            if covered(-vmax) >= distance {
+                through(-vmax, (covered(-vmax) - distance) / vmax)
+            } else {
+                through(bisect(-vmax, lower), 0.0)
+            }
+        } else if lower > 0.0 {
+            through(lower, (distance - covered(lower)) / lower)
+        } else if upper < 0.0 {
+            through(upper, (distance - covered(upper)) / upper)
+        } else {
+            through(bisect(lower, upper), 0.0)
+        };
+
+        Plan {
+            start,
+            segments,
+            goal,
+        }
+    }
+
+    fn duration(&self) -> f64 {
+        self.segments.iter().map(|s| s.duration).sum()
+    }
+
+    fn state_at(&self, mut t: f64) -> Kinematic {
+        let mut state = self.start;
+        for segment in &self.segments {
+            if t < segment.duration {
+                return segment.advance(state, t.max(0.0));
+            }
+            state = segment.advance(state, segment.duration);
+            t -= segment.duration;
+        }
+        self.goal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tetanus_core::node::{BaseNode, Node};
+    use tetanus_core::testing::{assert_close, tick, FakeClock, Probe};
+    use uom::si::acceleration::meter_per_second_squared;
+    use uom::si::jerk::meter_per_second_cubed;
+    use uom::si::length::meter;
+    use uom::si::time::millisecond;
+    use uom::si::velocity::meter_per_second;
+
+    fn at(position: f64, velocity: f64) -> ProfileState<Length> {
+        ProfileState::new(
+            Length::new::<meter>(position),
+            Velocity::new::<meter_per_second>(velocity),
+        )
+    }
+
+    fn trapezoid(max_velocity: f64, max_acceleration: f64) -> TrapezoidConstraints<Length> {
+        TrapezoidConstraints {
+            max_velocity: Velocity::new::<meter_per_second>(max_velocity),
+            max_acceleration: Acceleration::new::<meter_per_second_squared>(max_acceleration),
+        }
+    }
+
+    fn s_curve(velocity: f64, acceleration: f64, jerk: f64) -> SCurveConstraints<Length> {
+        SCurveConstraints {
+            max_velocity: Velocity::new::<meter_per_second>(velocity),
+            max_acceleration: Acceleration::new::<meter_per_second_squared>(acceleration),
+            max_jerk: Jerk::new::<meter_per_second_cubed>(jerk),
+        }
+    }
+
+    fn seconds(t: f64) -> Time {
+        Time::new::<second>(t)
+    }
+
+    /// Samples the profile every 10 ms, checking it never exceeds the limits and that position
+    /// and velocity are continuous
+    fn check_limits(profile: &impl MotionProfile<Position = Length>, v: f64, a: f64, j: f64) {
+        let dt = 0.01;
+        let steps = (profile.total_time().get::<second>() / dt).ceil() as usize + 1;
+        let mut previous = profile.state_at(seconds(0.0));
+        for i in 1..=steps {
+            let state = profile.state_at(seconds(i as f64 * dt));
+            let (p0, p1) = (previous.position.value, state.position.value);
+            let (v0, v1) = (previous.velocity.value, state.velocity.value);
+            let (a0, a1) = (previous.acceleration.value, state.acceleration.value);
+
+            assert!(v1.abs() <= v + 1e-6, "velocity {} over limit", v1);
+            assert!(a1.abs() <= a + 1e-6, "acceleration {} over limit", a1);
+            assert!((p1 - p0).abs() <= v * dt + 1e-6);
+            assert!((v1 - v0).abs() <= a * dt + 1e-6);
+            assert!((a1 - a0).abs() <= j * dt + 1e-6);
+            previous = state;
+        }
+    }
+
+    #[test]
+    fn trapezoid_cruises_at_max_velocity() {
+        let profile = TrapezoidProfile::new(trapezoid(2.0, 1.0), at(0.0, 0.0), at(10.0, 0.0));
+
+        assert_close(profile.total_time().get::<second>(), 7.0, 1e-6);
+        assert_close(profile.state_at(seconds(1.0)).position.value, 0.5, 1e-6);
+        assert_close(profile.state_at(seconds(3.5)).velocity.value, 2.0, 1e-6);
+        assert_eq!(profile.state_at(seconds(8.0)), at(10.0, 0.0));
+        check_limits(&profile, 2.0, 1.0, f64::INFINITY);
+    }
+
+    #[test]
+    fn short_trapezoid_is_a_triangle() {
+        let profile = TrapezoidProfile::new(trapezoid(10.0, 1.0), at(0.0, 0.0), at(-1.0, 0.0));
+
+        assert_close(profile.total_time().get::<second>(), 2.0, 1e-6);
+        assert_close(profile.state_at(seconds(1.0)).velocity.value, -1.0, 1e-6);
+    }
+
+    #[test]
+    fn trapezoid_replans_from_moving_away() {
+        let profile = TrapezoidProfile::new(trapezoid(2.0, 1.0), at(0.0, -2.0), at(2.0, 0.0));
+
+        assert_close(profile.state_at(seconds(2.0)).velocity.value, 0.0, 1e-6);
+        assert_close(profile.state_at(seconds(2.0)).position.value, -2.0, 1e-6);
+        assert_close(profile.total_time().get::<second>(), 6.0, 1e-6);
+        check_limits(&profile, 2.0, 1.0, f64::INFINITY);
+    }
+
+    #[test]
+    fn trapezoid_keeps_goal_velocity() {
+        let profile = TrapezoidProfile::new(trapezoid(3.0, 1.0), at(0.0, 1.0), at(4.0, 1.0));
+
+        let end = profile.state_at(profile.total_time() - seconds(1e-9));
+        assert_close(end.velocity.value, 1.0, 1e-6);
+        assert_close(end.position.value, 4.0, 1e-6);
+    }
+
+    #[test]
+    fn s_curve_rest_to_rest_matches_closed_form() {
+        let profile = SCurveProfile::new(s_curve(10.0, 10.0, 1.0), at(0.0, 0.0), at(2.0, 0.0));
+
+        assert_close(profile.total_time().get::<second>(), 4.0, 1e-6);
+        check_limits(&profile, 10.0, 10.0, 1.0);
+    }
+
+    #[test]
+    fn s_curve_respects_every_limit() {
+        let profile = SCurveProfile::new(s_curve(1.5, 2.0, 8.0), at(1.0, 0.0), at(-5.0, 0.0));
+
+        check_limits(&profile, 1.5, 2.0, 8.0);
+        let end = profile.state_at(profile.total_time());
+        assert_close(end.position.value, -5.0, 1e-6);
+    }
+
+    #[test]
+    fn s_curve_replans_mid_motion() {
+        let constraints = s_curve(2.0, 3.0, 10.0);
+        let mut profile = SCurveProfile::new(constraints, at(0.0, 0.0), at(5.0, 0.0));
+        let current = profile.state_at(seconds(1.0));
+
+        profile.replan(current, at(-1.0, 0.0));
+
+        assert_eq!(profile.state_at(seconds(0.0)), current);
+        check_limits(&profile, 2.0, 3.0, 10.0);
+        let end = profile.state_at(profile.total_time() - seconds(1e-9));
+        assert_close(end.position.value, -1.0, 1e-6);
+        assert_close(end.velocity.value, 0.0, 1e-6);
+    }
+
+    #[test]
+    fn producer_emits_setpoints_each_tick() {
+        let clock = FakeClock::new();
+        let profile = TrapezoidProfile::new(trapezoid(2.0, 1.0), at(0.0, 0.0), at(10.0, 0.0));
+        let producer = Arc::new(Mutex::new(ProfileProducer::new(profile, clock.clone())));
+        let mut ticker = BaseNode::new();
+        let probe = Probe::new();
+        ticker.produce(producer.clone()).chain(probe.clone());
+
+        tick(&ticker, 1);
+        clock.advance(Time::new::<millisecond>(1000.0));
+        tick(&ticker, 1);
+        producer.lock().unwrap().set_goal(at(0.0, 0.0));
+        clock.advance(Time::new::<second>(1.0));
+        tick(&ticker, 1);
+
+        let setpoints = probe.received();
+        assert_eq!(setpoints[0].velocity.value, 0.0);
+        assert_eq!(setpoints[0].acceleration.value, 1.0);
+        assert_close(setpoints[1].position.value, 0.5, 1e-6);
+        assert_close(setpoints[2].velocity.value, 0.0, 1e-6);
+        assert!(!producer.lock().unwrap().is_finished());
+    }
+}
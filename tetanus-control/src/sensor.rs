@@ -0,0 +1,214 @@
+//! Converting motor controller sensor readings to and from the units of what they drive
+
+use std::f64::consts::PI;
+use std::marker::PhantomData;
+
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::time::second;
+
+use crate::units::{Motion, SiValue};
+
+/// Converts between native sensor units and where a mechanism is and how fast it's moving
+///
+/// `P` is [`Length`] for wheels, spools and belts, or [`Angle`] for arms and turrets.
+#[derive(Clone, Copy, Debug)]
+pub struct SensorConversion<P = Length> {
+    counts_per_revolution: f64,
+    /// Time native velocities are counted over, such as 100 ms for a Talon
+    velocity_period: Time,
+    /// Sensor revolutions per mechanism revolution
+    gear_ratio: f64,
+    /// Distance or angle the mechanism moves each revolution, in base SI units
+    travel_per_revolution: f64,
+    units: PhantomData<P>,
+}
+
+impl SensorConversion<Length> {
+    /// Wheel, spool or pulley of `diameter`
+    pub fn linear(
+        counts_per_revolution: f64,
+        velocity_period: Time,
+        gear_ratio: f64,
+        diameter: Length,
+    ) -> Self {
+        SensorConversion {
+            counts_per_revolution,
+            velocity_period,
+            gear_ratio,
+            travel_per_revolution: (diameter * PI).get::<meter>(),
+            units: PhantomData,
+        }
+    }
+}
+
+impl SensorConversion<Angle> {
+    pub fn angular(counts_per_revolution: f64, velocity_period: Time, gear_ratio: f64) -> Self {
+        SensorConversion {
+            counts_per_revolution,
+            velocity_period,
+            gear_ratio,
+            travel_per_revolution: 2.0 * PI,
+            units: PhantomData,
+        }
+    }
+}
+
+impl<P: Motion> SensorConversion<P> {
+    pub fn position(&self, counts: f64) -> P {
+        P::from_si(self.revolutions(counts) * self.travel_per_revolution)
+    }
+
+    pub fn velocity(&self, native: f64) -> P::Velocity {
+        P::Velocity::from_si(
+            self.revolutions(native) * self.travel_per_revolution
+                / self.velocity_period.get::<second>(),
+        )
+    }
+
+    pub fn to_counts(&self, position: P) -> f64 {
+        position.si() / self.travel_per_revolution * self.counts_per_revolution * self.gear_ratio
+    }
+
+    pub fn to_native_velocity(&self, velocity: P::Velocity) -> f64 {
+        velocity.si() * self.velocity_period.get::<second>() / self.travel_per_revolution
+            * self.counts_per_revolution
+            * self.gear_ratio
+    }
+
+    /// Mechanism revolutions in `counts`
+    fn revolutions(&self, counts: f64) -> f64 {
+        counts / self.counts_per_revolution / self.gear_ratio
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tetanus_core::testing::assert_close;
+    use uom::si::angle::radian;
+    use uom::si::angular_velocity::radian_per_second;
+    use uom::si::length::inch;
+    use uom::si::time::{millisecond, minute};
+    use uom::si::velocity::meter_per_second;
+
+    /// Integrated Talon FX sensor: 2048 counts per revolution, velocity per 100 ms
+    fn talon_fx_period() -> Time {
+        Time::new::<millisecond>(100.0)
+    }
+
+    /// SparkMax hall sensor: rotations, and velocity in RPM
+    fn spark_max_period() -> Time {
+        Time::new::<minute>(1.0)
+    }
+
+    #[test]
+    fn talon_fx_linear() {
+        let conversion =
+            SensorConversion::linear(2048.0, talon_fx_period(), 10.71, Length::new::<inch>(6.0));
+        let circumference = 6.0 * 0.0254 * PI;
+
+        // One wheel revolution, and one wheel revolution per second
+        let counts = 2048.0 * 10.71;
+        assert_close(
+            conversion.position(counts).get::<meter>(),
+            circumference,
+            1e-9,
+        );
+        assert_close(
+            conversion.velocity(counts / 10.0).get::<meter_per_second>(),
+            circumference,
+            1e-9,
+        );
+
+        let position = Length::new::<meter>(2.5);
+        assert_close(
+            conversion.to_counts(position),
+            2.5 / circumference * counts,
+            1e-6,
+        );
+        assert_close(
+            conversion
+                .position(conversion.to_counts(position))
+                .get::<meter>(),
+            2.5,
+            1e-9,
+        );
+        let velocity = Velocity::new::<meter_per_second>(-1.5);
+        assert_close(
+            conversion
+                .velocity(conversion.to_native_velocity(velocity))
+                .get::<meter_per_second>(),
+            -1.5,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn talon_fx_angular() {
+        let conversion = SensorConversion::angular(2048.0, talon_fx_period(), 12.8);
+
+        // A quarter turn of the module is 12.8 quarter turns of the motor
+        assert_close(
+            conversion.position(2048.0 * 12.8 / 4.0).get::<radian>(),
+            PI / 2.0,
+            1e-9,
+        );
+        assert_close(
+            conversion.to_native_velocity(AngularVelocity::new::<radian_per_second>(2.0 * PI)),
+            2048.0 * 12.8 / 10.0,
+            1e-6,
+        );
+
+        let angle = Angle::new::<radian>(-3.0);
+        assert_close(
+            conversion
+                .position(conversion.to_counts(angle))
+                .get::<radian>(),
+            -3.0,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn spark_max_linear() {
+        let conversion =
+            SensorConversion::linear(1.0, spark_max_period(), 5.0, Length::new::<meter>(0.1));
+
+        assert_close(conversion.position(5.0).get::<meter>(), 0.1 * PI, 1e-9);
+        // 300 motor RPM turns the spool once a second
+        assert_close(
+            conversion.velocity(300.0).get::<meter_per_second>(),
+            0.1 * PI,
+            1e-9,
+        );
+
+        let velocity = Velocity::new::<meter_per_second>(0.8);
+        assert_close(
+            conversion
+                .velocity(conversion.to_native_velocity(velocity))
+                .get::<meter_per_second>(),
+            0.8,
+            1e-9,
+        );
+    }
+
+    #[test]
+    fn spark_max_angular() {
+        let conversion = SensorConversion::angular(1.0, spark_max_period(), 100.0);
+
+        assert_close(conversion.position(25.0).get::<radian>(), PI / 2.0, 1e-9);
+        assert_close(conversion.to_counts(Angle::new::<radian>(PI)), 50.0, 1e-9);
+        // 6000 motor RPM turns the arm once a second
+        assert_close(
+            conversion.velocity(6000.0).get::<radian_per_second>(),
+            2.0 * PI,
+            1e-9,
+        );
+        assert_close(
+            conversion.to_native_velocity(AngularVelocity::new::<radian_per_second>(PI)),
+            3000.0,
+            1e-9,
+        );
+    }
+}
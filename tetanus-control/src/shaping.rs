@@ -0,0 +1,310 @@
+//! Building blocks for shaping driver inputs such as joystick axes
+//!
+//! Shapers can be chained with [`Shaper::then`], and every shaper is a
+//! [`tetanus_core::processor::Processor`] so it can be used as a node.
+
+use std::sync::Arc;
+
+use tetanus_core::clock::Clock;
+use tetanus_core::processor::Processor;
+use uom::si::f64::*;
+use uom::si::time::second;
+
+pub trait Shaper: Send + Sync {
+    fn shape(&mut self, x: f64) -> f64;
+
+    /// Feeds this shaper's output into `next`
+    fn then<S: Shaper>(self, next: S) -> Then<Self, S>
+    where
+        Self: Sized,
+    {
+        Then {
+            first: self,
+            second: next,
+        }
+    }
+}
+
+impl Shaper for Box<dyn Shaper> {
+    fn shape(&mut self, x: f64) -> f64 {
+        (**self).shape(x)
+    }
+}
+
+/// Two shapers applied one after the other
+pub struct Then<A, B> {
+    first: A,
+    second: B,
+}
+
+impl<A: Shaper, B: Shaper> Shaper for Then<A, B> {
+    fn shape(&mut self, x: f64) -> f64 {
+        let x = self.first.shape(x);
+        self.second.shape(x)
+    }
+}
+
+/// Zeroes inputs within `threshold` of zero and rescales the rest so the output still covers the
+/// full range without jumping at the threshold
+#[derive(Clone, Copy, Debug)]
+pub struct Deadband {
+    threshold: f64,
+}
+
+impl Deadband {
+    pub fn new(threshold: f64) -> Self {
+        assert!(
+            (0.0..1.0).contains(&threshold),
+            "deadband threshold must be in [0, 1)"
+        );
+        Deadband { threshold }
+    }
+}
+
+impl Shaper for Deadband {
+    fn shape(&mut self, x: f64) -> f64 {
+        if x.abs() <= self.threshold {
+            0.0
+        } else {
+            x.signum() * (x.abs() - self.threshold) / (1.0 - self.threshold)
+        }
+    }
+}
+
+/// `sgn(x)·|x|^exponent`, for finer control near zero
+#[derive(Clone, Copy, Debug)]
+pub struct PowerCurve {
+    exponent: f64,
+}
+
+impl PowerCurve {
+    pub fn new(exponent: f64) -> Self {
+        PowerCurve { exponent }
+    }
+}
+
+impl Shaper for PowerCurve {
+    fn shape(&mut self, x: f64) -> f64 {
+        x.signum() * x.abs().powf(self.exponent)
+    }
+}
+
+/// `sgn(x)·(e^(k|x|) - 1) / (e^k - 1)`, which still maps -1, 0 and 1 to themselves
+///
+/// Larger `steepness` gives finer control near zero.
+#[derive(Clone, Copy, Debug)]
+pub struct ExponentialCurve {
+    steepness: f64,
+}
+
+impl ExponentialCurve {
+    pub fn new(steepness: f64) -> Self {
+        assert!(
+            steepness > 0.0,
+            "exponential curve steepness must be positive"
+        );
+        ExponentialCurve { steepness }
+    }
+}
+
+impl Shaper for ExponentialCurve {
+    fn shape(&mut self, x: f64) -> f64 {
+        let k = self.steepness;
+        x.signum() * (k * x.abs()).exp_m1() / k.exp_m1()
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Scale {
+    factor: f64,
+}
+
+impl Scale {
+    pub fn new(factor: f64) -> Self {
+        Scale { factor }
+    }
+}
+
+impl Shaper for Scale {
+    fn shape(&mut self, x: f64) -> f64 {
+        x * self.factor
+    }
+}
+
+/// Scales inputs down while a condition, such as a button being held, is true
+#[derive(Clone)]
+pub struct PrecisionMode {
+    scale: f64,
+    enabled: Arc<dyn Fn() -> bool + Send + Sync>,
+}
+
+impl PrecisionMode {
+    pub fn new(scale: f64, enabled: impl Fn() -> bool + Send + Sync + 'static) -> Self {
+        PrecisionMode {
+            scale,
+            enabled: Arc::new(enabled),
+        }
+    }
+}
+
+impl Shaper for PrecisionMode {
+    fn shape(&mut self, x: f64) -> f64 {
+        if (self.enabled)() {
+            x * self.scale
+        } else {
+            x
+        }
+    }
+}
+
+/// Limits how fast the output can change, in units per second
+pub struct SlewRateLimiter<C> {
+    rising: f64,
+    falling: f64,
+    clock: C,
+    last: Option<(f64, Time)>,
+}
+
+impl<C: Clock> SlewRateLimiter<C> {
+    pub fn new(rate: f64, clock: C) -> Self {
+        Self::asymmetric(rate, rate, clock)
+    }
+
+    /// Limits increases to `rising` and decreases to `falling` units per second
+    pub fn asymmetric(rising: f64, falling: f64, clock: C) -> Self {
+        assert!(rising > 0.0 && falling > 0.0, "slew rates must be positive");
+        SlewRateLimiter {
+            rising,
+            falling,
+            clock,
+            last: None,
+        }
+    }
+
+    /// Jumps straight to `value`
+    pub fn reset(&mut self, value: f64) {
+        self.last = Some((value, self.clock.now()));
+    }
+}
+
+impl<C: Clock> Shaper for SlewRateLimiter<C> {
+    fn shape(&mut self, x: f64) -> f64 {
+        let now = self.clock.now();
+        let output = match self.last {
+            Some((last, at)) => {
+                let dt = (now - at).get::<second>();
+                x.clamp(last - self.falling * dt, last + self.rising * dt)
+            }
+            None => x,
+        };
+        self.last = Some((output, now));
+        output
+    }
+}
+
+macro_rules! shaper_processor {
+    ($([$($generics:tt)*] $shaper:ty),* $(,)?) => {
+        $(
+            impl<$($generics)*> Processor for $shaper {
+                type In = f64;
+                type Out = f64;
+
+                fn process(&mut self, msg: Self::In) -> Self::Out {
+                    self.shape(msg)
+                }
+            }
+        )*
+    };
+}
+
+shaper_processor!(
+    [] Box<dyn Shaper>,
+    [A: Shaper, B: Shaper] Then<A, B>,
+    [] Deadband,
+    [] PowerCurve,
+    [] ExponentialCurve,
+    [] Scale,
+    [] PrecisionMode,
+    [C: Clock] SlewRateLimiter<C>,
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+    use tetanus_core::node::{BaseNode, Node, NodeReceiver};
+    use tetanus_core::testing::{assert_close, FakeClock, Probe};
+    use uom::si::time::millisecond;
+
+    fn shape_all(shaper: &mut impl Shaper, inputs: &[f64]) -> Vec<f64> {
+        inputs.iter().map(|x| shaper.shape(*x)).collect()
+    }
+
+    #[test]
+    fn deadband_rescales_outside_threshold() {
+        let mut deadband = Deadband::new(0.2);
+
+        let outputs = shape_all(&mut deadband, &[0.1, -0.2, 0.6, -1.0, 1.0]);
+
+        for (actual, expected) in outputs.iter().zip(&[0.0, 0.0, 0.5, -1.0, 1.0]) {
+            assert_close(*actual, *expected, 1e-9);
+        }
+    }
+
+    #[test]
+    fn curves_keep_sign_and_endpoints() {
+        let mut power = PowerCurve::new(3.0);
+        let mut exponential = ExponentialCurve::new(2.0);
+
+        assert_close(power.shape(-0.5), -0.125, 1e-9);
+        assert_close(power.shape(1.0), 1.0, 1e-9);
+        assert_close(exponential.shape(-1.0), -1.0, 1e-9);
+        assert_close(exponential.shape(0.0), 0.0, 1e-9);
+        assert!(exponential.shape(0.5) < 0.5);
+    }
+
+    #[test]
+    fn precision_mode_follows_condition() {
+        let enabled = Arc::new(AtomicBool::new(false));
+        let mut precision = {
+            let enabled = enabled.clone();
+            PrecisionMode::new(0.25, move || enabled.load(Ordering::SeqCst))
+        };
+
+        assert_close(precision.shape(0.8), 0.8, 1e-9);
+        enabled.store(true, Ordering::SeqCst);
+        assert_close(precision.shape(0.8), 0.2, 1e-9);
+    }
+
+    #[test]
+    fn slew_rate_limiter_limits_change_per_second() {
+        let clock = FakeClock::new();
+        let mut limiter = SlewRateLimiter::asymmetric(2.0, 4.0, clock.clone());
+
+        assert_close(limiter.shape(0.0), 0.0, 1e-9);
+        clock.advance(Time::new::<millisecond>(100.0));
+        assert_close(limiter.shape(1.0), 0.2, 1e-9);
+        clock.advance(Time::new::<millisecond>(100.0));
+        assert_close(limiter.shape(-1.0), -0.2, 1e-9);
+        clock.advance(Time::new::<millisecond>(500.0));
+        assert_close(limiter.shape(-1.0), -1.0, 1e-9);
+    }
+
+    #[test]
+    fn chained_shapers_run_as_a_node() {
+        let shaper = Deadband::new(0.1)
+            .then(PowerCurve::new(2.0))
+            .then(Scale::new(0.5));
+        let mut stick = BaseNode::new();
+        let probe = Probe::new();
+        stick
+            .process(Arc::new(Mutex::new(shaper)))
+            .chain(probe.clone());
+
+        stick.send(0.05);
+        stick.send(-1.0);
+
+        probe.assert_received(&[0.0, -0.5]);
+    }
+}
@@ -0,0 +1,320 @@
+//! Splines through field waypoints
+//!
+//! Splines are parametric polynomials `x(t)`, `y(t)` for `t` in `[0, 1]`, in meters.
+
+use anyhow::{bail, Result};
+use uom::si::curvature::radian_per_meter;
+use uom::si::f64::*;
+use uom::si::length::meter;
+
+use crate::geometry::{Pose2d, Rotation2d, Translation2d};
+
+/// Position and its first two derivatives along x and y, in meters
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ControlVector {
+    pub x: [f64; 3],
+    pub y: [f64; 3],
+}
+
+impl ControlVector {
+    /// Control vector at `pose` heading along its rotation with the given tangent length
+    fn from_pose(pose: Pose2d, scale: f64) -> Self {
+        ControlVector {
+            x: [pose.x().get::<meter>(), scale * pose.rotation.cos(), 0.0],
+            y: [pose.y().get::<meter>(), scale * pose.rotation.sin(), 0.0],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PoseWithCurvature {
+    pub pose: Pose2d,
+    pub curvature: Curvature,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Spline {
+    /// Coefficients of `t^0` to `t^5`
+    x: [f64; 6],
+    y: [f64; 6],
+}
+
+impl Spline {
+    /// Matches position and first derivative at each end
+    pub fn cubic_hermite(start: ControlVector, end: ControlVector) -> Self {
+        let cubic = |p0: f64, v0: f64, p1: f64, v1: f64| {
+            [
+                p0,
+                v0,
+                -3.0 * p0 - 2.0 * v0 + 3.0 * p1 - v1,
+                2.0 * p0 + v0 - 2.0 * p1 + v1,
+                0.0,
+                0.0,
+            ]
+        };
+        Spline {
+            x: cubic(start.x[0], start.x[1], end.x[0], end.x[1]),
+            y: cubic(start.y[0], start.y[1], end.y[0], end.y[1]),
+        }
+    }
+
+    /// Matches position and first and second derivatives at each end
+    pub fn quintic_hermite(start: ControlVector, end: ControlVector) -> Self {
+        let quintic = |[p0, v0, a0]: [f64; 3], [p1, v1, a1]: [f64; 3]| {
+            [
+                p0,
+                v0,
+                0.5 * a0,
+                -10.0 * p0 - 6.0 * v0 - 1.5 * a0 + 0.5 * a1 - 4.0 * v1 + 10.0 * p1,
+                15.0 * p0 + 8.0 * v0 + 1.5 * a0 - a1 + 7.0 * v1 - 15.0 * p1,
+                -6.0 * p0 - 3.0 * v0 - 0.5 * a0 + 0.5 * a1 - 3.0 * v1 + 6.0 * p1,
+            ]
+        };
+        Spline {
+            x: quintic(start.x, end.x),
+            y: quintic(start.y, end.y),
+        }
+    }
+
+    pub fn point(&self, t: f64) -> PoseWithCurvature {
+        let (x, dx, ddx) = evaluate(&self.x, t);
+        let (y, dy, ddy) = evaluate(&self.y, t);
+
+        let curvature = (dx * ddy - ddx * dy) / (dx * dx + dy * dy).powf(1.5);
+        PoseWithCurvature {
+            pose: Pose2d::new(
+                Translation2d::new(Length::new::<meter>(x), Length::new::<meter>(y)),
+                Rotation2d::from_components(dx, dy),
+            ),
+            curvature: Curvature::new::<radian_per_meter>(curvature),
+        }
+    }
+}
+
+/// Value and first two derivatives of a polynomial
+fn evaluate(coefficients: &[f64; 6], t: f64) -> (f64, f64, f64) {
+    let mut value = 0.0;
+    let mut first = 0.0;
+    let mut second = 0.0;
+    for (power, c) in coefficients.iter().enumerate().rev() {
+        let n = power as f64;
+        value = value * t + c;
+        if power >= 1 {
+            first = first * t + n * c;
+        }
+        if power >= 2 {
+            second = second * t + n * (n - 1.0) * c;
+        }
+    }
+    (value, first, second)
+}
+
+/// Tangents are scaled to this many times the distance between waypoints, which gives gentle
+/// curves without overshooting
+const TANGENT_SCALE: f64 = 1.2;
+
+/// One quintic spline between each pair of consecutive poses
+pub fn quintic_splines(waypoints: &[Pose2d]) -> Vec<Spline> {
+    waypoints
+        .windows(2)
+        .map(|pair| {
+            let scale = TANGENT_SCALE
+                * pair[0]
+                    .translation
+                    .distance(pair[1].translation)
+                    .get::<meter>();
+            Spline::quintic_hermite(
+                ControlVector::from_pose(pair[0], scale),
+                ControlVector::from_pose(pair[1], scale),
+            )
+        })
+        .collect()
+}
+
+/// Cubic splines from `start` to `end` through `interior`, with headings only fixed at the ends
+///
+/// Tangents at interior points are chosen so the path's curvature is continuous.
+pub fn clamped_cubic_splines(
+    start: Pose2d,
+    interior: &[Translation2d],
+    end: Pose2d,
+) -> Vec<Spline> {
+    let points: Vec<(f64, f64)> = std::iter::once(start.translation)
+        .chain(interior.iter().copied())
+        .chain(std::iter::once(end.translation))
+        .map(|p| (p.x.get::<meter>(), p.y.get::<meter>()))
+        .collect();
+    let first_leg = start.translation.distance(Translation2d::new(
+        Length::new::<meter>(points[1].0),
+        Length::new::<meter>(points[1].1),
+    ));
+    let last_leg = end.translation.distance(Translation2d::new(
+        Length::new::<meter>(points[points.len() - 2].0),
+        Length::new::<meter>(points[points.len() - 2].1),
+    ));
+    let start = ControlVector::from_pose(start, TANGENT_SCALE * first_leg.get::<meter>());
+    let end = ControlVector::from_pose(end, TANGENT_SCALE * last_leg.get::<meter>());
+
+    let xs: Vec<f64> = points.iter().map(|p| p.0).collect();
+    let ys: Vec<f64> = points.iter().map(|p| p.1).collect();
+    let x_tangents = interior_tangents(&xs, start.x[1], end.x[1]);
+    let y_tangents = interior_tangents(&ys, start.y[1], end.y[1]);
+
+    (0..points.len() - 1)
+        .map(|i| {
+            Spline::cubic_hermite(
+                ControlVector {
+                    x: [xs[i], x_tangents[i], 0.0],
+                    y: [ys[i], y_tangents[i], 0.0],
+                },
+                ControlVector {
+                    x: [xs[i + 1], x_tangents[i + 1], 0.0],
+                    y: [ys[i + 1], y_tangents[i + 1], 0.0],
+                },
+            )
+        })
+        .collect()
+}
+
+/// Tangents at every point for C2 continuity, given the tangents at the ends
+///
+/// Solves `v[i-1] + 4v[i] + v[i+1] = 3(p[i+1] - p[i-1])` with the Thomas algorithm.
+fn interior_tangents(points: &[f64], start: f64, end: f64) -> Vec<f64> {
+    let n = points.len();
+    let mut tangents = vec![0.0; n];
+    tangents[0] = start;
+    tangents[n - 1] = end;
+    if n <= 2 {
+        return tangents;
+    }
+
+    let unknowns = n - 2;
+    let mut diagonal = vec![4.0; unknowns];
+    let mut rhs: Vec<f64> = (1..n - 1)
+        .map(|i| 3.0 * (points[i + 1] - points[i - 1]))
+        .collect();
+    rhs[0] -= start;
+    rhs[unknowns - 1] -= end;
+
+    for i in 1..unknowns {
+        let w = 1.0 / diagonal[i - 1];
+        diagonal[i] -= w;
+        rhs[i] -= w * rhs[i - 1];
+    }
+    tangents[n - 2] = rhs[unknowns - 1] / diagonal[unknowns - 1];
+    for i in (0..unknowns - 1).rev() {
+        tangents[i + 1] = (rhs[i] - tangents[i + 2]) / diagonal[i];
+    }
+    tangents
+}
+
+/// Largest step between samples, chosen so straight-line interpolation between them is accurate
+const MAX_DX: f64 = 0.127;
+const MAX_DY: f64 = 0.00127;
+const MAX_DTHETA: f64 = 0.0872;
+const MAX_ITERATIONS: usize = 5000;
+
+/// Samples `splines` densely enough to follow them closely, subdividing where they curve
+pub fn parameterize(splines: &[Spline]) -> Result<Vec<PoseWithCurvature>> {
+    let first = match splines.first() {
+        Some(spline) => spline,
+        None => bail!("no splines to parameterize"),
+    };
+    let mut points = vec![first.point(0.0)];
+
+    for spline in splines {
+        let mut stack = vec![(0.0, 1.0)];
+        let mut iterations = 0;
+        while let Some((t0, t1)) = stack.pop() {
+            let start = spline.point(t0);
+            let end = spline.point(t1);
+            let twist = start.pose.log(end.pose);
+
+            if twist.dy.get::<meter>().abs() > MAX_DY
+                || twist.dx.get::<meter>().abs() > MAX_DX
+                || twist.dtheta.value.abs() > MAX_DTHETA
+            {
+                let middle = (t0 + t1) / 2.0;
+                stack.push((middle, t1));
+                stack.push((t0, middle));
+            } else {
+                points.push(end);
+            }
+
+            iterations += 1;
+            if iterations >= MAX_ITERATIONS {
+                bail!("spline is malformed, e.g. two waypoints are at the same place");
+            }
+        }
+    }
+
+    Ok(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{assert_position, pose};
+    use tetanus_core::testing::assert_close;
+    use uom::si::angle::degree;
+
+    #[test]
+    fn quintic_splines_hit_waypoints_and_headings() {
+        let waypoints = [
+            pose(0.0, 0.0, 0.0),
+            pose(2.0, 1.0, 90.0),
+            pose(0.0, 2.0, 180.0),
+        ];
+
+        let splines = quintic_splines(&waypoints);
+
+        assert_eq!(splines.len(), 2);
+        assert_position(splines[0].point(0.0).pose, 0.0, 0.0, 1e-9);
+        assert_position(splines[0].point(1.0).pose, 2.0, 1.0, 1e-9);
+        assert_position(splines[1].point(1.0).pose, 0.0, 2.0, 1e-9);
+        let heading = splines[1].point(0.0).pose.rotation.angle();
+        assert_close(heading.get::<degree>(), 90.0, 1e-9);
+    }
+
+    #[test]
+    fn clamped_cubic_is_smooth_through_interior_points() {
+        let interior = [
+            Translation2d::new(Length::new::<meter>(1.0), Length::new::<meter>(1.0)),
+            Translation2d::new(Length::new::<meter>(2.0), Length::new::<meter>(-1.0)),
+        ];
+
+        let splines = clamped_cubic_splines(pose(0.0, 0.0, 0.0), &interior, pose(3.0, 0.0, 0.0));
+
+        assert_eq!(splines.len(), 3);
+        assert_position(splines[0].point(1.0).pose, 1.0, 1.0, 1e-9);
+        assert_position(splines[1].point(1.0).pose, 2.0, -1.0, 1e-9);
+        for pair in splines.windows(2) {
+            let (before, after) = (pair[0].point(1.0), pair[1].point(0.0));
+            assert_close(
+                before.curvature.get::<radian_per_meter>(),
+                after.curvature.get::<radian_per_meter>(),
+                1e-9,
+            );
+        }
+    }
+
+    #[test]
+    fn parameterize_stays_close_between_samples() {
+        let splines = quintic_splines(&[pose(0.0, 0.0, 0.0), pose(3.0, 3.0, 90.0)]);
+
+        let points = parameterize(&splines).unwrap();
+
+        assert_position(points[0].pose, 0.0, 0.0, 1e-9);
+        assert_position(points.last().unwrap().pose, 3.0, 3.0, 1e-9);
+        for pair in points.windows(2) {
+            let twist = pair[0].pose.log(pair[1].pose);
+            assert!(twist.dx.get::<meter>().abs() <= MAX_DX);
+            assert!(twist.dtheta.value.abs() <= MAX_DTHETA);
+        }
+    }
+
+    #[test]
+    fn parameterizing_no_splines_is_an_error() {
+        assert!(parameterize(&[]).is_err());
+    }
+}
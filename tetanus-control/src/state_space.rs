@@ -0,0 +1,483 @@
+//! Linear state-space models, and the controllers and observers designed from them
+//!
+//! A system is `dx/dt = Ax + Bu`, `y = Cx + Du` with `S` states, `I` inputs in volts and `O`
+//! measured outputs, all in base SI units.
+
+use anyhow::{bail, Result};
+use nalgebra::{DMatrix, SMatrix, SVector};
+use tetanus_core::processor::Processor;
+use uom::si::electric_potential::volt;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::mass::kilogram;
+use uom::si::time::second;
+
+use crate::motor::DcMotor;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearSystem<const S: usize, const I: usize, const O: usize> {
+    pub a: SMatrix<f64, S, S>,
+    pub b: SMatrix<f64, S, I>,
+    pub c: SMatrix<f64, O, S>,
+    pub d: SMatrix<f64, O, I>,
+}
+
+impl<const S: usize, const I: usize, const O: usize> LinearSystem<S, I, O> {
+    pub fn new(
+        a: SMatrix<f64, S, S>,
+        b: SMatrix<f64, S, I>,
+        c: SMatrix<f64, O, S>,
+        d: SMatrix<f64, O, I>,
+    ) -> Self {
+        LinearSystem { a, b, c, d }
+    }
+
+    /// State after `dt` with `u` held the whole time
+    pub fn calculate_x(&self, x: SVector<f64, S>, u: SVector<f64, I>, dt: Time) -> SVector<f64, S> {
+        let (a, b) = discretize_ab(&self.a, &self.b, dt);
+        a * x + b * u
+    }
+
+    pub fn calculate_y(&self, x: SVector<f64, S>, u: SVector<f64, I>) -> SVector<f64, O> {
+        self.c * x + self.d * u
+    }
+}
+
+impl LinearSystem<1, 1, 1> {
+    /// Angular velocity of a flywheel with moment of inertia `moi` in kg·m²
+    ///
+    /// `gearing` is motor revolutions per flywheel revolution.
+    pub fn flywheel(motor: DcMotor, moi: f64, gearing: f64) -> Self {
+        let r = motor.resistance().value;
+        LinearSystem::new(
+            SMatrix::<f64, 1, 1>::new(-gearing.powi(2) * motor.kt() / (motor.kv() * r * moi)),
+            SMatrix::<f64, 1, 1>::new(gearing * motor.kt() / (r * moi)),
+            SMatrix::identity(),
+            SMatrix::zeros(),
+        )
+    }
+}
+
+impl LinearSystem<2, 1, 1> {
+    /// Height and velocity of an elevator carriage lifted by a cable on a drum
+    pub fn elevator(motor: DcMotor, mass: Mass, drum_radius: Length, gearing: f64) -> Self {
+        let r = motor.resistance().value;
+        let (m, radius) = (mass.get::<kilogram>(), drum_radius.get::<meter>());
+        LinearSystem::new(
+            SMatrix::<f64, 2, 2>::new(
+                0.0,
+                1.0,
+                0.0,
+                -gearing.powi(2) * motor.kt() / (r * radius.powi(2) * m * motor.kv()),
+            ),
+            SMatrix::<f64, 2, 1>::new(0.0, gearing * motor.kt() / (r * radius * m)),
+            SMatrix::<f64, 1, 2>::new(1.0, 0.0),
+            SMatrix::zeros(),
+        )
+    }
+
+    /// Angle and angular velocity of an arm with moment of inertia `moi` in kg·m², ignoring
+    /// gravity
+    pub fn single_jointed_arm(motor: DcMotor, moi: f64, gearing: f64) -> Self {
+        let r = motor.resistance().value;
+        LinearSystem::new(
+            SMatrix::<f64, 2, 2>::new(
+                0.0,
+                1.0,
+                0.0,
+                -gearing.powi(2) * motor.kt() / (motor.kv() * r * moi),
+            ),
+            SMatrix::<f64, 2, 1>::new(0.0, gearing * motor.kt() / (r * moi)),
+            SMatrix::<f64, 1, 2>::new(1.0, 0.0),
+            SMatrix::zeros(),
+        )
+    }
+}
+
+/// Discrete `A` and `B` for inputs held constant over each `dt`
+pub fn discretize_ab<const S: usize, const I: usize>(
+    a: &SMatrix<f64, S, S>,
+    b: &SMatrix<f64, S, I>,
+    dt: Time,
+) -> (SMatrix<f64, S, S>, SMatrix<f64, S, I>) {
+    let dt = dt.get::<second>();
+    // exp([[A, B], [0, 0]]dt) = [[Ad, Bd], [0, I]]
+    let m = DMatrix::from_fn(S + I, S + I, |i, j| match (i < S, j < S) {
+        (true, true) => a[(i, j)] * dt,
+        (true, false) => b[(i, j - S)] * dt,
+        _ => 0.0,
+    })
+    .exp();
+    (
+        SMatrix::from_fn(|i, j| m[(i, j)]),
+        SMatrix::from_fn(|i, j| m[(i, S + j)]),
+    )
+}
+
+/// Discrete `A` and process noise covariance `Q`, using Van Loan's method
+pub fn discretize_aq<const S: usize>(
+    a: &SMatrix<f64, S, S>,
+    q: &SMatrix<f64, S, S>,
+    dt: Time,
+) -> (SMatrix<f64, S, S>, SMatrix<f64, S, S>) {
+    let dt = dt.get::<second>();
+    // exp([[-A, Q], [0, Aᵀ]]dt) = [[.., Ad⁻¹Qd], [0, Adᵀ]]
+    let m = DMatrix::from_fn(2 * S, 2 * S, |i, j| match (i < S, j < S) {
+        (true, true) => -a[(i, j)] * dt,
+        (true, false) => q[(i, j - S)] * dt,
+        (false, false) => a[(j - S, i - S)] * dt,
+        _ => 0.0,
+    })
+    .exp();
+    let upper = SMatrix::<f64, S, S>::from_fn(|i, j| m[(i, S + j)]);
+    let ad = SMatrix::<f64, S, S>::from_fn(|i, j| m[(S + j, S + i)]);
+    let qd = ad * upper;
+    (ad, (qd + qd.transpose()) / 2.0)
+}
+
+/// Diagonal cost or covariance matrix from the squares of `values`
+fn diagonal_squared<const N: usize>(values: [f64; N]) -> SMatrix<f64, N, N> {
+    SMatrix::from_diagonal(&SVector::from_fn(|i, _| values[i].powi(2)))
+}
+
+/// Bryson's rule: weights each value by one over the square of how far off it may be, so an
+/// infinite tolerance doesn't care about it at all
+fn cost_matrix<const N: usize>(tolerances: [f64; N]) -> SMatrix<f64, N, N> {
+    SMatrix::from_diagonal(&SVector::from_fn(|i, _| 1.0 / tolerances[i].powi(2)))
+}
+
+const MAX_RICCATI_ITERATIONS: usize = 1000;
+
+/// Solves the discrete algebraic Riccati equation `P = AᵀPA - AᵀPB(R + BᵀPB)⁻¹BᵀPA + Q`
+///
+/// Uses the structure-preserving doubling algorithm, which converges in a few dozen steps.
+pub fn solve_dare<const S: usize, const I: usize>(
+    a: &SMatrix<f64, S, S>,
+    b: &SMatrix<f64, S, I>,
+    q: &SMatrix<f64, S, S>,
+    r: &SMatrix<f64, I, I>,
+) -> Result<SMatrix<f64, S, S>> {
+    let r_inverse = match r.try_inverse() {
+        Some(inverse) => inverse,
+        None => bail!("input cost matrix R isn't invertible"),
+    };
+
+    let mut a = *a;
+    let mut g = b * r_inverse * b.transpose();
+    let mut h = *q;
+    for _ in 0..MAX_RICCATI_ITERATIONS {
+        let w = match (SMatrix::<f64, S, S>::identity() + g * h).try_inverse() {
+            Some(inverse) => inverse,
+            None => bail!("Riccati equation is singular, check the system is stabilizable"),
+        };
+        let next_a = a * w * a;
+        let next_g = g + a * w * g * a.transpose();
+        let next_h = h + a.transpose() * h * w * a;
+
+        let change = (next_h - h).norm();
+        a = next_a;
+        g = next_g;
+        h = next_h;
+        if change <= 1e-10 * h.norm() {
+            return Ok(h);
+        }
+    }
+    bail!("Riccati equation didn't converge, check the system is stabilizable")
+}
+
+/// Optimal state feedback `u = K(r - x)` for a linear system
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearQuadraticRegulator<const S: usize, const I: usize> {
+    k: SMatrix<f64, I, S>,
+}
+
+impl<const S: usize, const I: usize> LinearQuadraticRegulator<S, I> {
+    /// Trades off how far off each state may be against how much of each input may be used,
+    /// for a controller running every `dt`
+    pub fn new<const O: usize>(
+        system: &LinearSystem<S, I, O>,
+        state_tolerances: [f64; S],
+        input_tolerances: [f64; I],
+        dt: Time,
+    ) -> Result<Self> {
+        let (a, b) = discretize_ab(&system.a, &system.b, dt);
+        let (q, r) = (cost_matrix(state_tolerances), cost_matrix(input_tolerances));
+        let p = solve_dare(&a, &b, &q, &r)?;
+        let k = match (b.transpose() * p * b + r).try_inverse() {
+            Some(inverse) => inverse * b.transpose() * p * a,
+            None => bail!("couldn't compute LQR gain"),
+        };
+        Ok(LinearQuadraticRegulator { k })
+    }
+
+    pub fn k(&self) -> &SMatrix<f64, I, S> {
+        &self.k
+    }
+
+    pub fn calculate(&self, x: SVector<f64, S>, r: SVector<f64, S>) -> SVector<f64, I> {
+        self.k * (r - x)
+    }
+}
+
+/// Input needed to get from one reference to the next, by inverting the plant
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlantInversionFeedforward<const S: usize, const I: usize> {
+    a: SMatrix<f64, S, S>,
+    /// Least-squares inverse of the discrete `B`
+    b_inverse: SMatrix<f64, I, S>,
+}
+
+impl<const S: usize, const I: usize> PlantInversionFeedforward<S, I> {
+    pub fn new<const O: usize>(system: &LinearSystem<S, I, O>, dt: Time) -> Result<Self> {
+        let (a, b) = discretize_ab(&system.a, &system.b, dt);
+        let b_inverse = match (b.transpose() * b).try_inverse() {
+            Some(inverse) => inverse * b.transpose(),
+            None => bail!("every input needs to affect the system for feedforward"),
+        };
+        Ok(PlantInversionFeedforward { a, b_inverse })
+    }
+
+    pub fn calculate(&self, r: SVector<f64, S>, next_r: SVector<f64, S>) -> SVector<f64, I> {
+        self.b_inverse * (next_r - self.a * r)
+    }
+}
+
+/// Estimates the full state from noisy measurements, using the steady-state Kalman gain
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KalmanFilter<const S: usize, const I: usize, const O: usize> {
+    system: LinearSystem<S, I, O>,
+    a: SMatrix<f64, S, S>,
+    b: SMatrix<f64, S, I>,
+    k: SMatrix<f64, S, O>,
+    xhat: SVector<f64, S>,
+}
+
+impl<const S: usize, const I: usize, const O: usize> KalmanFilter<S, I, O> {
+    /// `state_std_devs` is how much the model is trusted and `measurement_std_devs` how much
+    /// the sensors are, for a filter running every `dt`
+    pub fn new(
+        system: LinearSystem<S, I, O>,
+        state_std_devs: [f64; S],
+        measurement_std_devs: [f64; O],
+        dt: Time,
+    ) -> Result<Self> {
+        let (a, q) = discretize_aq(&system.a, &diagonal_squared(state_std_devs), dt);
+        let (_, b) = discretize_ab(&system.a, &system.b, dt);
+        let r = diagonal_squared(measurement_std_devs) / dt.get::<second>();
+
+        let p = solve_dare(&a.transpose(), &system.c.transpose(), &q, &r)?;
+        let s = system.c * p * system.c.transpose() + r;
+        let k = match s.try_inverse() {
+            Some(inverse) => p * system.c.transpose() * inverse,
+            None => bail!("couldn't compute Kalman gain"),
+        };
+        Ok(KalmanFilter {
+            system,
+            a,
+            b,
+            k,
+            xhat: SVector::zeros(),
+        })
+    }
+
+    pub fn k(&self) -> &SMatrix<f64, S, O> {
+        &self.k
+    }
+
+    pub fn xhat(&self) -> SVector<f64, S> {
+        self.xhat
+    }
+
+    pub fn set_xhat(&mut self, xhat: SVector<f64, S>) {
+        self.xhat = xhat;
+    }
+
+    /// Steps the estimate forward one period with `u` applied
+    pub fn predict(&mut self, u: SVector<f64, I>) {
+        self.xhat = self.a * self.xhat + self.b * u;
+    }
+
+    /// Pulls the estimate towards the measurement `y`, taken while `u` was applied
+    pub fn correct(&mut self, u: SVector<f64, I>, y: SVector<f64, O>) {
+        self.xhat += self.k * (y - self.system.calculate_y(self.xhat, u));
+    }
+}
+
+/// Controller, feedforward and observer working together on one system
+///
+/// Each period, [`correct`](Self::correct) with the latest measurement and then
+/// [`predict`](Self::predict) to get the voltage to apply.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinearSystemLoop<const S: usize, const I: usize, const O: usize> {
+    controller: LinearQuadraticRegulator<S, I>,
+    feedforward: PlantInversionFeedforward<S, I>,
+    observer: KalmanFilter<S, I, O>,
+    max_voltage: ElectricPotential,
+    r: SVector<f64, S>,
+    next_r: SVector<f64, S>,
+    u: SVector<f64, I>,
+}
+
+impl<const S: usize, const I: usize, const O: usize> LinearSystemLoop<S, I, O> {
+    pub fn new(
+        controller: LinearQuadraticRegulator<S, I>,
+        feedforward: PlantInversionFeedforward<S, I>,
+        observer: KalmanFilter<S, I, O>,
+        max_voltage: ElectricPotential,
+    ) -> Self {
+        LinearSystemLoop {
+            controller,
+            feedforward,
+            observer,
+            max_voltage,
+            r: SVector::zeros(),
+            next_r: SVector::zeros(),
+            u: SVector::zeros(),
+        }
+    }
+
+    pub fn xhat(&self) -> SVector<f64, S> {
+        self.observer.xhat()
+    }
+
+    /// Input applied over the last period, in volts
+    pub fn u(&self) -> SVector<f64, I> {
+        self.u
+    }
+
+    pub fn set_next_r(&mut self, next_r: SVector<f64, S>) {
+        self.next_r = next_r;
+    }
+
+    /// Starts again from `x`, with no input and the reference where the system is
+    pub fn reset(&mut self, x: SVector<f64, S>) {
+        self.observer.set_xhat(x);
+        self.r = x;
+        self.next_r = x;
+        self.u = SVector::zeros();
+    }
+
+    pub fn correct(&mut self, y: SVector<f64, O>) {
+        self.observer.correct(self.u, y);
+    }
+
+    /// Works out the input for this period towards the next reference and advances the
+    /// estimate with it
+    pub fn predict(&mut self) -> SVector<f64, I> {
+        let max = self.max_voltage.get::<volt>();
+        let u = self.controller.calculate(self.observer.xhat(), self.r)
+            + self.feedforward.calculate(self.r, self.next_r);
+        self.u = u.map(|v| v.clamp(-max, max));
+        self.r = self.next_r;
+        self.observer.predict(self.u);
+        self.u
+    }
+}
+
+impl<const S: usize, const I: usize, const O: usize> Processor for LinearSystemLoop<S, I, O> {
+    /// Reference to head towards, and the latest measurement
+    type In = (SVector<f64, S>, SVector<f64, O>);
+    /// Volts to apply
+    type Out = SVector<f64, I>;
+
+    fn process(&mut self, (next_r, y): Self::In) -> Self::Out {
+        self.set_next_r(next_r);
+        self.correct(y);
+        self.predict()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::ms;
+    use nalgebra::{Matrix1, Vector1};
+    use tetanus_core::testing::assert_close;
+
+    #[test]
+    fn discretizes_double_integrator() {
+        let a = SMatrix::<f64, 2, 2>::new(0.0, 1.0, 0.0, 0.0);
+        let b = SMatrix::<f64, 2, 1>::new(0.0, 1.0);
+
+        let (ad, bd) = discretize_ab(&a, &b, Time::new::<second>(1.0));
+
+        assert_close(
+            (ad - SMatrix::<f64, 2, 2>::new(1.0, 1.0, 0.0, 1.0)).norm(),
+            0.0,
+            1e-9,
+        );
+        assert_close((bd - SMatrix::<f64, 2, 1>::new(0.5, 1.0)).norm(), 0.0, 1e-9);
+
+        // ∫ e^(Aτ) Q e^(Aᵀτ) dτ over one second, worked out by hand
+        let q = SMatrix::<f64, 2, 2>::new(1.0, 0.0, 0.0, 1.0);
+        let (_, qd) = discretize_aq(&a, &q, Time::new::<second>(1.0));
+        let expected = SMatrix::<f64, 2, 2>::new(4.0 / 3.0, 0.5, 0.5, 1.0);
+        assert_close((qd - expected).norm(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn lqr_gains_match_reference_values() {
+        let elevator = LinearSystem::elevator(
+            DcMotor::vex_775_pro(2),
+            Mass::new::<kilogram>(5.0),
+            Length::new::<meter>(0.0181864),
+            1.0,
+        );
+        let lqr = LinearQuadraticRegulator::new(&elevator, [0.02, 0.4], [12.0], ms(5.05)).unwrap();
+        assert_close(lqr.k()[(0, 0)], 522.153, 0.1);
+        assert_close(lqr.k()[(0, 1)], 38.2, 0.1);
+
+        let moi = 4.0 * 0.4 * 0.4 / 3.0;
+        let arm = LinearSystem::single_jointed_arm(DcMotor::vex_775_pro(2), moi, 100.0);
+        let lqr =
+            LinearQuadraticRegulator::new(&arm, [0.01745, 0.08726], [12.0], ms(5.05)).unwrap();
+        assert_close(lqr.k()[(0, 0)], 19.16, 0.1);
+        assert_close(lqr.k()[(0, 1)], 3.32, 0.1);
+    }
+
+    #[test]
+    fn scalar_kalman_gain_matches_closed_form() {
+        let flywheel = LinearSystem::flywheel(DcMotor::falcon_500(1), 0.002, 1.0);
+        let dt = ms(20.0);
+        let filter = KalmanFilter::new(flywheel, [3.0], [0.01], dt).unwrap();
+
+        // Scalar Riccati equation p = a²p - a²p²/(p + r) + q, solved as a quadratic in p
+        let (a, q) = discretize_aq(&flywheel.a, &Matrix1::new(9.0), dt);
+        let (a, q, r) = (a[(0, 0)], q[(0, 0)], 0.0001 / 0.02);
+        let (qa, qb, qc) = (1.0, r - a * a * r - q, -q * r);
+        let p = (-qb + (qb * qb - 4.0 * qa * qc).sqrt()) / (2.0 * qa);
+        assert_close(filter.k()[(0, 0)], p / (p + r), 1e-9);
+    }
+
+    #[test]
+    fn flywheel_loop_reaches_speed_through_noise_free_measurements() {
+        let flywheel = LinearSystem::flywheel(DcMotor::falcon_500(2), 0.00032, 1.5);
+        let dt = ms(20.0);
+        let controller = LinearQuadraticRegulator::new(&flywheel, [8.0], [12.0], dt).unwrap();
+        let feedforward = PlantInversionFeedforward::new(&flywheel, dt).unwrap();
+        let observer = KalmanFilter::new(flywheel, [3.0], [0.01], dt).unwrap();
+        let mut controlled = LinearSystemLoop::new(
+            controller,
+            feedforward,
+            observer,
+            ElectricPotential::new::<volt>(12.0),
+        );
+
+        let target = Vector1::new(200.0);
+        let mut x = Vector1::zeros();
+        for _ in 0..100 {
+            let u = controlled.process((target, flywheel.calculate_y(x, controlled.u())));
+            assert!(u[0].abs() <= 12.0);
+            x = flywheel.calculate_x(x, u, dt);
+        }
+
+        assert_close(x[0], 200.0, 0.5);
+        assert_close(controlled.xhat()[0], 200.0, 0.5);
+        // Holding speed only takes the feedforward's voltage against back EMF
+        let back_emf = 200.0 * 1.5 / DcMotor::falcon_500(2).kv();
+        assert_close(controlled.u()[0], back_emf, 0.1);
+
+        controlled.reset(Vector1::zeros());
+        assert_eq!(controlled.xhat(), Vector1::zeros());
+    }
+}
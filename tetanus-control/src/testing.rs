@@ -0,0 +1,51 @@
+//! Helpers shared by this crate's tests
+
+use tetanus_core::testing::assert_close;
+use uom::si::angle::degree;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::time::millisecond;
+use uom::si::velocity::meter_per_second;
+
+use crate::geometry::Pose2d;
+
+pub fn m(value: f64) -> Length {
+    Length::new::<meter>(value)
+}
+
+pub fn deg(value: f64) -> Angle {
+    Angle::new::<degree>(value)
+}
+
+pub fn ms(value: f64) -> Time {
+    Time::new::<millisecond>(value)
+}
+
+pub fn mps(value: f64) -> Velocity {
+    Velocity::new::<meter_per_second>(value)
+}
+
+/// Pose from meters and degrees
+pub fn pose(x: f64, y: f64, degrees: f64) -> Pose2d {
+    Pose2d::from_xy_angle(m(x), m(y), deg(degrees))
+}
+
+/// Checks `actual` is within `tolerance` meters of `(x, y)`, ignoring its heading
+#[track_caller]
+pub fn assert_position(actual: Pose2d, x: f64, y: f64, tolerance: f64) {
+    assert_close(actual.x().get::<meter>(), x, tolerance);
+    assert_close(actual.y().get::<meter>(), y, tolerance);
+}
+
+/// Checks `actual` is within `tolerance` meters and degrees of `expected`
+#[track_caller]
+pub fn assert_pose(actual: Pose2d, expected: Pose2d, tolerance: f64) {
+    assert_position(
+        actual,
+        expected.x().get::<meter>(),
+        expected.y().get::<meter>(),
+        tolerance,
+    );
+    let heading_error = (actual.rotation - expected.rotation).angle();
+    assert_close(heading_error.get::<degree>(), 0.0, tolerance);
+}
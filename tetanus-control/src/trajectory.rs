@@ -0,0 +1,513 @@
+//! Time-parameterized paths for autonomous driving
+
+use std::f64::consts::PI;
+
+use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use tetanus_core::rate::Interpolate;
+use uom::si::acceleration::meter_per_second_squared;
+use uom::si::angle::radian;
+use uom::si::f64::*;
+use uom::si::length::meter;
+use uom::si::time::second;
+use uom::si::velocity::meter_per_second;
+
+use crate::constraint::TrajectoryConstraint;
+use crate::geometry::{Pose2d, Rotation2d, Translation2d};
+use crate::spline::{self, PoseWithCurvature, Spline};
+
+const EPSILON: f64 = 1e-6;
+
+/// Where the robot should be at a point in time
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrajectoryState {
+    pub time: Time,
+    /// Negative when driving backwards
+    pub velocity: Velocity,
+    pub acceleration: Acceleration,
+    pub pose: Pose2d,
+    pub curvature: Curvature,
+}
+
+impl TrajectoryState {
+    /// Integrates this state's acceleration forward, assuming it stays constant until `end`
+    fn interpolate(self, end: TrajectoryState, t: f64) -> TrajectoryState {
+        let time = self.time.lerp(end.time, t);
+        let dt = (time - self.time).get::<second>();
+        if dt < 0.0 {
+            return end.interpolate(self, 1.0 - t);
+        }
+
+        let v = self.velocity.get::<meter_per_second>();
+        let a = self.acceleration.get::<meter_per_second_squared>();
+        let reversing = v < 0.0 || (v == 0.0 && end.velocity.value < 0.0);
+        let travelled = (v * dt + 0.5 * a * dt * dt).abs();
+        let segment = self
+            .pose
+            .translation
+            .distance(end.pose.translation)
+            .get::<meter>();
+        let fraction = if segment > 0.0 {
+            travelled / segment
+        } else {
+            0.0
+        };
+        let velocity = v + a * dt;
+
+        TrajectoryState {
+            time,
+            velocity: Velocity::new::<meter_per_second>(if reversing {
+                velocity.min(0.0)
+            } else {
+                velocity
+            }),
+            acceleration: self.acceleration,
+            pose: self.pose.lerp(end.pose, fraction),
+            curvature: self.curvature.lerp(end.curvature, fraction),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Trajectory {
+    states: Vec<TrajectoryState>,
+}
+
+impl Trajectory {
+    pub fn new(states: Vec<TrajectoryState>) -> Self {
+        Trajectory { states }
+    }
+
+    pub fn states(&self) -> &[TrajectoryState] {
+        &self.states
+    }
+
+    pub fn total_time(&self) -> Time {
+        self.states.last().map(|s| s.time).unwrap_or_default()
+    }
+
+    pub fn initial_pose(&self) -> Pose2d {
+        self.states.first().map(|s| s.pose).unwrap_or_default()
+    }
+
+    /// State at `time`, clamped to the start and end of the trajectory
+    pub fn sample(&self, time: Time) -> TrajectoryState {
+        let (first, last) = match (self.states.first(), self.states.last()) {
+            (Some(first), Some(last)) => (*first, *last),
+            _ => return TrajectoryState::default(),
+        };
+        if time <= first.time {
+            return first;
+        }
+        if time >= last.time {
+            return last;
+        }
+
+        let after = self.states.partition_point(|s| s.time < time);
+        let (previous, next) = (self.states[after - 1], self.states[after]);
+        if (next.time - previous.time).get::<second>().abs() < EPSILON {
+            return next;
+        }
+        previous.interpolate(
+            next,
+            ((time - previous.time) / (next.time - previous.time)).value,
+        )
+    }
+}
+
+pub struct TrajectoryConfig {
+    pub max_velocity: Velocity,
+    pub max_acceleration: Acceleration,
+    pub start_velocity: Velocity,
+    pub end_velocity: Velocity,
+    /// Drive the path backwards
+    pub reversed: bool,
+    constraints: Vec<Box<dyn TrajectoryConstraint>>,
+}
+
+impl TrajectoryConfig {
+    pub fn new(max_velocity: Velocity, max_acceleration: Acceleration) -> Self {
+        TrajectoryConfig {
+            max_velocity,
+            max_acceleration,
+            start_velocity: Velocity::default(),
+            end_velocity: Velocity::default(),
+            reversed: false,
+            constraints: Vec::new(),
+        }
+    }
+
+    pub fn add_constraint(&mut self, constraint: impl TrajectoryConstraint + 'static) -> &mut Self {
+        self.constraints.push(Box::new(constraint));
+        self
+    }
+}
+
+/// Trajectory through every waypoint, using their headings, along quintic splines
+pub fn generate_quintic(waypoints: &[Pose2d], config: &TrajectoryConfig) -> Result<Trajectory> {
+    if waypoints.len() < 2 {
+        bail!("a trajectory needs at least two waypoints");
+    }
+    let waypoints: Vec<Pose2d> = waypoints.iter().map(|w| flip(*w, config)).collect();
+    generate(&spline::quintic_splines(&waypoints), config)
+}
+
+/// Trajectory from `start` to `end` through `interior`, along clamped cubic splines
+pub fn generate_clamped_cubic(
+    start: Pose2d,
+    interior: &[Translation2d],
+    end: Pose2d,
+    config: &TrajectoryConfig,
+) -> Result<Trajectory> {
+    let splines = spline::clamped_cubic_splines(flip(start, config), interior, flip(end, config));
+    generate(&splines, config)
+}
+
+/// Reversed paths are built facing the other way, then turned back around
+fn flip(pose: Pose2d, config: &TrajectoryConfig) -> Pose2d {
+    if config.reversed {
+        Pose2d::new(
+            pose.translation,
+            pose.rotation + Rotation2d::new(Angle::new::<radian>(PI)),
+        )
+    } else {
+        pose
+    }
+}
+
+fn generate(splines: &[Spline], config: &TrajectoryConfig) -> Result<Trajectory> {
+    Ok(generate_with_speed_limits(splines, &[], config)?.0)
+}
+
+/// Trajectory along `splines`, with spline `i` limited to `limits[i]` if given
+///
+/// Also returns the index of the state each spline starts at.
+pub(crate) fn generate_with_speed_limits(
+    splines: &[Spline],
+    limits: &[Option<Velocity>],
+    config: &TrajectoryConfig,
+) -> Result<(Trajectory, Vec<usize>)> {
+    if splines.is_empty() {
+        bail!("a trajectory needs at least one spline");
+    }
+    let mut points = Vec::new();
+    let mut caps = Vec::new();
+    let mut starts = Vec::with_capacity(splines.len());
+    for (i, spline) in splines.iter().enumerate() {
+        let segment = spline::parameterize(std::slice::from_ref(spline))?;
+        // Each spline starts where the last one ended
+        let skip = if i == 0 { 0 } else { 1 };
+        let cap = limits
+            .get(i)
+            .copied()
+            .flatten()
+            .map_or(f64::INFINITY, |limit| limit.get::<meter_per_second>().abs());
+
+        starts.push(points.len().saturating_sub(1));
+        caps.resize(caps.len() + segment.len() - skip, cap);
+        points.extend(segment.into_iter().skip(skip));
+    }
+
+    if config.reversed {
+        for point in &mut points {
+            *point = PoseWithCurvature {
+                pose: flip(point.pose, config),
+                curvature: -point.curvature,
+            };
+        }
+    }
+    Ok((time_parameterize(&points, &caps, config)?, starts))
+}
+
+/// Limits found for each point along the path, in base SI units
+#[derive(Clone, Copy, Debug)]
+struct Constrained {
+    point: PoseWithCurvature,
+    distance: f64,
+    max_velocity: f64,
+    min_acceleration: f64,
+    max_acceleration: f64,
+}
+
+/// Fastest speed at each point that respects every limit, found by a forward pass limiting
+/// acceleration and a backward pass limiting deceleration
+fn time_parameterize(
+    points: &[PoseWithCurvature],
+    caps: &[f64],
+    config: &TrajectoryConfig,
+) -> Result<Trajectory> {
+    let max_velocity = config.max_velocity.get::<meter_per_second>().abs();
+    let max_acceleration = config
+        .max_acceleration
+        .get::<meter_per_second_squared>()
+        .abs();
+    let unconstrained = |point: PoseWithCurvature| Constrained {
+        point,
+        distance: 0.0,
+        max_velocity,
+        min_acceleration: -max_acceleration,
+        max_acceleration,
+    };
+
+    let mut states: Vec<Constrained> = Vec::with_capacity(points.len());
+    let mut predecessor = Constrained {
+        max_velocity: config.start_velocity.get::<meter_per_second>().abs(),
+        ..unconstrained(points[0])
+    };
+
+    for (point, cap) in points.iter().zip(caps) {
+        let mut state = unconstrained(*point);
+        let ds = point
+            .pose
+            .translation
+            .distance(predecessor.point.pose.translation)
+            .get::<meter>();
+        state.distance = predecessor.distance + ds;
+
+        loop {
+            state.max_velocity = max_velocity.min(*cap).min(
+                (predecessor.max_velocity.powi(2) + 2.0 * predecessor.max_acceleration * ds).sqrt(),
+            );
+            state.min_acceleration = -max_acceleration;
+            state.max_acceleration = max_acceleration;
+            for constraint in &config.constraints {
+                state.max_velocity = state.max_velocity.min(
+                    constraint
+                        .max_velocity(
+                            point.pose,
+                            point.curvature,
+                            Velocity::new::<meter_per_second>(state.max_velocity),
+                        )
+                        .get::<meter_per_second>(),
+                );
+            }
+            enforce_acceleration_limits(&mut state, config)?;
+
+            if ds < EPSILON {
+                break;
+            }
+            let actual =
+                (state.max_velocity.powi(2) - predecessor.max_velocity.powi(2)) / (2.0 * ds);
+            if state.max_acceleration < actual - EPSILON {
+                // Accelerate less out of the previous point so this one can be reached
+                predecessor.max_acceleration = state.max_acceleration;
+            } else {
+                break;
+            }
+        }
+
+        states.push(state);
+        predecessor = state;
+    }
+
+    let mut successor = Constrained {
+        max_velocity: config.end_velocity.get::<meter_per_second>().abs(),
+        ..*states.last().unwrap()
+    };
+    for state in states.iter_mut().rev() {
+        let ds = state.distance - successor.distance;
+
+        loop {
+            let new_max_velocity =
+                (successor.max_velocity.powi(2) + 2.0 * successor.min_acceleration * ds).sqrt();
+            if new_max_velocity >= state.max_velocity {
+                break;
+            }
+            state.max_velocity = new_max_velocity;
+            if ds.abs() < EPSILON {
+                break;
+            }
+            enforce_acceleration_limits(state, config)?;
+
+            let actual = (state.max_velocity.powi(2) - successor.max_velocity.powi(2)) / (2.0 * ds);
+            if state.min_acceleration > actual + EPSILON {
+                successor.min_acceleration = state.min_acceleration;
+            } else {
+                break;
+            }
+        }
+        successor = *state;
+    }
+
+    let sign = if config.reversed { -1.0 } else { 1.0 };
+    let mut trajectory = Vec::with_capacity(states.len());
+    let (mut time, mut distance, mut velocity) = (0.0, 0.0, 0.0_f64);
+    for (i, state) in states.iter().enumerate() {
+        let ds = state.distance - distance;
+        let acceleration = if ds.abs() < EPSILON {
+            0.0
+        } else {
+            (state.max_velocity.powi(2) - velocity.powi(2)) / (2.0 * ds)
+        };
+
+        let mut dt = 0.0;
+        if i > 0 {
+            let previous: &mut TrajectoryState = trajectory.last_mut().unwrap();
+            previous.acceleration =
+                Acceleration::new::<meter_per_second_squared>(sign * acceleration);
+            if acceleration.abs() > EPSILON {
+                dt = (state.max_velocity - velocity) / acceleration;
+            } else if velocity.abs() > EPSILON {
+                dt = ds / velocity;
+            } else if ds > EPSILON {
+                bail!("trajectory is stopped partway along, check the constraints");
+            }
+        }
+
+        velocity = state.max_velocity;
+        distance = state.distance;
+        time += dt;
+        trajectory.push(TrajectoryState {
+            time: Time::new::<second>(time),
+            velocity: Velocity::new::<meter_per_second>(sign * velocity),
+            acceleration: Acceleration::new::<meter_per_second_squared>(sign * acceleration),
+            pose: state.point.pose,
+            curvature: state.point.curvature,
+        });
+    }
+
+    Ok(Trajectory::new(trajectory))
+}
+
+fn enforce_acceleration_limits(state: &mut Constrained, config: &TrajectoryConfig) -> Result<()> {
+    let sign = if config.reversed { -1.0 } else { 1.0 };
+    for constraint in &config.constraints {
+        let (min, max) = constraint.acceleration_limits(
+            state.point.pose,
+            state.point.curvature,
+            Velocity::new::<meter_per_second>(sign * state.max_velocity),
+        );
+        let (min, max) = (
+            min.get::<meter_per_second_squared>(),
+            max.get::<meter_per_second_squared>(),
+        );
+        if min > max {
+            bail!("trajectory constraint gives a minimum acceleration above its maximum");
+        }
+        let (min, max) = if config.reversed {
+            (-max, -min)
+        } else {
+            (min, max)
+        };
+        state.min_acceleration = state.min_acceleration.max(min);
+        state.max_acceleration = state.max_acceleration.min(max);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constraint::{
+        CentripetalAccelerationConstraint, MaxVelocityConstraint, RegionConstraint,
+    };
+    use crate::testing::pose;
+    use tetanus_core::testing::assert_close;
+
+    fn config(max_velocity: f64, max_acceleration: f64) -> TrajectoryConfig {
+        TrajectoryConfig::new(
+            Velocity::new::<meter_per_second>(max_velocity),
+            Acceleration::new::<meter_per_second_squared>(max_acceleration),
+        )
+    }
+
+    #[track_caller]
+    fn assert_within_limits(trajectory: &Trajectory, max_velocity: f64, max_acceleration: f64) {
+        for state in trajectory.states() {
+            assert!(state.velocity.get::<meter_per_second>().abs() <= max_velocity + 1e-9);
+            assert!(
+                state.acceleration.get::<meter_per_second_squared>().abs()
+                    <= max_acceleration + 1e-9
+            );
+        }
+    }
+
+    #[test]
+    fn straight_line_matches_trapezoid() {
+        let trajectory = generate_quintic(
+            &[pose(0.0, 0.0, 0.0), pose(4.0, 0.0, 0.0)],
+            &config(2.0, 1.0),
+        )
+        .unwrap();
+
+        assert_within_limits(&trajectory, 2.0, 1.0);
+        assert_close(trajectory.total_time().get::<second>(), 4.0, 1e-3);
+        let middle = trajectory.sample(Time::new::<second>(2.0));
+        assert_close(middle.pose.x().get::<meter>(), 2.0, 1e-3);
+        assert_close(middle.velocity.get::<meter_per_second>(), 2.0, 1e-3);
+
+        let end = trajectory.sample(Time::new::<second>(10.0));
+        assert_close(end.pose.x().get::<meter>(), 4.0, 1e-9);
+        assert_close(end.velocity.get::<meter_per_second>(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn centripetal_constraint_slows_the_turn() {
+        let waypoints = [
+            pose(0.0, 0.0, 0.0),
+            pose(2.0, 2.0, 90.0),
+            pose(0.0, 4.0, 180.0),
+        ];
+        let mut limited = config(3.0, 2.0);
+        limited.add_constraint(CentripetalAccelerationConstraint::new(Acceleration::new::<
+            meter_per_second_squared,
+        >(1.0)));
+
+        let fast = generate_quintic(&waypoints, &config(3.0, 2.0)).unwrap();
+        let slow = generate_quintic(&waypoints, &limited).unwrap();
+
+        assert!(slow.total_time() > fast.total_time());
+        for state in slow.states() {
+            let v = state.velocity.get::<meter_per_second>();
+            let k = state.curvature.value;
+            assert!(v * v * k.abs() <= 1.0 + 1e-6);
+        }
+    }
+
+    #[test]
+    fn region_constraint_limits_speed_inside() {
+        let point =
+            |x: f64, y: f64| Translation2d::new(Length::new::<meter>(x), Length::new::<meter>(y));
+        let mut config = config(3.0, 2.0);
+        config.add_constraint(RegionConstraint::new(
+            point(2.0, -1.0),
+            point(3.0, 1.0),
+            MaxVelocityConstraint::new(Velocity::new::<meter_per_second>(0.5)),
+        ));
+
+        let trajectory = generate_clamped_cubic(
+            pose(0.0, 0.0, 0.0),
+            &[point(2.5, 0.0)],
+            pose(6.0, 0.0, 0.0),
+            &config,
+        )
+        .unwrap();
+
+        for state in trajectory.states() {
+            if (2.0..=3.0).contains(&state.pose.x().get::<meter>()) {
+                assert!(state.velocity.get::<meter_per_second>() <= 0.5 + 1e-9);
+            }
+        }
+        assert_within_limits(&trajectory, 3.0, 2.0);
+    }
+
+    #[test]
+    fn reversed_trajectory_drives_backwards() {
+        let mut config = config(2.0, 1.0);
+        config.reversed = true;
+
+        let trajectory =
+            generate_quintic(&[pose(3.0, 0.0, 0.0), pose(0.0, 0.0, 0.0)], &config).unwrap();
+
+        let middle = trajectory.sample(trajectory.total_time() / 2.0);
+        assert!(middle.velocity.get::<meter_per_second>() < 0.0);
+        assert_close(middle.pose.rotation.radians(), 0.0, 1e-9);
+        let end = trajectory.sample(trajectory.total_time());
+        assert_close(end.pose.x().get::<meter>(), 0.0, 1e-9);
+    }
+
+    #[test]
+    fn no_splines_is_an_error() {
+        assert!(generate_with_speed_limits(&[], &[], &config(2.0, 1.0)).is_err());
+    }
+}
@@ -0,0 +1,71 @@
+use std::fmt::Debug;
+
+use uom::si::f64::*;
+use uom::si::{Dimension, Quantity, Units};
+
+/// Values that can be converted to and from a plain number in base SI units
+///
+/// Lets controllers work on any quantity without caring about its dimension.
+pub trait SiValue: Copy {
+    fn si(self) -> f64;
+
+    fn from_si(value: f64) -> Self;
+}
+
+impl SiValue for f64 {
+    fn si(self) -> f64 {
+        self
+    }
+
+    fn from_si(value: f64) -> Self {
+        value
+    }
+}
+
+impl<D, U> SiValue for Quantity<D, U, f64>
+where
+    D: Dimension + ?Sized,
+    U: Units<f64> + ?Sized,
+{
+    fn si(self) -> f64 {
+        self.value
+    }
+
+    fn from_si(value: f64) -> Self {
+        Quantity {
+            dimension: Default::default(),
+            units: Default::default(),
+            value,
+        }
+    }
+}
+
+/// Position-like quantity along with the quantities of its time derivatives
+pub trait Motion: SiValue + Debug + PartialEq + Send + Sync {
+    type Velocity: SiValue + Debug + PartialEq + Send + Sync;
+    type Acceleration: SiValue + Debug + PartialEq + Send + Sync;
+    type Jerk: SiValue + Debug + PartialEq + Send + Sync;
+}
+
+impl Motion for Length {
+    type Velocity = Velocity;
+    type Acceleration = Acceleration;
+    type Jerk = Jerk;
+}
+
+impl Motion for Angle {
+    type Velocity = AngularVelocity;
+    type Acceleration = AngularAcceleration;
+    type Jerk = AngularJerk;
+}
+
+impl Motion for f64 {
+    type Velocity = f64;
+    type Acceleration = f64;
+    type Jerk = f64;
+}
+
+/// Wraps `value` into the range `[min, max)`
+pub fn input_modulus(value: f64, min: f64, max: f64) -> f64 {
+    min + (value - min).rem_euclid(max - min)
+}
@@ -0,0 +1,228 @@
+//! Typed named topics for connecting parts of the graph that are built in different places
+
+use std::any::{self, Any, TypeId};
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::node::{Node, NodeChildren, NodeReceiver};
+
+/// Name of a topic along with the type of message it carries
+pub struct Topic<T> {
+    name: &'static str,
+    msg: PhantomData<fn() -> T>,
+}
+
+impl<T> Topic<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Topic {
+            name,
+            msg: PhantomData,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+}
+
+// #derive(Clone, Copy) would require T: Clone + Copy
+impl<T> Clone for Topic<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Topic<T> {}
+
+/// Snapshot of a topic for telemetry
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopicInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+    pub publishers: usize,
+    pub subscribers: usize,
+    pub messages: u64,
+}
+
+struct TopicEntry {
+    type_id: TypeId,
+    type_name: &'static str,
+    hub: Box<dyn Any + Send>,
+    publishers: usize,
+    subscribers: usize,
+    messages: Arc<AtomicU64>,
+}
+
+/// Registry of topics shared by everything that builds the graph
+///
+/// Clones refer to the same set of topics.
+#[derive(Clone, Default)]
+pub struct Bus {
+    topics: Arc<Mutex<BTreeMap<&'static str, TopicEntry>>>,
+}
+
+impl Bus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the node to chain messages into for `topic`
+    pub fn publisher<T: Copy + Send + 'static>(&self, topic: &Topic<T>) -> Result<TopicNode<T>> {
+        self.register(topic, |entry| entry.publishers += 1)
+    }
+
+    /// Returns a node that receives every message published to `topic`
+    pub fn subscribe<T: Copy + Send + 'static>(&self, topic: &Topic<T>) -> Result<TopicNode<T>> {
+        self.register(topic, |entry| entry.subscribers += 1)
+    }
+
+    pub fn topics(&self) -> Vec<TopicInfo> {
+        self.topics
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, entry)| TopicInfo {
+                name,
+                type_name: entry.type_name,
+                publishers: entry.publishers,
+                subscribers: entry.subscribers,
+                messages: entry.messages.load(Ordering::Relaxed),
+            })
+            .collect()
+    }
+
+    fn register<T: Copy + Send + 'static>(
+        &self,
+        topic: &Topic<T>,
+        update: impl FnOnce(&mut TopicEntry),
+    ) -> Result<TopicNode<T>> {
+        let mut topics = self.topics.lock().unwrap();
+        let entry = topics.entry(topic.name).or_insert_with(|| {
+            let hub = TopicNode::<T> {
+                children: Arc::new(Mutex::new(Vec::new())),
+                messages: Arc::new(AtomicU64::new(0)),
+            };
+
+            TopicEntry {
+                type_id: TypeId::of::<T>(),
+                type_name: any::type_name::<T>(),
+                messages: hub.messages.clone(),
+                hub: Box::new(hub),
+                publishers: 0,
+                subscribers: 0,
+            }
+        });
+
+        if entry.type_id != TypeId::of::<T>() {
+            bail!(
+                "Topic \"{}\" carries {}, not {}",
+                topic.name,
+                entry.type_name,
+                any::type_name::<T>()
+            );
+        }
+
+        update(entry);
+        Ok(entry.hub.downcast_ref::<TopicNode<T>>().unwrap().clone())
+    }
+}
+
+#[derive(Clone)]
+pub struct TopicNode<T: Copy + 'static> {
+    children: NodeChildren<'static, T>,
+    messages: Arc<AtomicU64>,
+}
+
+impl<T: Copy + Send + 'static> NodeReceiver for TopicNode<T> {
+    type In = T;
+
+    fn send(&self, msg: Self::In) {
+        self.messages.fetch_add(1, Ordering::Relaxed);
+        for child in self.children.lock().unwrap().iter() {
+            child.send(msg);
+        }
+    }
+}
+
+impl<T: Copy + Send + 'static> Node<'static> for TopicNode<T> {
+    type Out = T;
+
+    fn chain<NewOut: Copy>(
+        &mut self,
+        other: impl Node<'static, In = Self::Out, Out = NewOut> + 'static,
+    ) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::BaseNode;
+    use crate::testing::Probe;
+
+    const SPEED: Topic<f64> = Topic::new("drive/speed");
+
+    #[test]
+    fn subscribers_receive_published_messages() {
+        let bus = Bus::new();
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+
+        bus.subscribe(&SPEED).unwrap().chain(probe.clone());
+        source
+            .map(|x: f64| x * 2.0)
+            .chain(bus.publisher(&SPEED).unwrap());
+
+        source.send(1.0);
+        source.send(1.5);
+
+        probe.assert_received(&[2.0, 3.0]);
+    }
+
+    #[test]
+    fn mismatched_types_fail_at_registration() {
+        let bus = Bus::new();
+        bus.publisher(&SPEED).unwrap();
+
+        let err = bus
+            .subscribe(&Topic::<bool>::new("drive/speed"))
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("carries f64, not bool"));
+    }
+
+    #[test]
+    fn topics_are_introspectable() {
+        let bus = Bus::new();
+        let enabled = Topic::<bool>::new("robot/enabled");
+        bus.subscribe(&enabled).unwrap();
+        bus.subscribe(&SPEED).unwrap();
+        bus.subscribe(&SPEED).unwrap();
+        bus.publisher(&SPEED).unwrap().send(4.0);
+
+        assert_eq!(
+            bus.topics(),
+            [
+                TopicInfo {
+                    name: "drive/speed",
+                    type_name: "f64",
+                    publishers: 1,
+                    subscribers: 2,
+                    messages: 1,
+                },
+                TopicInfo {
+                    name: "robot/enabled",
+                    type_name: "bool",
+                    publishers: 0,
+                    subscribers: 1,
+                    messages: 0,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use std::time::Instant;
+
+use uom::si::f64::*;
+use uom::si::time::second;
+
+/// Source of the current time for nodes that need it
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Time;
+}
+
+/// Monotonic host clock, starting at zero when created
+#[derive(Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        SystemClock {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> Time {
+        Time::new::<second>(self.start.elapsed().as_secs_f64())
+    }
+}
@@ -1,5 +1,12 @@
+use crate::resource::Resource;
+
 pub trait Consumer: Send + Sync {
     type Msg: Copy;
 
     fn output(&mut self, msg: Self::Msg);
+
+    /// Resources this consumer needs exclusive use of, checked by [`crate::node::Node::consume_exclusive`]
+    fn resources(&self) -> Vec<Resource> {
+        Vec::new()
+    }
 }
@@ -0,0 +1,578 @@
+//! Hierarchical state machines for superstructure logic
+
+use std::collections::VecDeque;
+use std::fmt::{self, Debug};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use uom::si::f64::*;
+use uom::si::time::second;
+
+use crate::clock::Clock;
+use crate::node::{Node, NodeChildren, NodeReceiver};
+
+type Action = Box<dyn FnMut() + Send>;
+type Guard = Box<dyn Fn(Time) -> bool + Send>;
+
+struct Transition<S, E> {
+    from: S,
+    event: E,
+    to: S,
+    guard: Option<Guard>,
+}
+
+/// Entry in a state machine's transition history
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TransitionRecord<S, E> {
+    pub from: S,
+    pub to: S,
+    pub event: E,
+    pub at: Time,
+}
+
+impl<S: Debug, E: Debug> fmt::Display for TransitionRecord<S, E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:.3} s: {:?} -> {:?} on {:?}",
+            self.at.get::<second>(),
+            self.from,
+            self.to,
+            self.event
+        )
+    }
+}
+
+/// State machine with typed states and events
+///
+/// States can be nested with [`StateMachine::parent`]. Being in a state also means being in all of
+/// its ancestors, so events a state doesn't handle are handled by its parent, and states without
+/// an output use their parent's.
+pub struct StateMachine<S, E, O> {
+    current: S,
+    /// Active states from the outermost ancestor to `current`, with the time each was entered
+    active: Vec<(S, Time)>,
+    started: bool,
+    parents: Vec<(S, S)>,
+    entry_actions: Vec<(S, Action)>,
+    exit_actions: Vec<(S, Action)>,
+    outputs: Vec<(S, O)>,
+    transitions: Vec<Transition<S, E>>,
+    /// Most recent transitions, oldest first, holding at most `history_capacity`
+    history: VecDeque<TransitionRecord<S, E>>,
+    history_capacity: usize,
+    /// Name transitions are logged under, if logging is enabled
+    log_name: Option<&'static str>,
+    clock: Box<dyn Clock>,
+}
+
+impl<S, E, O> StateMachine<S, E, O>
+where
+    S: Copy + PartialEq + Debug,
+    E: Copy + PartialEq + Debug,
+    O: Copy + Default,
+{
+    /// Transitions kept by default, enough for a match's worth of superstructure changes
+    pub const DEFAULT_HISTORY_CAPACITY: usize = 256;
+
+    pub fn new(initial: S, clock: impl Clock + 'static) -> Self {
+        StateMachine {
+            current: initial,
+            active: Vec::new(),
+            started: false,
+            parents: Vec::new(),
+            entry_actions: Vec::new(),
+            exit_actions: Vec::new(),
+            outputs: Vec::new(),
+            transitions: Vec::new(),
+            history: VecDeque::new(),
+            history_capacity: Self::DEFAULT_HISTORY_CAPACITY,
+            log_name: None,
+            clock: Box::new(clock),
+        }
+    }
+
+    /// Nests `state` inside `parent`
+    pub fn parent(&mut self, state: S, parent: S) -> Result<&mut Self> {
+        if self.ancestors(parent).contains(&state) {
+            bail!(
+                "Making {:?} the parent of {:?} would create a cycle",
+                parent,
+                state
+            );
+        }
+        if self.parents.iter().any(|(child, _)| *child == state) {
+            bail!("{:?} already has a parent", state);
+        }
+
+        self.parents.push((state, parent));
+        Ok(self)
+    }
+
+    pub fn on_entry(&mut self, state: S, action: impl FnMut() + Send + 'static) -> &mut Self {
+        self.entry_actions.push((state, Box::new(action)));
+        self
+    }
+
+    pub fn on_exit(&mut self, state: S, action: impl FnMut() + Send + 'static) -> &mut Self {
+        self.exit_actions.push((state, Box::new(action)));
+        self
+    }
+
+    /// Sets what the machine outputs while in `state` (or a child of it without its own output)
+    pub fn output(&mut self, state: S, output: O) -> &mut Self {
+        self.outputs.retain(|(s, _)| *s != state);
+        self.outputs.push((state, output));
+        self
+    }
+
+    /// Moves from `from` (or any state nested in it) to `to` when `event` is fired
+    pub fn transition(&mut self, from: S, event: E, to: S) -> &mut Self {
+        self.transitions.push(Transition {
+            from,
+            event,
+            to,
+            guard: None,
+        });
+        self
+    }
+
+    /// Like [`StateMachine::transition`], but only if `guard` returns true when given the time
+    /// spent in `from`
+    pub fn guarded_transition(
+        &mut self,
+        from: S,
+        event: E,
+        to: S,
+        guard: impl Fn(Time) -> bool + Send + 'static,
+    ) -> &mut Self {
+        self.transitions.push(Transition {
+            from,
+            event,
+            to,
+            guard: Some(Box::new(guard)),
+        });
+        self
+    }
+
+    /// Keeps only the last `capacity` transitions, dropping older ones
+    pub fn history_capacity(&mut self, capacity: usize) -> &mut Self {
+        self.history_capacity = capacity;
+        self.trim_history();
+        self
+    }
+
+    /// Prints each transition to stdout, which ends up in the robot's log, prefixed by `name`
+    pub fn log_transitions(&mut self, name: &'static str) -> &mut Self {
+        self.log_name = Some(name);
+        self
+    }
+
+    /// Enters the initial state, running its entry actions
+    ///
+    /// Called automatically by the first [`StateMachine::fire`].
+    pub fn start(&mut self) {
+        if self.started {
+            return;
+        }
+        self.started = true;
+
+        let now = self.clock.now();
+        let mut path = self.ancestors(self.current);
+        path.reverse();
+        for state in path {
+            self.enter(state, now);
+        }
+    }
+
+    /// Handles `event`, returning whether it caused a transition
+    ///
+    /// Transitions from the current state are checked first, then those of each of its ancestors.
+    pub fn fire(&mut self, event: E) -> bool {
+        self.start();
+        let now = self.clock.now();
+
+        let mut target = None;
+        for (state, entered) in self.active.iter().rev() {
+            let time_in_state = now - *entered;
+            target = self
+                .transitions
+                .iter()
+                .filter(|t| t.from == *state && t.event == event)
+                .find(|t| t.guard.iter().all(|guard| guard(time_in_state)))
+                .map(|t| t.to);
+            if target.is_some() {
+                break;
+            }
+        }
+
+        match target {
+            Some(to) => {
+                self.go_to(to, event, now);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Innermost active state
+    pub fn state(&self) -> S {
+        self.current
+    }
+
+    /// Whether `state` is the current state or one of its ancestors
+    pub fn is_in(&self, state: S) -> bool {
+        self.ancestors(self.current).contains(&state)
+    }
+
+    pub fn time_in_state(&self) -> Time {
+        self.active
+            .last()
+            .map_or(Time::default(), |(_, entered)| self.clock.now() - *entered)
+    }
+
+    /// Time since `state` was entered, if it's active
+    pub fn time_in(&self, state: S) -> Option<Time> {
+        self.active
+            .iter()
+            .find(|(s, _)| *s == state)
+            .map(|(_, entered)| self.clock.now() - *entered)
+    }
+
+    pub fn current_output(&self) -> O {
+        self.ancestors(self.current)
+            .iter()
+            .find_map(|state| {
+                self.outputs
+                    .iter()
+                    .find(|(s, _)| s == state)
+                    .map(|(_, output)| *output)
+            })
+            .unwrap_or_default()
+    }
+
+    /// Most recent transitions, oldest first
+    pub fn history(&self) -> Vec<TransitionRecord<S, E>> {
+        self.history.iter().copied().collect()
+    }
+
+    pub fn clear_history(&mut self) {
+        self.history.clear();
+    }
+
+    /// `state` followed by its ancestors, outermost last
+    fn ancestors(&self, state: S) -> Vec<S> {
+        let mut ancestors = vec![state];
+        let mut state = state;
+        while let Some((_, parent)) = self.parents.iter().find(|(child, _)| *child == state) {
+            ancestors.push(*parent);
+            state = *parent;
+        }
+        ancestors
+    }
+
+    fn go_to(&mut self, to: S, event: E, now: Time) {
+        let mut path = self.ancestors(to);
+        path.reverse();
+
+        // Always leave and re-enter the target itself, even if it's already active
+        let common = self
+            .active
+            .iter()
+            .zip(&path)
+            .take_while(|((active, _), state)| active == *state)
+            .count()
+            .min(path.len() - 1);
+
+        while self.active.len() > common {
+            let (state, _) = self.active.pop().unwrap();
+            for (_, action) in self.exit_actions.iter_mut().filter(|(s, _)| *s == state) {
+                action();
+            }
+        }
+        for state in path.into_iter().skip(common) {
+            self.enter(state, now);
+        }
+
+        let record = TransitionRecord {
+            from: self.current,
+            to,
+            event,
+            at: now,
+        };
+        if let Some(name) = self.log_name {
+            println!("{}: {}", name, record);
+        }
+        self.history.push_back(record);
+        self.trim_history();
+        self.current = to;
+    }
+
+    fn trim_history(&mut self) {
+        while self.history.len() > self.history_capacity {
+            self.history.pop_front();
+        }
+    }
+
+    fn enter(&mut self, state: S, now: Time) {
+        self.active.push((state, now));
+        for (_, action) in self.entry_actions.iter_mut().filter(|(s, _)| *s == state) {
+            action();
+        }
+    }
+}
+
+/// Node that fires each message it receives into a state machine and emits the resulting state and
+/// output
+#[derive(Clone)]
+pub struct StateMachineNode<'a, S: Copy, E, O: Copy> {
+    pub(crate) children: NodeChildren<'a, (S, O)>,
+    pub(crate) machine: Arc<Mutex<StateMachine<S, E, O>>>,
+}
+
+impl<'a, S, E, O> NodeReceiver for StateMachineNode<'a, S, E, O>
+where
+    S: Copy + PartialEq + Debug + Send + 'a,
+    E: Copy + PartialEq + Debug + Send + 'a,
+    O: Copy + Default + Send + 'a,
+{
+    type In = E;
+
+    fn send(&self, msg: Self::In) {
+        let out = {
+            let mut machine = self.machine.lock().unwrap();
+            machine.fire(msg);
+            (machine.state(), machine.current_output())
+        };
+        for child in self.children.lock().unwrap().iter() {
+            child.send(out);
+        }
+    }
+}
+
+impl<'a, S, E, O> Node<'a> for StateMachineNode<'a, S, E, O>
+where
+    S: Copy + PartialEq + Debug + Send + 'a,
+    E: Copy + PartialEq + Debug + Send + 'a,
+    O: Copy + Default + Send + 'a,
+{
+    type Out = (S, O);
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::BaseNode;
+    use crate::testing::{FakeClock, Probe};
+    use uom::si::time::millisecond;
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Shooter {
+        Idle,
+        Active,
+        SpinUp,
+        Firing,
+    }
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    enum Event {
+        Tick,
+        Shoot,
+        Stop,
+    }
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    fn record(log: &Log, entry: &'static str) -> impl FnMut() + Send + 'static {
+        let log = log.clone();
+        move || log.lock().unwrap().push(entry.to_string())
+    }
+
+    fn shooter(clock: &FakeClock, log: &Log) -> StateMachine<Shooter, Event, f64> {
+        let mut machine = StateMachine::new(Shooter::Idle, clock.clone());
+        machine
+            .parent(Shooter::SpinUp, Shooter::Active)
+            .unwrap()
+            .parent(Shooter::Firing, Shooter::Active)
+            .unwrap();
+        machine
+            .on_entry(Shooter::Idle, record(log, "enter idle"))
+            .on_exit(Shooter::Idle, record(log, "exit idle"))
+            .on_entry(Shooter::Active, record(log, "enter active"))
+            .on_exit(Shooter::Active, record(log, "exit active"))
+            .on_entry(Shooter::SpinUp, record(log, "enter spin up"))
+            .on_exit(Shooter::SpinUp, record(log, "exit spin up"))
+            .on_entry(Shooter::Firing, record(log, "enter firing"))
+            .output(Shooter::Active, 0.8)
+            .output(Shooter::Firing, 1.0)
+            .transition(Shooter::Idle, Event::Shoot, Shooter::SpinUp)
+            .guarded_transition(Shooter::SpinUp, Event::Tick, Shooter::Firing, |t| {
+                t >= Time::new::<millisecond>(500.0)
+            })
+            .transition(Shooter::Active, Event::Stop, Shooter::Idle);
+        machine
+    }
+
+    fn take(log: &Log) -> Vec<String> {
+        log.lock().unwrap().drain(..).collect()
+    }
+
+    #[test]
+    fn entry_and_exit_actions_run_through_the_hierarchy() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+
+        assert!(machine.fire(Event::Shoot));
+        assert_eq!(
+            take(&log),
+            ["enter idle", "exit idle", "enter active", "enter spin up"]
+        );
+        assert_eq!(machine.state(), Shooter::SpinUp);
+        assert!(machine.is_in(Shooter::Active));
+        assert!(!machine.is_in(Shooter::Idle));
+
+        clock.advance(Time::new::<millisecond>(600.0));
+        machine.fire(Event::Tick);
+        assert_eq!(take(&log), ["exit spin up", "enter firing"]);
+
+        machine.fire(Event::Stop);
+        assert_eq!(take(&log), ["exit active", "enter idle"]);
+    }
+
+    #[test]
+    fn guard_sees_time_in_state() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+        machine.fire(Event::Shoot);
+
+        clock.advance(Time::new::<millisecond>(300.0));
+        assert!(!machine.fire(Event::Tick));
+        assert_eq!(machine.time_in_state(), Time::new::<millisecond>(300.0));
+
+        clock.advance(Time::new::<millisecond>(300.0));
+        assert!(machine.fire(Event::Tick));
+        assert_eq!(machine.state(), Shooter::Firing);
+        assert_eq!(machine.time_in_state(), Time::default());
+        assert_eq!(
+            machine.time_in(Shooter::Active),
+            Some(Time::new::<millisecond>(600.0))
+        );
+    }
+
+    #[test]
+    fn unhandled_events_are_ignored() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+
+        assert!(!machine.fire(Event::Stop));
+        assert_eq!(machine.state(), Shooter::Idle);
+        assert!(machine.history().is_empty());
+    }
+
+    #[test]
+    fn outputs_are_inherited_from_parents() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+
+        assert_eq!(machine.current_output(), 0.0);
+        machine.fire(Event::Shoot);
+        assert_eq!(machine.current_output(), 0.8);
+        clock.advance(Time::new::<millisecond>(500.0));
+        machine.fire(Event::Tick);
+        assert_eq!(machine.current_output(), 1.0);
+    }
+
+    #[test]
+    fn transitions_are_recorded() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+
+        machine.fire(Event::Shoot);
+        clock.advance(Time::new::<millisecond>(250.0));
+        machine.fire(Event::Stop);
+
+        assert_eq!(
+            machine.history(),
+            [
+                TransitionRecord {
+                    from: Shooter::Idle,
+                    to: Shooter::SpinUp,
+                    event: Event::Shoot,
+                    at: Time::default(),
+                },
+                TransitionRecord {
+                    from: Shooter::SpinUp,
+                    to: Shooter::Idle,
+                    event: Event::Stop,
+                    at: Time::new::<millisecond>(250.0),
+                },
+            ]
+        );
+        assert_eq!(
+            machine.history()[1].to_string(),
+            "0.250 s: SpinUp -> Idle on Stop"
+        );
+    }
+
+    #[test]
+    fn history_keeps_only_the_latest_transitions() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let mut machine = shooter(&clock, &log);
+        machine.history_capacity(3);
+
+        for _ in 0..3 {
+            machine.fire(Event::Shoot);
+            machine.fire(Event::Stop);
+        }
+
+        let history = machine.history();
+        assert_eq!(history.len(), 3);
+        assert_eq!(
+            history.iter().map(|r| r.event).collect::<Vec<_>>(),
+            [Event::Stop, Event::Shoot, Event::Stop]
+        );
+
+        machine.history_capacity(1);
+        assert_eq!(machine.history().len(), 1);
+        assert_eq!(machine.history()[0].to, Shooter::Idle);
+    }
+
+    #[test]
+    fn parent_cycles_are_rejected() {
+        let clock = FakeClock::new();
+        let mut machine: StateMachine<Shooter, Event, ()> = StateMachine::new(Shooter::Idle, clock);
+        machine.parent(Shooter::SpinUp, Shooter::Active).unwrap();
+
+        assert!(machine.parent(Shooter::Active, Shooter::SpinUp).is_err());
+    }
+
+    #[test]
+    fn node_emits_state_and_output_for_each_event() {
+        let clock = FakeClock::new();
+        let log = Log::default();
+        let machine = Arc::new(Mutex::new(shooter(&clock, &log)));
+        let mut events = BaseNode::new();
+        let probe = Probe::new();
+        events.state_machine(machine).chain(probe.clone());
+
+        events.send(Event::Tick);
+        events.send(Event::Shoot);
+        events.send(Event::Stop);
+
+        probe.assert_received(&[
+            (Shooter::Idle, 0.0),
+            (Shooter::SpinUp, 0.8),
+            (Shooter::Idle, 0.0),
+        ]);
+    }
+}
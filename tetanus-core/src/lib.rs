@@ -1,3 +1,10 @@
+pub mod bus;
+pub mod clock;
 pub mod consumer;
+pub mod fsm;
 pub mod node;
+pub mod processor;
 pub mod producer;
+pub mod rate;
+pub mod resource;
+pub mod testing;
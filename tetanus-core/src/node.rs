@@ -1,7 +1,13 @@
 use std::fmt::Debug;
 use std::{marker::PhantomData, sync::Arc, sync::Mutex};
 
-use crate::{consumer::Consumer, producer::Producer};
+use anyhow::Result;
+
+use crate::clock::Clock;
+use crate::fsm::{StateMachine, StateMachineNode};
+use crate::rate::{DecimateNode, Interpolate, ResampleNode, SampleHistory, StampNode, Stamped};
+use crate::resource::Resources;
+use crate::{consumer::Consumer, processor::Processor, producer::Producer};
 
 pub trait NodeReceiver {
     type In: Copy + Send;
@@ -93,6 +99,23 @@ pub trait Node<'a>: NodeReceiver + Clone + Send {
         node
     }
 
+    /// Passes each message through `processor`, emitting its result
+    fn process<P: Processor<In = Self::Out> + 'a>(
+        &mut self,
+        processor: Arc<Mutex<P>>,
+    ) -> ProcessorNode<'a, P>
+    where
+        P::Out: Send + 'a,
+    {
+        let node = ProcessorNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+            processor,
+        };
+
+        self.chain(node.clone());
+        node
+    }
+
     fn consume<C: Consumer<Msg = Self::Out> + 'a>(
         &mut self,
         consumer: Arc<Mutex<C>>,
@@ -106,6 +129,20 @@ pub trait Node<'a>: NodeReceiver + Clone + Send {
         node
     }
 
+    /// Like [`Node::consume`], but first claims the consumer's resources for `owner`
+    ///
+    /// Fails if another part of the graph already owns any of them.
+    fn consume_exclusive<C: Consumer<Msg = Self::Out> + 'a>(
+        &mut self,
+        resources: &Resources,
+        owner: &str,
+        consumer: Arc<Mutex<C>>,
+    ) -> Result<ConsumerNode<'a, Self::Out, C>> {
+        let required = consumer.lock().unwrap().resources();
+        resources.claim(owner, &required)?;
+        Ok(self.consume(consumer))
+    }
+
     fn log(&mut self) -> LoggingNode<'a, Self::Out>
     where
         Self::Out: Debug,
@@ -117,9 +154,107 @@ pub trait Node<'a>: NodeReceiver + Clone + Send {
         self.chain(node.clone());
         node
     }
+
+    /// Fires each message into `machine` as an event and emits its state and output afterwards
+    fn state_machine<S, O>(
+        &mut self,
+        machine: Arc<Mutex<StateMachine<S, Self::Out, O>>>,
+    ) -> StateMachineNode<'a, S, Self::Out, O>
+    where
+        S: Copy + PartialEq + Debug + Send + 'a,
+        Self::Out: PartialEq + Debug,
+        O: Copy + Default + Send + 'a,
+    {
+        let node = StateMachineNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+            machine,
+        };
+
+        self.chain(node.clone());
+        node
+    }
+
+    /// Tags each message with the time it passed through this node
+    fn stamp<C: Clock + Clone + 'a>(&mut self, clock: C) -> StampNode<'a, Self::Out, C> {
+        let node = StampNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+            clock,
+        };
+
+        self.chain(node.clone());
+        node
+    }
+
+    /// Forwards the first message and then every `factor`th one after it
+    fn decimate(&mut self, factor: usize) -> DecimateNode<'a, Self::Out> {
+        assert!(factor > 0, "decimation factor must be positive");
+
+        let node = DecimateNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+            factor,
+            count: Arc::new(Mutex::new(0)),
+        };
+
+        self.chain(node.clone());
+        node
+    }
+
+    /// Re-emits the latest sample from `source` every time this node receives a message
+    ///
+    /// Emits `None` until `source` has produced its first sample.
+    fn sample_hold<T, C>(
+        &mut self,
+        mut source: impl Node<'a, Out = Stamped<T>> + 'a,
+        clock: C,
+    ) -> ResampleNode<'a, T>
+    where
+        T: Copy + Send + 'a,
+        C: Clock + Clone + 'a,
+    {
+        let node = ResampleNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+        };
+        let history = Arc::new(Mutex::new(SampleHistory::new()));
+
+        {
+            let history = history.clone();
+            source.map(move |sample| history.lock().unwrap().push(sample));
+        }
+
+        self.map(move |_| history.lock().unwrap().hold(clock.now()))
+            .chain(node.clone());
+        node
+    }
+
+    /// Like [`Node::sample_hold`], but linearly interpolates between the last two samples from `source`
+    ///
+    /// The output lags `source` by one of its sample periods.
+    fn sample_interpolate<T, C>(
+        &mut self,
+        mut source: impl Node<'a, Out = Stamped<T>> + 'a,
+        clock: C,
+    ) -> ResampleNode<'a, T>
+    where
+        T: Interpolate + Send + 'a,
+        C: Clock + Clone + 'a,
+    {
+        let node = ResampleNode {
+            children: Arc::new(Mutex::new(Vec::new())),
+        };
+        let history = Arc::new(Mutex::new(SampleHistory::new()));
+
+        {
+            let history = history.clone();
+            source.map(move |sample| history.lock().unwrap().push(sample));
+        }
+
+        self.map(move |_| history.lock().unwrap().interpolate(clock.now()))
+            .chain(node.clone());
+        node
+    }
 }
 
-type NodeChildren<'a, T> = Arc<Mutex<Vec<Box<dyn NodeReceiver<In = T> + Send + 'a>>>>;
+pub(crate) type NodeChildren<'a, T> = Arc<Mutex<Vec<Box<dyn NodeReceiver<In = T> + Send + 'a>>>>;
 
 #[derive(Clone)]
 pub struct BaseNode<'a, T: Copy> {
@@ -134,6 +269,12 @@ impl<T: Copy> BaseNode<'_, T> {
     }
 }
 
+impl<T: Copy> Default for BaseNode<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a, T: Copy + Send + 'a> NodeReceiver for BaseNode<'a, T> {
     type In = T;
 
@@ -349,6 +490,50 @@ where
     }
 }
 
+pub struct ProcessorNode<'a, P: Processor> {
+    children: NodeChildren<'a, P::Out>,
+    processor: Arc<Mutex<P>>,
+}
+
+// #derive(Clone) doesn't work
+impl<P: Processor> Clone for ProcessorNode<'_, P> {
+    fn clone(&self) -> Self {
+        ProcessorNode {
+            children: self.children.clone(),
+            processor: self.processor.clone(),
+        }
+    }
+}
+
+impl<'a, P> NodeReceiver for ProcessorNode<'a, P>
+where
+    P: Processor,
+    P::In: Send,
+    P::Out: Send + 'a,
+{
+    type In = P::In;
+
+    fn send(&self, msg: Self::In) {
+        let processed = self.processor.lock().unwrap().process(msg);
+        for child in self.children.lock().unwrap().iter() {
+            child.send(processed);
+        }
+    }
+}
+
+impl<'a, P> Node<'a> for ProcessorNode<'a, P>
+where
+    P: Processor,
+    P::In: Send,
+    P::Out: Send + 'a,
+{
+    type Out = P::Out;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
 #[derive(Clone)]
 pub struct LoggingNode<'a, T: Copy + Debug> {
     children: NodeChildren<'a, T>,
@@ -378,3 +563,154 @@ where
         self.children.lock().unwrap().push(Box::new(other));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{tick, Probe, ScriptedProducer};
+
+    #[test]
+    fn base_node_broadcasts_to_every_child() {
+        let mut source = BaseNode::new();
+        let first = Probe::new();
+        let second = Probe::new();
+        source.chain(first.clone());
+        source.chain(second.clone());
+
+        source.send(1);
+        source.send(2);
+
+        first.assert_received(&[1, 2]);
+        second.assert_received(&[1, 2]);
+    }
+
+    #[test]
+    fn map_transforms_each_message() {
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source.map(|x: i32| x * 10).chain(probe.clone());
+
+        source.send(1);
+        source.send(-3);
+
+        probe.assert_received(&[10, -30]);
+    }
+
+    #[test]
+    fn zip_waits_for_both_inputs_then_pairs_latest() {
+        let mut left = BaseNode::new();
+        let right = BaseNode::new();
+        let probe = Probe::new();
+        left.zip(right.clone()).chain(probe.clone());
+
+        left.send(1);
+        right.send('a');
+        left.send(2);
+        right.send('b');
+
+        probe.assert_received(&[None, Some((1, 'a')), Some((2, 'a')), Some((2, 'b'))]);
+    }
+
+    #[test]
+    fn filter_drops_rejected_messages() {
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source.filter(|x: i32| x % 2 == 0).chain(probe.clone());
+
+        for x in 0..5 {
+            source.send(x);
+        }
+
+        probe.assert_received(&[0, 2, 4]);
+    }
+
+    #[test]
+    fn produce_pulls_one_value_per_input() {
+        let mut ticker = BaseNode::new();
+        let probe = Probe::new();
+        ticker
+            .produce(Arc::new(Mutex::new(ScriptedProducer::new(vec![3, 1, 4]))))
+            .chain(probe.clone());
+
+        tick(&ticker, 3);
+
+        probe.assert_received(&[3, 1, 4]);
+    }
+
+    #[test]
+    fn process_keeps_state_between_messages() {
+        struct Sum(i32);
+
+        impl Processor for Sum {
+            type In = i32;
+            type Out = i32;
+
+            fn process(&mut self, msg: Self::In) -> Self::Out {
+                self.0 += msg;
+                self.0
+            }
+        }
+
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source
+            .process(Arc::new(Mutex::new(Sum(0))))
+            .chain(probe.clone());
+
+        for msg in [1, 2, 3] {
+            source.send(msg);
+        }
+
+        probe.assert_received(&[1, 3, 6]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ran out of values")]
+    fn scripted_producer_panics_when_exhausted() {
+        let mut ticker = BaseNode::new();
+        ticker.produce(Arc::new(Mutex::new(ScriptedProducer::new(vec![1]))));
+
+        tick(&ticker, 2);
+    }
+
+    #[test]
+    fn consume_outputs_and_forwards() {
+        let mut source = BaseNode::new();
+        let consumer = Arc::new(Mutex::new(Probe::new()));
+        let downstream = Probe::new();
+        source.consume(consumer.clone()).chain(downstream.clone());
+
+        source.send(7);
+        source.send(8);
+
+        consumer.lock().unwrap().assert_received(&[7, 8]);
+        downstream.assert_received(&[7, 8]);
+    }
+
+    #[test]
+    fn log_forwards_unchanged() {
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source.log().chain(probe.clone());
+
+        source.send("hello");
+
+        probe.assert_received(&["hello"]);
+    }
+
+    #[test]
+    fn nodes_compose_in_order() {
+        let mut ticker = BaseNode::new();
+        let probe = Probe::new();
+        ticker
+            .produce(Arc::new(Mutex::new(ScriptedProducer::new(1..=6))))
+            .filter(|x| x > 2)
+            .map(|x| x * x)
+            .chain(probe.clone());
+
+        tick(&ticker, 6);
+
+        probe.assert_received(&[9, 16, 25, 36]);
+        probe.assert_last(36);
+    }
+}
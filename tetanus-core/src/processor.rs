@@ -0,0 +1,7 @@
+/// Stateful transformation from one message to another, such as a controller or filter
+pub trait Processor: Send + Sync {
+    type In: Copy;
+    type Out: Copy;
+
+    fn process(&mut self, msg: Self::In) -> Self::Out;
+}
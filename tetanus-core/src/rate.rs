@@ -0,0 +1,345 @@
+//! Nodes for moving messages between tick domains running at different rates
+
+use std::marker::PhantomData;
+use std::sync::{Arc, Mutex};
+
+use uom::si::f64::*;
+use uom::si::{Dimension, Quantity, Units};
+
+use crate::clock::Clock;
+use crate::node::{Node, NodeChildren, NodeReceiver};
+
+/// Message tagged with the time it was sampled
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stamped<T> {
+    pub value: T,
+    pub timestamp: Time,
+}
+
+impl<T> Stamped<T> {
+    pub fn new(value: T, timestamp: Time) -> Self {
+        Stamped { value, timestamp }
+    }
+
+    pub fn age(&self, now: Time) -> Time {
+        now - self.timestamp
+    }
+}
+
+/// Message re-emitted into a faster tick domain by [`Node::sample_hold`] or [`Node::sample_interpolate`]
+///
+/// Carries enough timing information for consumers to notice when the source is slower than they expect.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Resampled<T> {
+    pub value: T,
+    /// Timestamp of the newest source sample used to produce `value`
+    pub sampled_at: Time,
+    /// Time the message was emitted
+    pub emitted_at: Time,
+    /// Number of times the newest source sample had already been emitted before this message
+    pub repeats: u32,
+}
+
+impl<T> Resampled<T> {
+    pub fn age(&self) -> Time {
+        self.emitted_at - self.sampled_at
+    }
+
+    pub fn is_fresh(&self) -> bool {
+        self.repeats == 0
+    }
+
+    pub fn is_stale(&self, max_age: Time) -> bool {
+        self.age() > max_age
+    }
+}
+
+/// Values that can be linearly interpolated
+pub trait Interpolate: Copy {
+    /// Returns `self` at `t = 0` and `other` at `t = 1`
+    fn lerp(self, other: Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t
+    }
+}
+
+impl Interpolate for f32 {
+    fn lerp(self, other: Self, t: f64) -> Self {
+        self + (other - self) * t as f32
+    }
+}
+
+impl<D, U> Interpolate for Quantity<D, U, f64>
+where
+    D: Dimension + ?Sized,
+    U: Units<f64> + ?Sized,
+{
+    fn lerp(self, other: Self, t: f64) -> Self {
+        Quantity {
+            dimension: PhantomData,
+            units: PhantomData,
+            value: self.value.lerp(other.value, t),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct StampNode<'a, T: Copy, C: Clock> {
+    pub(crate) children: NodeChildren<'a, Stamped<T>>,
+    pub(crate) clock: C,
+}
+
+impl<'a, T, C> NodeReceiver for StampNode<'a, T, C>
+where
+    T: Copy + Send + 'a,
+    C: Clock,
+{
+    type In = T;
+
+    fn send(&self, msg: Self::In) {
+        let stamped = Stamped::new(msg, self.clock.now());
+        for child in self.children.lock().unwrap().iter() {
+            child.send(stamped);
+        }
+    }
+}
+
+impl<'a, T, C> Node<'a> for StampNode<'a, T, C>
+where
+    T: Copy + Send + 'a,
+    C: Clock + Clone,
+{
+    type Out = Stamped<T>;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+#[derive(Clone)]
+pub struct DecimateNode<'a, T: Copy> {
+    pub(crate) children: NodeChildren<'a, T>,
+    pub(crate) factor: usize,
+    pub(crate) count: Arc<Mutex<usize>>,
+}
+
+impl<'a, T> NodeReceiver for DecimateNode<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type In = T;
+
+    fn send(&self, msg: Self::In) {
+        let mut count = self.count.lock().unwrap();
+        let forward = *count == 0;
+        *count = (*count + 1) % self.factor;
+        drop(count);
+
+        if forward {
+            for child in self.children.lock().unwrap().iter() {
+                child.send(msg);
+            }
+        }
+    }
+}
+
+impl<'a, T> Node<'a> for DecimateNode<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type Out = T;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+/// Latest source samples kept by an upsampling node
+pub(crate) struct SampleHistory<T> {
+    previous: Option<Stamped<T>>,
+    latest: Option<Stamped<T>>,
+    repeats: u32,
+}
+
+impl<T: Copy> SampleHistory<T> {
+    pub(crate) fn new() -> Self {
+        SampleHistory {
+            previous: None,
+            latest: None,
+            repeats: 0,
+        }
+    }
+
+    pub(crate) fn push(&mut self, sample: Stamped<T>) {
+        self.previous = self.latest.replace(sample);
+        self.repeats = 0;
+    }
+
+    pub(crate) fn hold(&mut self, now: Time) -> Option<Resampled<T>> {
+        let latest = self.latest?;
+        Some(self.emit(latest.value, latest.timestamp, now))
+    }
+
+    /// Interpolates one source period behind `now` so the output stays between two real samples
+    pub(crate) fn interpolate(&mut self, now: Time) -> Option<Resampled<T>>
+    where
+        T: Interpolate,
+    {
+        let latest = self.latest?;
+        let value = match self.previous {
+            Some(previous) if latest.timestamp > previous.timestamp => {
+                let period = latest.timestamp - previous.timestamp;
+                let t = ((now - period - previous.timestamp) / period).value;
+                previous.value.lerp(latest.value, t.clamp(0.0, 1.0))
+            }
+            _ => latest.value,
+        };
+        Some(self.emit(value, latest.timestamp, now))
+    }
+
+    fn emit(&mut self, value: T, sampled_at: Time, now: Time) -> Resampled<T> {
+        let repeats = self.repeats;
+        self.repeats += 1;
+        Resampled {
+            value,
+            sampled_at,
+            emitted_at: now,
+            repeats,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct ResampleNode<'a, T: Copy> {
+    pub(crate) children: NodeChildren<'a, Option<Resampled<T>>>,
+}
+
+impl<'a, T> NodeReceiver for ResampleNode<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type In = Option<Resampled<T>>;
+
+    fn send(&self, msg: Self::In) {
+        for child in self.children.lock().unwrap().iter() {
+            child.send(msg);
+        }
+    }
+}
+
+impl<'a, T> Node<'a> for ResampleNode<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type Out = Self::In;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::BaseNode;
+    use crate::testing::{assert_close, FakeClock, Probe};
+    use uom::si::length::meter;
+    use uom::si::time::{millisecond, second};
+
+    fn ms(value: f64) -> Time {
+        Time::new::<millisecond>(value)
+    }
+
+    #[test]
+    fn decimate_keeps_every_nth_message() {
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source.decimate(3).chain(probe.clone());
+
+        for x in 0..8 {
+            source.send(x);
+        }
+
+        probe.assert_received(&[0, 3, 6]);
+    }
+
+    #[test]
+    fn stamp_uses_clock_time() {
+        let clock = FakeClock::new();
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        source.stamp(clock.clone()).chain(probe.clone());
+
+        clock.set(ms(20.0));
+        source.send('a');
+
+        probe.assert_received(&[Stamped::new('a', ms(20.0))]);
+    }
+
+    #[test]
+    fn sample_hold_reports_repeats_and_age() {
+        let clock = FakeClock::new();
+        let mut ticker = BaseNode::new();
+        let mut source = BaseNode::new();
+        let probe = Probe::new();
+        ticker
+            .sample_hold(source.stamp(clock.clone()), clock.clone())
+            .chain(probe.clone());
+
+        ticker.send(());
+        source.send(1.0);
+        for _ in 0..3 {
+            clock.advance(ms(5.0));
+            ticker.send(());
+        }
+
+        let received = probe.received();
+        assert_eq!(received[0], None);
+
+        let held: Vec<_> = received[1..].iter().map(|m| m.unwrap()).collect();
+        assert!(held.iter().all(|m| m.value == 1.0));
+        assert_eq!(
+            held.iter().map(|m| m.repeats).collect::<Vec<_>>(),
+            [0, 1, 2]
+        );
+        assert!(held[0].is_fresh());
+        assert!(held[2].is_stale(ms(10.0)));
+        assert_close(held[2].age().get::<millisecond>(), 15.0, 1e-9);
+    }
+
+    #[test]
+    fn sample_interpolate_lags_one_source_period() {
+        let clock = FakeClock::new();
+        let mut ticker = BaseNode::new();
+        let source = BaseNode::new();
+        let probe = Probe::new();
+        ticker
+            .sample_interpolate(source.clone(), clock.clone())
+            .chain(probe.clone());
+
+        source.send(Stamped::new(Length::new::<meter>(0.0), ms(0.0)));
+        source.send(Stamped::new(Length::new::<meter>(1.0), ms(100.0)));
+
+        for now in &[100.0, 150.0, 200.0, 250.0] {
+            clock.set(ms(*now));
+            ticker.send(());
+        }
+
+        let values: Vec<f64> = probe
+            .received()
+            .iter()
+            .map(|m| m.unwrap().value.get::<meter>())
+            .collect();
+        for (actual, expected) in values.iter().zip(&[0.0, 0.5, 1.0, 1.0]) {
+            assert_close(*actual, *expected, 1e-9);
+        }
+        assert_close(
+            probe.last().unwrap().unwrap().sampled_at.get::<second>(),
+            0.1,
+            1e-12,
+        );
+    }
+}
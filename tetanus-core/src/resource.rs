@@ -0,0 +1,333 @@
+//! Exclusive ownership of the hardware behind consumers
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+
+use crate::node::{BaseNode, Node, NodeChildren, NodeReceiver};
+
+/// Something only one branch of the graph may drive at a time, such as a subsystem's motors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Resource(&'static str);
+
+impl Resource {
+    pub const fn new(name: &'static str) -> Self {
+        Resource(name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.0
+    }
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Snapshot of a resource's ownership for telemetry
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResourceOwner {
+    pub resource: Resource,
+    pub owner: String,
+    /// Arbiter input currently in control, if the owner is an [`Arbiter`]
+    pub holder: Option<String>,
+}
+
+struct Claim {
+    owner: String,
+    holder: Option<String>,
+}
+
+/// Registry of which part of the graph owns each resource
+///
+/// Clones refer to the same registry.
+#[derive(Clone, Default)]
+pub struct Resources {
+    claims: Arc<Mutex<BTreeMap<Resource, Claim>>>,
+}
+
+impl Resources {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Claims every resource in `resources` for `owner`, or none of them if any is owned by someone else
+    pub fn claim(&self, owner: &str, resources: &[Resource]) -> Result<()> {
+        let mut claims = self.claims.lock().unwrap();
+
+        for resource in resources {
+            if let Some(claim) = claims.get(resource) {
+                if claim.owner != owner {
+                    bail!(
+                        "Resource \"{}\" is already owned by \"{}\", so \"{}\" can't use it (share it through an Arbiter)",
+                        resource,
+                        claim.owner,
+                        owner
+                    );
+                }
+            }
+        }
+
+        for resource in resources {
+            claims.entry(*resource).or_insert_with(|| Claim {
+                owner: owner.to_string(),
+                holder: None,
+            });
+        }
+
+        Ok(())
+    }
+
+    pub fn release(&self, resource: Resource) {
+        self.claims.lock().unwrap().remove(&resource);
+    }
+
+    pub fn owner(&self, resource: Resource) -> Option<String> {
+        self.claims
+            .lock()
+            .unwrap()
+            .get(&resource)
+            .map(|claim| claim.owner.clone())
+    }
+
+    pub fn owners(&self) -> Vec<ResourceOwner> {
+        self.claims
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(resource, claim)| ResourceOwner {
+                resource: *resource,
+                owner: claim.owner.clone(),
+                holder: claim.holder.clone(),
+            })
+            .collect()
+    }
+
+    fn set_holder(&self, owner: &str, holder: Option<&str>) {
+        for claim in self.claims.lock().unwrap().values_mut() {
+            if claim.owner == owner {
+                claim.holder = holder.map(str::to_string);
+            }
+        }
+    }
+}
+
+struct ArbiterInput {
+    name: String,
+    priority: i32,
+    active: bool,
+}
+
+struct ArbiterState {
+    inputs: Vec<ArbiterInput>,
+    holder: Option<usize>,
+}
+
+impl ArbiterState {
+    /// Highest priority active input, preferring the earliest added on ties
+    fn winner(&self) -> Option<usize> {
+        self.inputs
+            .iter()
+            .enumerate()
+            .filter(|(_, input)| input.active)
+            .fold(None, |best: Option<(usize, i32)>, (i, input)| match best {
+                Some((_, priority)) if priority >= input.priority => best,
+                _ => Some((i, input.priority)),
+            })
+            .map(|(i, _)| i)
+    }
+}
+
+/// Node that lets several branches share a resource by priority
+///
+/// Each branch sends `Some(msg)` into its input to request control and `None` to give it up.
+/// Only messages from the highest priority input currently requesting control are passed on.
+#[derive(Clone)]
+pub struct Arbiter<'a, T: Copy> {
+    children: NodeChildren<'a, T>,
+    state: Arc<Mutex<ArbiterState>>,
+    resources: Resources,
+    owner: String,
+}
+
+impl<'a, T: Copy + Send + 'a> Arbiter<'a, T> {
+    /// `owner` should be the name the arbiter's output consumer claims its resources with
+    pub fn new(resources: &Resources, owner: &str) -> Self {
+        Arbiter {
+            children: Arc::new(Mutex::new(Vec::new())),
+            state: Arc::new(Mutex::new(ArbiterState {
+                inputs: Vec::new(),
+                holder: None,
+            })),
+            resources: resources.clone(),
+            owner: owner.to_string(),
+        }
+    }
+
+    pub fn input(&self, name: &str, priority: i32) -> BaseNode<'a, Option<T>> {
+        let index = {
+            let mut state = self.state.lock().unwrap();
+            state.inputs.push(ArbiterInput {
+                name: name.to_string(),
+                priority,
+                active: false,
+            });
+            state.inputs.len() - 1
+        };
+
+        let mut input = BaseNode::new();
+        let arbiter = self.clone();
+        input
+            .map(move |msg: Option<T>| arbiter.accept(index, msg))
+            .filter(|msg| msg.is_some())
+            .map(|msg| msg.unwrap())
+            .chain(self.clone());
+        input
+    }
+
+    /// Name of the input currently in control
+    pub fn holder(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.holder.map(|i| state.inputs[i].name.clone())
+    }
+
+    fn accept(&self, index: usize, msg: Option<T>) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        state.inputs[index].active = msg.is_some();
+
+        let winner = state.winner();
+        if winner != state.holder {
+            state.holder = winner;
+            let holder = winner.map(|i| state.inputs[i].name.as_str());
+            self.resources.set_holder(&self.owner, holder);
+        }
+
+        msg.filter(|_| winner == Some(index))
+    }
+}
+
+impl<'a, T> NodeReceiver for Arbiter<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type In = T;
+
+    fn send(&self, msg: Self::In) {
+        for child in self.children.lock().unwrap().iter() {
+            child.send(msg);
+        }
+    }
+}
+
+impl<'a, T> Node<'a> for Arbiter<'a, T>
+where
+    T: Copy + Send + 'a,
+{
+    type Out = T;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::consumer::Consumer;
+    use crate::testing::Probe;
+
+    const DRIVETRAIN: Resource = Resource::new("drivetrain");
+
+    struct Motors {
+        received: Vec<f64>,
+    }
+
+    impl Consumer for Motors {
+        type Msg = f64;
+
+        fn output(&mut self, msg: Self::Msg) {
+            self.received.push(msg);
+        }
+
+        fn resources(&self) -> Vec<Resource> {
+            vec![DRIVETRAIN]
+        }
+    }
+
+    fn motors() -> Arc<Mutex<Motors>> {
+        Arc::new(Mutex::new(Motors {
+            received: Vec::new(),
+        }))
+    }
+
+    #[test]
+    fn second_owner_fails_at_build_time() {
+        let resources = Resources::new();
+        let drivetrain = motors();
+        let mut teleop = BaseNode::new();
+        let mut auto = BaseNode::new();
+
+        teleop
+            .consume_exclusive(&resources, "teleop", drivetrain.clone())
+            .unwrap();
+        let err = auto
+            .consume_exclusive(&resources, "auto", drivetrain)
+            .err()
+            .unwrap();
+
+        assert!(err.to_string().contains("already owned by \"teleop\""));
+        assert_eq!(resources.owner(DRIVETRAIN), Some("teleop".to_string()));
+    }
+
+    #[test]
+    fn claims_are_all_or_nothing() {
+        let resources = Resources::new();
+        let intake = Resource::new("intake");
+        resources.claim("auto", &[DRIVETRAIN]).unwrap();
+
+        assert!(resources.claim("teleop", &[intake, DRIVETRAIN]).is_err());
+        assert_eq!(resources.owner(intake), None);
+    }
+
+    #[test]
+    fn arbiter_passes_highest_priority_active_input() {
+        let resources = Resources::new();
+        let drivetrain = motors();
+        let mut arbiter = Arbiter::new(&resources, "drive-arbiter");
+        arbiter
+            .consume_exclusive(&resources, "drive-arbiter", drivetrain.clone())
+            .unwrap();
+        let probe = Probe::new();
+        arbiter.chain(probe.clone());
+
+        let teleop = arbiter.input("teleop", 0);
+        let align = arbiter.input("align", 10);
+
+        teleop.send(Some(1.0));
+        assert_eq!(arbiter.holder(), Some("teleop".to_string()));
+
+        align.send(Some(2.0));
+        teleop.send(Some(3.0));
+        assert_eq!(
+            resources.owners(),
+            [ResourceOwner {
+                resource: DRIVETRAIN,
+                owner: "drive-arbiter".to_string(),
+                holder: Some("align".to_string()),
+            }]
+        );
+
+        align.send(None);
+        teleop.send(Some(4.0));
+        teleop.send(None);
+
+        probe.assert_received(&[1.0, 2.0, 4.0]);
+        assert_eq!(drivetrain.lock().unwrap().received, [1.0, 2.0, 4.0]);
+        assert_eq!(arbiter.holder(), None);
+        assert_eq!(resources.owners()[0].holder, None);
+    }
+}
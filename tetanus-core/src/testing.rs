@@ -0,0 +1,247 @@
+//! Helpers for testing graphs without hardware
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+
+use uom::si::f64::*;
+
+use crate::clock::Clock;
+use crate::consumer::Consumer;
+use crate::node::{Node, NodeChildren, NodeReceiver};
+use crate::producer::Producer;
+
+/// Node that records every message it receives and passes it on unchanged
+///
+/// Clones share the same recording, so a probe can be chained into a graph and inspected afterwards.
+/// It also implements [`Consumer`] so it can stand in for a subsystem.
+#[derive(Clone)]
+pub struct Probe<'a, T: Copy> {
+    children: NodeChildren<'a, T>,
+    received: Arc<Mutex<Vec<T>>>,
+}
+
+impl<T: Copy> Probe<'_, T> {
+    pub fn new() -> Self {
+        Probe {
+            children: Arc::new(Mutex::new(Vec::new())),
+            received: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    pub fn received(&self) -> Vec<T> {
+        self.received.lock().unwrap().clone()
+    }
+
+    pub fn last(&self) -> Option<T> {
+        self.received.lock().unwrap().last().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.received.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.received.lock().unwrap().clear();
+    }
+
+    fn record(&self, msg: T) {
+        self.received.lock().unwrap().push(msg);
+    }
+}
+
+impl<T: Copy + PartialEq + Debug> Probe<'_, T> {
+    #[track_caller]
+    pub fn assert_received(&self, expected: &[T]) {
+        assert_eq!(
+            self.received(),
+            expected,
+            "probe received unexpected messages"
+        );
+    }
+
+    #[track_caller]
+    pub fn assert_last(&self, expected: T) {
+        assert_eq!(self.last(), Some(expected), "probe's last message differs");
+    }
+
+    #[track_caller]
+    pub fn assert_empty(&self) {
+        assert_eq!(self.received(), [], "probe expected no messages");
+    }
+}
+
+impl<T: Copy> Default for Probe<'_, T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, T: Copy + Send + 'a> NodeReceiver for Probe<'a, T> {
+    type In = T;
+
+    fn send(&self, msg: Self::In) {
+        self.record(msg);
+        for child in self.children.lock().unwrap().iter() {
+            child.send(msg);
+        }
+    }
+}
+
+impl<'a, T: Copy + Send + 'a> Node<'a> for Probe<'a, T> {
+    type Out = T;
+
+    fn chain<NewOut: Copy>(&mut self, other: impl Node<'a, In = Self::Out, Out = NewOut> + 'a) {
+        self.children.lock().unwrap().push(Box::new(other));
+    }
+}
+
+impl<'a, T: Copy + Send + 'a> Consumer for Probe<'a, T> {
+    type Msg = T;
+
+    fn output(&mut self, msg: Self::Msg) {
+        self.record(msg);
+    }
+}
+
+/// Producer that replays a fixed list of values, one per call to `next`
+///
+/// Panics once the script runs out so a test can't silently read past it.
+pub struct ScriptedProducer<T> {
+    values: Mutex<VecDeque<T>>,
+}
+
+impl<T> ScriptedProducer<T> {
+    pub fn new(values: impl IntoIterator<Item = T>) -> Self {
+        ScriptedProducer {
+            values: Mutex::new(values.into_iter().collect()),
+        }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.values.lock().unwrap().len()
+    }
+}
+
+impl<T: Copy + Send> Producer for ScriptedProducer<T> {
+    type Msg = T;
+
+    fn next(&self) -> Self::Msg {
+        self.values
+            .lock()
+            .unwrap()
+            .pop_front()
+            .expect("ScriptedProducer ran out of values")
+    }
+}
+
+/// Manually advanced clock, starting at zero
+///
+/// Clones share the same time.
+#[derive(Clone)]
+pub struct FakeClock {
+    now: Arc<Mutex<Time>>,
+}
+
+impl FakeClock {
+    pub fn new() -> Self {
+        FakeClock {
+            now: Arc::new(Mutex::new(Time::default())),
+        }
+    }
+
+    pub fn set(&self, time: Time) {
+        *self.now.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, dt: Time) {
+        *self.now.lock().unwrap() += dt;
+    }
+}
+
+impl Default for FakeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> Time {
+        *self.now.lock().unwrap()
+    }
+}
+
+/// Sends `n` ticks into `node`
+pub fn tick(node: &impl NodeReceiver<In = ()>, n: usize) {
+    for _ in 0..n {
+        node.send(());
+    }
+}
+
+/// Sends `n` ticks into `node`, advancing `clock` by `period` before each one
+pub fn tick_every(node: &impl NodeReceiver<In = ()>, clock: &FakeClock, period: Time, n: usize) {
+    for _ in 0..n {
+        clock.advance(period);
+        node.send(());
+    }
+}
+
+#[track_caller]
+pub fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "expected {} to be within {} of {}",
+        actual,
+        tolerance,
+        expected
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::BaseNode;
+    use uom::si::time::{millisecond, second};
+
+    #[test]
+    fn fake_clock_advances_and_is_shared() {
+        let clock = FakeClock::new();
+        let other = clock.clone();
+
+        clock.advance(Time::new::<millisecond>(20.0));
+        other.advance(Time::new::<millisecond>(20.0));
+        assert_close(clock.now().get::<second>(), 0.04, 1e-12);
+
+        clock.set(Time::new::<second>(3.0));
+        assert_close(other.now().get::<second>(), 3.0, 1e-12);
+    }
+
+    #[test]
+    fn tick_every_advances_before_each_tick() {
+        let clock = FakeClock::new();
+        let mut ticker = BaseNode::new();
+        let times = Probe::new();
+        let clock2 = clock.clone();
+        ticker
+            .map(move |_| clock2.now().get::<millisecond>())
+            .chain(times.clone());
+
+        tick_every(&ticker, &clock, Time::new::<millisecond>(5.0), 3);
+
+        times.assert_received(&[5.0, 10.0, 15.0]);
+    }
+
+    #[test]
+    fn probe_clear_resets_recording() {
+        let probe = Probe::new();
+        probe.send(1);
+        assert_eq!(probe.len(), 1);
+
+        probe.clear();
+        probe.assert_empty();
+    }
+}
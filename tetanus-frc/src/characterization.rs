@@ -0,0 +1,67 @@
+//! Characterizing mechanisms driven by an [`Esc`]
+//!
+//! Wrap the mechanism's controller in an [`EscMechanism`] and consume a
+//! [`SysIdRoutine`](tetanus_control::characterization::SysIdRoutine) timed by
+//! [`FpgaClock`](crate::clock::FpgaClock), then save its data for SysId or fit it on the robot.
+
+use tetanus_control::characterization::{Characterize, MechanismSample};
+use tetanus_control::units::{Motion, SiValue};
+use tetanus_core::resource::Resource;
+use uom::si::f64::*;
+
+use crate::esc::Esc;
+use crate::mechanism::Mechanism;
+
+/// Directory data is saved to, which survives redeploying code
+pub const DATA_DIRECTORY: &str = "/home/lvuser/sysid";
+
+/// A mechanism driven by a single controller, with any others following it
+///
+/// Positions are recorded in meters, or radians for an angular [`Mechanism`].
+pub struct EscMechanism<E, P = Length> {
+    mechanism: Mechanism<E, P>,
+    resource: Resource,
+}
+
+impl<E: Esc, P: Motion> EscMechanism<E, P> {
+    pub fn new(mechanism: Mechanism<E, P>, resource: Resource) -> Self {
+        EscMechanism {
+            mechanism,
+            resource,
+        }
+    }
+
+    /// Gives the mechanism back once characterization is done
+    pub fn into_inner(self) -> Mechanism<E, P> {
+        self.mechanism
+    }
+}
+
+impl<E: Esc, P: Motion> Characterize for EscMechanism<E, P> {
+    type Sample = MechanismSample;
+
+    fn apply(&mut self, voltage: ElectricPotential) {
+        self.mechanism.output_voltage(voltage);
+    }
+
+    fn sample(&mut self, timestamp: Time) -> Self::Sample {
+        mechanism_sample(&mut self.mechanism, timestamp)
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        vec![self.resource]
+    }
+}
+
+/// Measures the voltage, position and velocity of `mechanism`
+pub fn mechanism_sample<E: Esc, P: Motion>(
+    mechanism: &mut Mechanism<E, P>,
+    timestamp: Time,
+) -> MechanismSample {
+    MechanismSample {
+        timestamp,
+        voltage: mechanism.esc().applied_voltage(),
+        position: mechanism.position().si(),
+        velocity: mechanism.velocity().si(),
+    }
+}
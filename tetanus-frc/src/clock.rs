@@ -0,0 +1,13 @@
+use frc::wpilib::robot_controller;
+use tetanus_core::clock::Clock;
+use uom::si::f64::*;
+
+/// Clock backed by the roboRIO's FPGA timestamp
+#[derive(Clone, Copy, Default)]
+pub struct FpgaClock;
+
+impl Clock for FpgaClock {
+    fn now(&self) -> Time {
+        robot_controller::get_fpga_time()
+    }
+}
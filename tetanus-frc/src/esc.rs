@@ -1,107 +1,398 @@
-use core::panic;
-
 use anyhow::Result;
-use frc::ctre::motorcontrol::can::{BaseMotorController, TalonFX};
+use frc::ctre::motorcontrol::can::{BaseMotorController, BaseTalon, TalonFX};
 use frc::ctre::motorcontrol::{
-    NeutralMode, StatorCurrentLimitConfiguration, SupplyCurrentLimitConfiguration,
-    TalonFXControlMode,
+    DemandType, FollowerType, NeutralMode, StatorCurrentLimitConfiguration,
+    SupplyCurrentLimitConfiguration, TalonFXControlMode, TalonFXInvertType,
 };
+use frc::rev::{ArbFFUnits, CANSparkMax, ControlType, IdleMode, MotorType};
 use uom::si::electric_current::ampere;
 use uom::si::electric_potential::volt;
 use uom::si::f64::*;
-use uom::si::time::second;
+use uom::si::ratio::ratio;
+use uom::si::thermodynamic_temperature::degree_celsius;
+use uom::si::time::{millisecond, minute, second};
 
 pub const CONFIG_TIMEOUT_MS: i32 = 1000;
 
-pub enum OffloadedEsc {
-    TalonFX(TalonFX),
+/// Voltage that arbitrary feedforward is scaled against, matching the default voltage compensation
+pub const NOMINAL_VOLTAGE: f64 = 12.0;
+
+/// Closed-loop gains for a controller slot, in the controller's native units
+#[derive(Clone, Copy, Debug, Default)]
+pub struct EscGains {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    pub kf: f64,
+}
+
+/// Motor controller that runs its own closed loops, whichever vendor makes it
+///
+/// Closed-loop setpoints and raw sensor readings are in the controller's native units.
+pub trait Esc: Send + Sync {
+    fn setup(&mut self, config: EscConfig) -> Result<()>;
+
+    fn config_gains(&mut self, slot: i32, gains: EscGains) -> Result<()>;
+
+    /// Whether positive output turns the motor clockwise, looking at its shaft
+    fn set_inverted(&mut self, inverted: bool);
+
+    /// Whether the sensor counts backwards relative to the motor's output
+    fn set_sensor_phase(&mut self, phase: bool);
+
+    /// Copies `leader`'s output, reversed if only one of the two is inverted
+    ///
+    /// Set both controllers' inversion first, since some controllers ignore it once following.
+    fn follow(&mut self, leader: &mut Self) -> Result<()>
+    where
+        Self: Sized;
+
+    fn output_percent(&mut self, value: f64);
+
+    /// Open-loop voltage, scaled against the battery voltage the controller currently sees
+    fn output_voltage(&mut self, voltage: ElectricPotential);
+
+    fn output_velocity(&mut self, value: f64);
+
+    /// Closed-loop velocity with an added feedforward voltage, e.g. from tetanus-control's
+    /// `SimpleMotorFeedforward`
+    fn output_velocity_with_feedforward(&mut self, value: f64, feedforward: ElectricPotential);
+
+    fn output_position(&mut self, value: f64);
+
+    fn raw_sensor_position(&mut self) -> f64;
+
+    fn raw_sensor_velocity(&mut self) -> f64;
+
+    fn set_raw_sensor_position(&mut self, value: f64) -> Result<()>;
+
+    /// Counts in one motor revolution, in the units raw positions are reported in
+    fn counts_per_revolution(&self) -> f64;
+
+    /// Time raw velocities are counted over
+    fn velocity_period(&self) -> Time;
+
+    fn bus_voltage(&mut self) -> ElectricPotential;
+
+    /// Voltage the controller is applying to its motor
+    fn applied_voltage(&mut self) -> ElectricPotential;
+
+    /// Current through the motor, rather than drawn from the battery
+    fn output_current(&mut self) -> ElectricCurrent;
+
+    fn temperature(&mut self) -> ThermodynamicTemperature;
 }
 
-impl OffloadedEsc {
-    pub fn talon_fx(id: i32) -> Self {
-        Self::TalonFX(TalonFX::new(id))
-    }
-
-    pub fn setup(&mut self, config: OffloadedEscConfig) -> Result<()> {
-        match self {
-            OffloadedEsc::TalonFX(talon) => {
-                talon.config_factory_default(CONFIG_TIMEOUT_MS)?;
-                talon.set_neutral_mode(NeutralMode::Brake);
-
-                talon.config_openloop_ramp(config.open_loop_ramp, CONFIG_TIMEOUT_MS)?;
-                talon.config_closedloop_ramp(config.closed_loop_ramp, CONFIG_TIMEOUT_MS)?;
-                talon.config_peak_output_forward(
-                    config.peak_output_forward / config.voltage_comp_saturation,
-                    CONFIG_TIMEOUT_MS,
-                )?;
-                talon.config_peak_output_reverse(
-                    config.peak_output_reverse / config.voltage_comp_saturation,
-                    CONFIG_TIMEOUT_MS,
-                )?;
-                talon.config_nominal_output_forward(
-                    config.nominal_output_forward / config.voltage_comp_saturation,
-                    CONFIG_TIMEOUT_MS,
-                )?;
-                talon.config_nominal_output_reverse(
-                    config.nominal_output_reverse / config.voltage_comp_saturation,
-                    CONFIG_TIMEOUT_MS,
-                )?;
-                talon.config_voltage_comp_saturation(
-                    config.voltage_comp_saturation,
-                    CONFIG_TIMEOUT_MS,
-                )?;
-
-                talon.config_stator_current_limit(
-                    StatorCurrentLimitConfiguration {
-                        enable: true,
-                        current_limit: config.continuous_current_limit,
-                        trigger_threshold_current: config.peak_current_limit,
-                        trigger_threshold_time: config.peak_current_duration,
-                    },
-                    CONFIG_TIMEOUT_MS,
-                )?;
-                talon.config_supply_current_limit(
-                    SupplyCurrentLimitConfiguration {
-                        enable: true,
-                        current_limit: config.continuous_current_limit,
-                        trigger_threshold_current: config.peak_current_limit,
-                        trigger_threshold_time: config.peak_current_duration,
-                    },
-                    CONFIG_TIMEOUT_MS,
-                )?;
-            }
+/// CTRE TalonFX, as built into the Falcon 500
+pub struct TalonFxEsc {
+    talon: TalonFX,
+}
+
+impl TalonFxEsc {
+    /// Counts per revolution of the integrated sensor
+    pub const COUNTS_PER_REVOLUTION: f64 = 2048.0;
+
+    pub fn new(id: i32) -> Self {
+        TalonFxEsc {
+            talon: TalonFX::new(id),
         }
+    }
+
+    /// For Talon features [`Esc`] doesn't cover
+    pub fn as_talon_fx(&mut self) -> &mut TalonFX {
+        &mut self.talon
+    }
+}
+
+impl Esc for TalonFxEsc {
+    fn setup(&mut self, config: EscConfig) -> Result<()> {
+        let talon = &mut self.talon;
+        talon.config_factory_default(CONFIG_TIMEOUT_MS)?;
+        talon.set_neutral_mode(NeutralMode::Brake);
+
+        talon.config_openloop_ramp(config.open_loop_ramp, CONFIG_TIMEOUT_MS)?;
+        talon.config_closedloop_ramp(config.closed_loop_ramp, CONFIG_TIMEOUT_MS)?;
+        talon.config_peak_output_forward(
+            config.peak_output_forward / config.voltage_comp_saturation,
+            CONFIG_TIMEOUT_MS,
+        )?;
+        talon.config_peak_output_reverse(
+            config.peak_output_reverse / config.voltage_comp_saturation,
+            CONFIG_TIMEOUT_MS,
+        )?;
+        talon.config_nominal_output_forward(
+            config.nominal_output_forward / config.voltage_comp_saturation,
+            CONFIG_TIMEOUT_MS,
+        )?;
+        talon.config_nominal_output_reverse(
+            config.nominal_output_reverse / config.voltage_comp_saturation,
+            CONFIG_TIMEOUT_MS,
+        )?;
+        talon.config_voltage_comp_saturation(config.voltage_comp_saturation, CONFIG_TIMEOUT_MS)?;
+
+        talon.config_stator_current_limit(
+            StatorCurrentLimitConfiguration {
+                enable: true,
+                current_limit: config.continuous_current_limit,
+                trigger_threshold_current: config.peak_current_limit,
+                trigger_threshold_time: config.peak_current_duration,
+            },
+            CONFIG_TIMEOUT_MS,
+        )?;
+        talon.config_supply_current_limit(
+            SupplyCurrentLimitConfiguration {
+                enable: true,
+                current_limit: config.continuous_current_limit,
+                trigger_threshold_current: config.peak_current_limit,
+                trigger_threshold_time: config.peak_current_duration,
+            },
+            CONFIG_TIMEOUT_MS,
+        )?;
 
         Ok(())
     }
 
-    pub fn output_velocity(&mut self, value: f64) {
-        match self {
-            OffloadedEsc::TalonFX(talon) => talon.set(TalonFXControlMode::Velocity, value),
-            _ => panic!("Not a TalonFx"),
-        }
+    fn config_gains(&mut self, slot: i32, gains: EscGains) -> Result<()> {
+        self.talon.config_kp(slot, gains.kp, CONFIG_TIMEOUT_MS)?;
+        self.talon.config_ki(slot, gains.ki, CONFIG_TIMEOUT_MS)?;
+        self.talon.config_kd(slot, gains.kd, CONFIG_TIMEOUT_MS)?;
+        self.talon.config_kf(slot, gains.kf, CONFIG_TIMEOUT_MS)?;
+        Ok(())
+    }
+
+    fn set_inverted(&mut self, inverted: bool) {
+        self.talon.set_inverted(if inverted {
+            TalonFXInvertType::Clockwise
+        } else {
+            TalonFXInvertType::CounterClockwise
+        });
     }
 
-    pub fn output_percent(&mut self, value: f64) {
-        match self {
-            OffloadedEsc::TalonFX(talon) => talon.set(TalonFXControlMode::PercentOutput, value),
+    fn set_sensor_phase(&mut self, phase: bool) {
+        self.talon.set_sensor_phase(phase);
+    }
+
+    fn follow(&mut self, leader: &mut Self) -> Result<()> {
+        self.talon
+            .follow(&mut leader.talon, FollowerType::FollowerType_PercentOutput);
+        Ok(())
+    }
+
+    fn output_percent(&mut self, value: f64) {
+        self.talon.set(TalonFXControlMode::PercentOutput, value);
+    }
+
+    fn output_voltage(&mut self, voltage: ElectricPotential) {
+        // The bus voltage reads zero before the Talon's first status frame or after losing it
+        let bus_voltage = self.talon.get_bus_voltage();
+        let percent = if bus_voltage.is_finite() && bus_voltage > 0.0 {
+            (voltage.get::<volt>() / bus_voltage).clamp(-1.0, 1.0)
+        } else {
+            0.0
+        };
+        self.talon.set(TalonFXControlMode::PercentOutput, percent);
+    }
+
+    fn output_velocity(&mut self, value: f64) {
+        self.talon.set(TalonFXControlMode::Velocity, value);
+    }
+
+    fn output_velocity_with_feedforward(&mut self, value: f64, feedforward: ElectricPotential) {
+        self.talon.set1(
+            TalonFXControlMode::Velocity,
+            value,
+            DemandType::DemandType_ArbitraryFeedForward,
+            feedforward.get::<volt>() / NOMINAL_VOLTAGE,
+        );
+    }
+
+    fn output_position(&mut self, value: f64) {
+        self.talon.set(TalonFXControlMode::Position, value);
+    }
+
+    fn raw_sensor_position(&mut self) -> f64 {
+        self.talon.get_selected_sensor_position(0)
+    }
+
+    fn raw_sensor_velocity(&mut self) -> f64 {
+        self.talon.get_selected_sensor_velocity(0)
+    }
+
+    fn counts_per_revolution(&self) -> f64 {
+        Self::COUNTS_PER_REVOLUTION
+    }
+
+    /// Talons report velocity in counts per 100 ms
+    fn velocity_period(&self) -> Time {
+        Time::new::<millisecond>(100.0)
+    }
+
+    fn set_raw_sensor_position(&mut self, value: f64) -> Result<()> {
+        self.talon
+            .set_selected_sensor_position(value, 0, CONFIG_TIMEOUT_MS)
+    }
+
+    fn bus_voltage(&mut self) -> ElectricPotential {
+        ElectricPotential::new::<volt>(self.talon.get_bus_voltage())
+    }
+
+    fn applied_voltage(&mut self) -> ElectricPotential {
+        ElectricPotential::new::<volt>(self.talon.get_motor_output_voltage())
+    }
+
+    fn output_current(&mut self) -> ElectricCurrent {
+        self.talon.get_stator_current()
+    }
+
+    fn temperature(&mut self) -> ThermodynamicTemperature {
+        ThermodynamicTemperature::new::<degree_celsius>(self.talon.get_temperature())
+    }
+}
+
+/// REV SparkMax driving a brushless motor, such as a NEO, with its built-in encoder
+///
+/// Native positions are in motor rotations and velocities in RPM.
+pub struct SparkMaxEsc {
+    spark: CANSparkMax,
+    inverted: bool,
+}
+
+impl SparkMaxEsc {
+    pub fn new(id: i32) -> Self {
+        SparkMaxEsc {
+            spark: CANSparkMax::new(id, MotorType::kBrushless),
+            inverted: false,
         }
     }
 
-    pub fn as_talon_fx(&mut self) -> &mut TalonFX {
-        match self {
-            OffloadedEsc::TalonFX(talon) => talon,
-            _ => panic!("Not a TalonFX"),
+    /// For SparkMax features [`Esc`] doesn't cover
+    pub fn as_spark_max(&mut self) -> &mut CANSparkMax {
+        &mut self.spark
+    }
+
+    fn set_reference(&mut self, value: f64, control: ControlType, feedforward: ElectricPotential) {
+        // Setpoints are sent every loop, so a failed one is reported rather than returned and the
+        // next one retries it
+        let result = self.spark.get_pid_controller().set_reference(
+            value,
+            control,
+            0,
+            feedforward.get::<volt>(),
+            ArbFFUnits::kVoltage,
+        );
+        if let Err(e) = result {
+            println!("SparkMax {:?} setpoint failed: {:#}", control, e);
         }
     }
+}
+
+impl Esc for SparkMaxEsc {
+    fn setup(&mut self, config: EscConfig) -> Result<()> {
+        let spark = &mut self.spark;
+        spark.restore_factory_defaults(false)?;
+        spark.set_idle_mode(IdleMode::kBrake)?;
+
+        spark.set_open_loop_ramp_rate(config.open_loop_ramp)?;
+        spark.set_closed_loop_ramp_rate(config.closed_loop_ramp)?;
+        // SparkMaxes have no nominal output, so those settings are left out
+        spark.get_pid_controller().set_output_range(
+            (config.peak_output_reverse / config.voltage_comp_saturation).get::<ratio>(),
+            (config.peak_output_forward / config.voltage_comp_saturation).get::<ratio>(),
+            0,
+        )?;
+
+        spark.set_smart_current_limit(config.continuous_current_limit)?;
+        spark.set_secondary_current_limit(config.peak_current_limit, 0)?;
+
+        Ok(())
+    }
+
+    fn config_gains(&mut self, slot: i32, gains: EscGains) -> Result<()> {
+        let pid = self.spark.get_pid_controller();
+        pid.set_p(gains.kp, slot)?;
+        pid.set_i(gains.ki, slot)?;
+        pid.set_d(gains.kd, slot)?;
+        pid.set_ff(gains.kf, slot)?;
+        Ok(())
+    }
+
+    fn set_inverted(&mut self, inverted: bool) {
+        self.inverted = inverted;
+        self.spark.set_inverted(inverted);
+    }
+
+    /// The built-in encoder always follows the motor's inversion, so this does nothing
+    fn set_sensor_phase(&mut self, _phase: bool) {}
+
+    fn follow(&mut self, leader: &mut Self) -> Result<()> {
+        let invert = self.inverted != leader.inverted;
+        self.spark.follow(&leader.spark, invert)
+    }
+
+    fn output_percent(&mut self, value: f64) {
+        self.spark.set(value);
+    }
+
+    fn output_voltage(&mut self, voltage: ElectricPotential) {
+        self.set_reference(
+            voltage.get::<volt>(),
+            ControlType::kVoltage,
+            ElectricPotential::default(),
+        );
+    }
+
+    fn output_velocity(&mut self, value: f64) {
+        self.set_reference(value, ControlType::kVelocity, ElectricPotential::default());
+    }
+
+    fn output_velocity_with_feedforward(&mut self, value: f64, feedforward: ElectricPotential) {
+        self.set_reference(value, ControlType::kVelocity, feedforward);
+    }
+
+    fn output_position(&mut self, value: f64) {
+        self.set_reference(value, ControlType::kPosition, ElectricPotential::default());
+    }
+
+    fn raw_sensor_position(&mut self) -> f64 {
+        self.spark.get_encoder().get_position()
+    }
+
+    fn raw_sensor_velocity(&mut self) -> f64 {
+        self.spark.get_encoder().get_velocity()
+    }
+
+    fn set_raw_sensor_position(&mut self, value: f64) -> Result<()> {
+        self.spark.get_encoder().set_position(value)
+    }
+
+    fn counts_per_revolution(&self) -> f64 {
+        1.0
+    }
+
+    /// SparkMaxes report velocity in RPM
+    fn velocity_period(&self) -> Time {
+        Time::new::<minute>(1.0)
+    }
+
+    fn bus_voltage(&mut self) -> ElectricPotential {
+        self.spark.get_bus_voltage()
+    }
+
+    fn applied_voltage(&mut self) -> ElectricPotential {
+        self.spark.get_bus_voltage() * self.spark.get_applied_output()
+    }
+
+    fn output_current(&mut self) -> ElectricCurrent {
+        self.spark.get_output_current()
+    }
 
-    pub fn config_talon_fx(&mut self, f: impl FnOnce(&mut TalonFX)) {
-        f(self.as_talon_fx());
+    fn temperature(&mut self) -> ThermodynamicTemperature {
+        self.spark.get_motor_temperature()
     }
 }
 
 #[derive(Clone, Copy)]
-pub struct OffloadedEscConfig {
+pub struct EscConfig {
     open_loop_ramp: Time,
     closed_loop_ramp: Time,
     peak_output_forward: ElectricPotential,
@@ -114,9 +405,9 @@ pub struct OffloadedEscConfig {
     peak_current_duration: Time,
 }
 
-impl Default for OffloadedEscConfig {
+impl Default for EscConfig {
     fn default() -> Self {
-        OffloadedEscConfig {
+        EscConfig {
             open_loop_ramp: Time::new::<second>(0.0),
             closed_loop_ramp: Time::new::<second>(0.0),
             peak_output_forward: ElectricPotential::new::<volt>(12.0),
@@ -0,0 +1,81 @@
+use frc::wpilib::driver_station;
+use frc::wpilib::xbox_controller::{Axis, Button};
+use frc::wpilib::{GenericHID, XboxController};
+use tetanus_commands::trigger::Trigger;
+
+/// Triggers for the buttons, axes and POV hats of any [`GenericHID`]
+///
+/// Triggers read from the driver station by port, so they don't borrow the controller.
+pub trait HidTriggers: GenericHID {
+    fn button(&self, button: i32) -> Trigger {
+        let port = self.get_port();
+        Trigger::new(move || driver_station::get_stick_button(port, button))
+    }
+
+    fn axis_above(&self, axis: i32, threshold: f64) -> Trigger {
+        let port = self.get_port();
+        Trigger::new(move || driver_station::get_stick_axis(port, axis) > threshold)
+    }
+
+    fn axis_below(&self, axis: i32, threshold: f64) -> Trigger {
+        let port = self.get_port();
+        Trigger::new(move || driver_station::get_stick_axis(port, axis) < threshold)
+    }
+
+    /// Active while the first POV hat is pressed in `angle` degrees (0 is up, clockwise)
+    fn pov(&self, angle: i32) -> Trigger {
+        let port = self.get_port();
+        Trigger::new(move || driver_station::get_stick_pov(port, 0) == angle)
+    }
+}
+
+impl<T: GenericHID> HidTriggers for T {}
+
+/// Named triggers for an [`XboxController`]
+pub trait XboxTriggers: HidTriggers {
+    fn xbox_button(&self, button: Button) -> Trigger {
+        self.button(button as i32)
+    }
+
+    fn a(&self) -> Trigger {
+        self.xbox_button(Button::kA)
+    }
+
+    fn b(&self) -> Trigger {
+        self.xbox_button(Button::kB)
+    }
+
+    fn x(&self) -> Trigger {
+        self.xbox_button(Button::kX)
+    }
+
+    fn y(&self) -> Trigger {
+        self.xbox_button(Button::kY)
+    }
+
+    fn left_bumper(&self) -> Trigger {
+        self.xbox_button(Button::kBumperLeft)
+    }
+
+    fn right_bumper(&self) -> Trigger {
+        self.xbox_button(Button::kBumperRight)
+    }
+
+    fn back(&self) -> Trigger {
+        self.xbox_button(Button::kBack)
+    }
+
+    fn start(&self) -> Trigger {
+        self.xbox_button(Button::kStart)
+    }
+
+    fn left_trigger(&self, threshold: f64) -> Trigger {
+        self.axis_above(Axis::kLeftTrigger as i32, threshold)
+    }
+
+    fn right_trigger(&self, threshold: f64) -> Trigger {
+        self.axis_above(Axis::kRightTrigger as i32, threshold)
+    }
+}
+
+impl XboxTriggers for XboxController {}
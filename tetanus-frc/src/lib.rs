@@ -1 +1,6 @@
+pub mod characterization;
+pub mod clock;
 pub mod esc;
+pub mod hid;
+pub mod mechanism;
+pub mod swerve;
\ No newline at end of file
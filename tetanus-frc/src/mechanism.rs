@@ -0,0 +1,106 @@
+//! Motor controllers commanded and read in the units of what they drive
+
+use anyhow::Result;
+use tetanus_control::sensor::SensorConversion;
+use tetanus_control::units::Motion;
+use uom::si::f64::*;
+
+use crate::esc::Esc;
+
+/// An [`Esc`] along with the gearing between its sensor and a mechanism, so setpoints and
+/// readings are in meters or radians rather than native sensor units
+///
+/// `P` is [`Length`] for wheels, spools and belts, or [`Angle`] for arms and turrets, with
+/// velocities in the matching [`Velocity`] or [`AngularVelocity`].
+pub struct Mechanism<E, P = Length> {
+    esc: E,
+    conversion: SensorConversion<P>,
+}
+
+impl<E: Esc> Mechanism<E, Length> {
+    /// Wheel, spool or pulley of `diameter`, turning once every `gear_ratio` motor revolutions
+    pub fn linear(esc: E, gear_ratio: f64, diameter: Length) -> Self {
+        let conversion = SensorConversion::linear(
+            esc.counts_per_revolution(),
+            esc.velocity_period(),
+            gear_ratio,
+            diameter,
+        );
+        Self::new(esc, conversion)
+    }
+}
+
+impl<E: Esc> Mechanism<E, Angle> {
+    /// Joint turning once every `gear_ratio` motor revolutions
+    pub fn angular(esc: E, gear_ratio: f64) -> Self {
+        let conversion = SensorConversion::angular(
+            esc.counts_per_revolution(),
+            esc.velocity_period(),
+            gear_ratio,
+        );
+        Self::new(esc, conversion)
+    }
+}
+
+impl<E: Esc, P: Motion> Mechanism<E, P> {
+    /// For sensors other than the controller's own, which count in different units
+    pub fn new(esc: E, conversion: SensorConversion<P>) -> Self {
+        Mechanism { esc, conversion }
+    }
+
+    /// For settings and telemetry that don't depend on the gearing
+    pub fn esc(&mut self) -> &mut E {
+        &mut self.esc
+    }
+
+    pub fn conversion(&self) -> SensorConversion<P> {
+        self.conversion
+    }
+
+    pub fn into_inner(self) -> E {
+        self.esc
+    }
+
+    pub fn output_percent(&mut self, value: f64) {
+        self.esc.output_percent(value);
+    }
+
+    pub fn output_voltage(&mut self, voltage: ElectricPotential) {
+        self.esc.output_voltage(voltage);
+    }
+
+    pub fn output_position(&mut self, position: P) {
+        self.esc
+            .output_position(self.conversion.to_counts(position));
+    }
+
+    pub fn output_velocity(&mut self, velocity: P::Velocity) {
+        self.esc
+            .output_velocity(self.conversion.to_native_velocity(velocity));
+    }
+
+    pub fn output_velocity_with_feedforward(
+        &mut self,
+        velocity: P::Velocity,
+        feedforward: ElectricPotential,
+    ) {
+        self.esc.output_velocity_with_feedforward(
+            self.conversion.to_native_velocity(velocity),
+            feedforward,
+        );
+    }
+
+    pub fn position(&mut self) -> P {
+        self.conversion.position(self.esc.raw_sensor_position())
+    }
+
+    pub fn velocity(&mut self) -> P::Velocity {
+        self.conversion.velocity(self.esc.raw_sensor_velocity())
+    }
+
+    /// Tells the sensor the mechanism is at `position`, such as zero at a hard stop
+    pub fn set_position(&mut self, position: P) -> Result<()> {
+        self.esc
+            .set_raw_sensor_position(self.conversion.to_counts(position))
+    }
+}
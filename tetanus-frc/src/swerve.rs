@@ -0,0 +1,192 @@
+use std::sync::{Arc, Mutex};
+
+use anyhow::Result;
+use frc::ctre::sensors::{AbsoluteSensorRange, CANCoder, SensorInitializationStrategy};
+use tetanus_control::geometry::{Pose2d, Rotation2d};
+use tetanus_control::kinematics::{SwerveDriveKinematics, SwerveModulePosition, SwerveModuleState};
+use tetanus_control::odometry::SwerveDriveOdometry;
+use tetanus_core::consumer::Consumer;
+use tetanus_core::producer::Producer;
+use tetanus_core::resource::Resource;
+use uom::si::angle::degree;
+use uom::si::f64::*;
+use uom::si::velocity::meter_per_second;
+
+use crate::esc::{Esc, EscConfig, EscGains, CONFIG_TIMEOUT_MS};
+use crate::mechanism::Mechanism;
+
+#[derive(Clone, Copy)]
+pub struct SwerveModuleConfig {
+    pub drive_id: i32,
+    pub steer_id: i32,
+    pub cancoder_id: i32,
+    /// Absolute encoder reading with the wheel pointing forward
+    pub forward_reading: Angle,
+    /// Drive motor revolutions per wheel revolution
+    pub drive_gear_ratio: f64,
+    /// Steer motor revolutions per module revolution
+    pub steer_gear_ratio: f64,
+    pub wheel_diameter: Length,
+    pub drive_gains: EscGains,
+    pub steer_gains: EscGains,
+    pub resource: Resource,
+}
+
+/// One swerve module: a motor controller driving the wheel, another steering it and a CANCoder
+/// reading its absolute direction
+pub struct SwerveModule<E> {
+    drive: Mechanism<E>,
+    steer: Mechanism<E, Angle>,
+    steer_encoder: CANCoder,
+    resource: Resource,
+}
+
+impl<E: Esc> SwerveModule<E> {
+    /// Below this the wheel is left pointing where it is, so it doesn't spin back to zero when
+    /// the robot stops
+    const MIN_STEER_SPEED_MPS: f64 = 0.01;
+
+    /// `esc` creates a motor controller with a given CAN ID, such as
+    /// [`TalonFxEsc::new`](crate::esc::TalonFxEsc::new)
+    pub fn new(config: SwerveModuleConfig, esc: impl Fn(i32) -> E) -> Result<Self> {
+        let mut drive = esc(config.drive_id);
+        drive.setup(EscConfig::default())?;
+        drive.config_gains(0, config.drive_gains)?;
+
+        let mut steer = esc(config.steer_id);
+        steer.setup(EscConfig::default())?;
+        steer.config_gains(0, config.steer_gains)?;
+
+        let mut steer_encoder = CANCoder::new(config.cancoder_id);
+        steer_encoder.config_absolute_sensor_range(
+            AbsoluteSensorRange::Signed_PlusMinus180,
+            CONFIG_TIMEOUT_MS,
+        )?;
+        steer_encoder.config_magnet_offset(-config.forward_reading, CONFIG_TIMEOUT_MS)?;
+        steer_encoder.config_sensor_initialization_strategy(
+            SensorInitializationStrategy::BootToAbsolutePosition,
+            CONFIG_TIMEOUT_MS,
+        )?;
+
+        let mut module = SwerveModule {
+            drive: Mechanism::linear(drive, config.drive_gear_ratio, config.wheel_diameter),
+            steer: Mechanism::angular(steer, config.steer_gear_ratio),
+            steer_encoder,
+            resource: config.resource,
+        };
+        module.seed_steer()?;
+        Ok(module)
+    }
+
+    /// Sets the steer motor's relative encoder from the absolute encoder, so closed-loop steering
+    /// starts from the module's real direction
+    pub fn seed_steer(&mut self) -> Result<()> {
+        let angle = self.absolute_angle().angle();
+        self.steer.set_position(angle)
+    }
+
+    /// Direction from the CANCoder, with forward as zero
+    pub fn absolute_angle(&mut self) -> Rotation2d {
+        Rotation2d::new(Angle::new::<degree>(
+            self.steer_encoder.get_absolute_position(),
+        ))
+    }
+
+    /// Direction from the steer motor's encoder, which updates faster than the CANCoder
+    pub fn steer_angle(&mut self) -> Rotation2d {
+        Rotation2d::new(self.steer.position())
+    }
+
+    pub fn position(&mut self) -> SwerveModulePosition {
+        SwerveModulePosition::new(self.drive.position(), self.absolute_angle())
+    }
+
+    pub fn state(&mut self) -> SwerveModuleState {
+        SwerveModuleState::new(self.drive.velocity(), self.steer_angle())
+    }
+
+    /// Drives and steers towards `state`, turning the shorter way and reversing the wheel if
+    /// needed
+    pub fn set_state(&mut self, state: SwerveModuleState) {
+        // Steering relative to the unwrapped encoder position, so the module never winds back
+        // through a full turn
+        let current_angle = self.steer.position();
+        let current = Rotation2d::new(current_angle);
+        let state = state.optimize(current);
+
+        if state.speed.abs().get::<meter_per_second>() >= Self::MIN_STEER_SPEED_MPS {
+            let delta = (state.angle - current).angle();
+            self.steer.output_position(current_angle + delta);
+        }
+        self.drive.output_velocity(state.speed);
+    }
+}
+
+impl<E: Esc> Consumer for SwerveModule<E> {
+    type Msg = SwerveModuleState;
+
+    fn output(&mut self, msg: Self::Msg) {
+        self.set_state(msg);
+    }
+
+    fn resources(&self) -> Vec<Resource> {
+        vec![self.resource]
+    }
+}
+
+/// Field pose of a swerve drive, from its modules and a heading source such as a gyro
+pub struct SwerveOdometry<E, H> {
+    modules: Vec<Arc<Mutex<SwerveModule<E>>>>,
+    heading: Arc<Mutex<H>>,
+    odometry: Mutex<SwerveDriveOdometry>,
+}
+
+impl<E: Esc, H: Producer<Msg = Angle>> SwerveOdometry<E, H> {
+    /// `modules` must be in the same order as in `kinematics`
+    pub fn new(
+        kinematics: SwerveDriveKinematics,
+        modules: Vec<Arc<Mutex<SwerveModule<E>>>>,
+        heading: Arc<Mutex<H>>,
+        initial: Pose2d,
+    ) -> Self {
+        let positions = read_positions(&modules);
+        let odometry = SwerveDriveOdometry::new(
+            kinematics,
+            Rotation2d::new(heading.lock().unwrap().next()),
+            &positions,
+            initial,
+        );
+
+        SwerveOdometry {
+            modules,
+            heading,
+            odometry: Mutex::new(odometry),
+        }
+    }
+
+    pub fn reset(&self, pose: Pose2d) {
+        let positions = read_positions(&self.modules);
+        let heading = Rotation2d::new(self.heading.lock().unwrap().next());
+        self.odometry
+            .lock()
+            .unwrap()
+            .reset(pose, heading, &positions);
+    }
+}
+
+impl<E: Esc, H: Producer<Msg = Angle>> Producer for SwerveOdometry<E, H> {
+    type Msg = Pose2d;
+
+    fn next(&self) -> Self::Msg {
+        let positions = read_positions(&self.modules);
+        let heading = Rotation2d::new(self.heading.lock().unwrap().next());
+        self.odometry.lock().unwrap().update(heading, &positions)
+    }
+}
+
+fn read_positions<E: Esc>(modules: &[Arc<Mutex<SwerveModule<E>>>]) -> Vec<SwerveModulePosition> {
+    modules
+        .iter()
+        .map(|module| module.lock().unwrap().position())
+        .collect()
+}